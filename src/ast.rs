@@ -29,181 +29,551 @@ pub enum Expr<'a> {
         name: Token<'a>,
         args: Vec<Expr<'a>>,
     },
+
+    Piecewise {
+        branches: Vec<Branch<'a>>,
+    },
+
+    // every row has the same length, a vector is a single row
+    Matrix {
+        rows: Vec<Vec<Expr<'a>>>,
+    },
+}
+
+// `value if guard`, or `value otherwise` when there's no guard
+#[derive(Debug, PartialEq, Clone)]
+pub struct Branch<'a> {
+    pub value: Expr<'a>,
+    pub guard: Option<Expr<'a>>,
 }
 
 impl Expr<'_> {
     fn is_binary(&self) -> bool {
-        match self {
-            Expr::Binary { .. } => true,
-            _ => false,
+        matches!(self, Expr::Binary { .. })
+    }
+}
+
+// read-only traversal. every method defaults to visiting the children, so an
+// impl only overrides the variants it cares about.
+pub trait Visitor<'a> {
+    fn visit_expr(&mut self, expr: &Expr<'a>) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_literal(&mut self, _literal: &Token<'a>) {}
+
+    fn visit_variable(&mut self, _name: &Token<'a>) {}
+
+    fn visit_special_variable(&mut self, _name: &Token<'a>) {}
+
+    fn visit_unary(&mut self, _op: &Token<'a>, rhs: &Expr<'a>) {
+        self.visit_expr(rhs);
+    }
+
+    fn visit_binary(&mut self, lhs: &Expr<'a>, _op: &Token<'a>, rhs: &Expr<'a>) {
+        self.visit_expr(lhs);
+        self.visit_expr(rhs);
+    }
+
+    fn visit_call(&mut self, _name: &Token<'a>, args: &[Expr<'a>]) {
+        for arg in args.iter() {
+            self.visit_expr(arg);
+        }
+    }
+
+    fn visit_piecewise(&mut self, branches: &[Branch<'a>]) {
+        for branch in branches.iter() {
+            self.visit_expr(&branch.value);
+            if let Some(guard) = &branch.guard {
+                self.visit_expr(guard);
+            }
+        }
+    }
+
+    fn visit_matrix(&mut self, rows: &[Vec<Expr<'a>>]) {
+        for entry in rows.iter().flatten() {
+            self.visit_expr(entry);
         }
     }
 }
 
-pub fn print_infix(expr: &Expr<'_>) {
+pub fn walk_expr<'a, V: Visitor<'a> + ?Sized>(visitor: &mut V, expr: &Expr<'a>) {
     match expr {
-        Expr::Literal { literal } => print!("{}", literal.lexeme),
-        Expr::Variable { name } => print!("{}", name.lexeme),
-        Expr::SpecialVariable { name } => print!("{}", name.lexeme),
-        Expr::Unary { op, rhs } => {
-            print!(" {}", op.lexeme);
-
-            if rhs.is_binary() {
-                print!("(");
-            }
-            print_infix(rhs.as_ref());
-            if rhs.is_binary() {
-                print!(")");
-            }
+        Expr::Literal { literal } => visitor.visit_literal(literal),
+        Expr::Variable { name } => visitor.visit_variable(name),
+        Expr::SpecialVariable { name } => visitor.visit_special_variable(name),
+        Expr::Unary { op, rhs } => visitor.visit_unary(op, rhs),
+        Expr::Binary { lhs, op, rhs } => visitor.visit_binary(lhs, op, rhs),
+        Expr::Call { name, args } => visitor.visit_call(name, args),
+        Expr::Piecewise { branches } => visitor.visit_piecewise(branches),
+        Expr::Matrix { rows } => visitor.visit_matrix(rows),
+    }
+}
+
+// rewriting traversal. takes the tree by value and builds a new one, by
+// default rebuilding every node unchanged from its folded children.
+pub trait Fold<'a> {
+    fn fold_expr(&mut self, expr: Expr<'a>) -> Expr<'a> {
+        fold_expr(self, expr)
+    }
+
+    fn fold_literal(&mut self, literal: Token<'a>) -> Expr<'a> {
+        Expr::Literal { literal }
+    }
+
+    fn fold_variable(&mut self, name: Token<'a>) -> Expr<'a> {
+        Expr::Variable { name }
+    }
+
+    fn fold_special_variable(&mut self, name: Token<'a>) -> Expr<'a> {
+        Expr::SpecialVariable { name }
+    }
+
+    fn fold_unary(&mut self, op: Token<'a>, rhs: Expr<'a>) -> Expr<'a> {
+        Expr::Unary {
+            op,
+            rhs: Box::new(self.fold_expr(rhs)),
         }
-        Expr::Binary { lhs, op, rhs } => {
-            if lhs.is_binary() {
-                print!("(");
-            }
-            print_infix(lhs);
-            if lhs.is_binary() {
-                print!(")");
-            }
+    }
+
+    fn fold_binary(&mut self, lhs: Expr<'a>, op: Token<'a>, rhs: Expr<'a>) -> Expr<'a> {
+        Expr::Binary {
+            lhs: Box::new(self.fold_expr(lhs)),
+            op,
+            rhs: Box::new(self.fold_expr(rhs)),
+        }
+    }
 
-            print!(" {} ", op.lexeme);
+    fn fold_call(&mut self, name: Token<'a>, args: Vec<Expr<'a>>) -> Expr<'a> {
+        Expr::Call {
+            name,
+            args: args.into_iter().map(|arg| self.fold_expr(arg)).collect(),
+        }
+    }
 
-            if rhs.is_binary() {
-                print!("(");
-            }
-            print_infix(rhs);
-            if rhs.is_binary() {
-                print!(")");
-            }
+    fn fold_piecewise(&mut self, branches: Vec<Branch<'a>>) -> Expr<'a> {
+        Expr::Piecewise {
+            branches: branches
+                .into_iter()
+                .map(|branch| Branch {
+                    value: self.fold_expr(branch.value),
+                    guard: branch.guard.map(|guard| self.fold_expr(guard)),
+                })
+                .collect(),
         }
-        Expr::Call { name, args } => {
-            print!("{}(", name.lexeme);
-            for expr in args.iter() {
-                print_infix(expr);
-                print!(",");
-            }
-            print!(")");
+    }
+
+    fn fold_matrix(&mut self, rows: Vec<Vec<Expr<'a>>>) -> Expr<'a> {
+        Expr::Matrix {
+            rows: rows
+                .into_iter()
+                .map(|row| row.into_iter().map(|entry| self.fold_expr(entry)).collect())
+                .collect(),
         }
     }
 }
 
-pub fn print_prefix(expr: &Expr<'_>) {
+pub fn fold_expr<'a, F: Fold<'a> + ?Sized>(folder: &mut F, expr: Expr<'a>) -> Expr<'a> {
     match expr {
-        Expr::Literal { literal } => print!("{} ", literal.lexeme),
-        Expr::Variable { name } => print!("{} ", name.lexeme),
-        Expr::SpecialVariable { name } => print!("{} ", name.lexeme),
-        Expr::Unary { op, rhs } => {
-            print!("{}", op.lexeme);
-            print_prefix(rhs.as_ref());
-        }
-        Expr::Binary { lhs, op, rhs } => {
-            print!("{} ", op.lexeme);
-
-            if lhs.is_binary() {
-                print!("(");
-            }
-            print_prefix(lhs);
-            if lhs.is_binary() {
-                print!(") ");
-            }
+        Expr::Literal { literal } => folder.fold_literal(literal),
+        Expr::Variable { name } => folder.fold_variable(name),
+        Expr::SpecialVariable { name } => folder.fold_special_variable(name),
+        Expr::Unary { op, rhs } => folder.fold_unary(op, *rhs),
+        Expr::Binary { lhs, op, rhs } => folder.fold_binary(*lhs, op, *rhs),
+        Expr::Call { name, args } => folder.fold_call(name, args),
+        Expr::Piecewise { branches } => folder.fold_piecewise(branches),
+        Expr::Matrix { rows } => folder.fold_matrix(rows),
+    }
+}
 
-            if rhs.is_binary() {
-                print!("(");
-            }
-            print_prefix(rhs);
-            if rhs.is_binary() {
-                print!(")");
-            }
+struct FreeVariables<'a>(Vec<&'a str>);
+
+impl<'a> Visitor<'a> for FreeVariables<'a> {
+    fn visit_variable(&mut self, name: &Token<'a>) {
+        if !self.0.contains(&name.lexeme) {
+            self.0.push(name.lexeme);
         }
-        Expr::Call { name, args } => {
-            print!("{}(", name.lexeme);
-            for expr in args.iter() {
-                print_prefix(expr);
-                print!(", ");
-            }
-            print!(") ");
+    }
+}
+
+// names of the variables in an expression, in order of first appearance
+pub fn free_variables<'a>(expr: &Expr<'a>) -> Vec<&'a str> {
+    let mut free = FreeVariables(Vec::new());
+    free.visit_expr(expr);
+    free.0
+}
+
+// word operators like `not` need a space before their operand
+fn push_op(out: &mut String, op: &Token<'_>) {
+    out.push_str(op.lexeme);
+    if op.lexeme.chars().all(char::is_alphabetic) {
+        out.push(' ');
+    }
+}
+
+// [a, b; c, d] with each entry written by `print`
+fn push_matrix(out: &mut String, rows: &[Vec<Expr<'_>>], print: fn(&Expr<'_>) -> String) {
+    let rows: Vec<String> = rows
+        .iter()
+        .map(|row| {
+            let entries: Vec<String> = row
+                .iter()
+                .map(|entry| print(entry).trim().to_string())
+                .collect();
+            entries.join(", ")
+        })
+        .collect();
+    out.push('[');
+    out.push_str(&rows.join("; "));
+    out.push(']');
+}
+
+struct Infix(String);
+
+impl Infix {
+    fn visit_grouped(&mut self, expr: &Expr<'_>) {
+        if expr.is_binary() {
+            self.0.push('(');
+        }
+        self.visit_expr(expr);
+        if expr.is_binary() {
+            self.0.push(')');
         }
     }
 }
 
-pub fn print_postfix(expr: &Expr<'_>) {
-    match expr {
-        Expr::Literal { literal } => print!("{} ", literal.lexeme),
-        Expr::Variable { name } => print!("{} ", name.lexeme),
-        Expr::SpecialVariable { name } => print!("{} ", name.lexeme),
-        Expr::Unary { op, rhs } => {
-            print!("{}", op.lexeme);
-
-            if rhs.is_binary() {
-                print!("(");
+impl<'a> Visitor<'a> for Infix {
+    fn visit_literal(&mut self, literal: &Token<'a>) {
+        self.0.push_str(&literal.to_string());
+    }
+
+    fn visit_variable(&mut self, name: &Token<'a>) {
+        self.0.push_str(name.lexeme);
+    }
+
+    fn visit_special_variable(&mut self, name: &Token<'a>) {
+        self.0.push_str(name.lexeme);
+    }
+
+    fn visit_unary(&mut self, op: &Token<'a>, rhs: &Expr<'a>) {
+        self.0.push(' ');
+        push_op(&mut self.0, op);
+        self.visit_grouped(rhs);
+    }
+
+    fn visit_binary(&mut self, lhs: &Expr<'a>, op: &Token<'a>, rhs: &Expr<'a>) {
+        self.visit_grouped(lhs);
+        self.0.push(' ');
+        self.0.push_str(op.lexeme);
+        self.0.push(' ');
+        self.visit_grouped(rhs);
+    }
+
+    fn visit_call(&mut self, name: &Token<'a>, args: &[Expr<'a>]) {
+        self.0.push_str(name.lexeme);
+        self.0.push('(');
+        for expr in args.iter() {
+            self.visit_expr(expr);
+            self.0.push(',');
+        }
+        self.0.push(')');
+    }
+
+    fn visit_piecewise(&mut self, branches: &[Branch<'a>]) {
+        self.0.push('{');
+        for (i, branch) in branches.iter().enumerate() {
+            if i > 0 {
+                self.0.push_str("; ");
             }
-            print_postfix(rhs.as_ref());
-            if rhs.is_binary() {
-                print!(")");
+            self.visit_grouped(&branch.value);
+            match &branch.guard {
+                Some(guard) => {
+                    self.0.push_str(" if ");
+                    self.visit_grouped(guard);
+                }
+                None => self.0.push_str(" otherwise"),
             }
         }
-        Expr::Binary { lhs, op, rhs } => {
-            if lhs.is_binary() {
-                print!("(");
-            }
-            print_postfix(lhs);
-            if lhs.is_binary() {
-                print!(") ");
+        self.0.push('}');
+    }
+
+    fn visit_matrix(&mut self, rows: &[Vec<Expr<'a>>]) {
+        push_matrix(&mut self.0, rows, to_infix);
+    }
+}
+
+struct Prefix(String);
+
+impl<'a> Visitor<'a> for Prefix {
+    fn visit_literal(&mut self, literal: &Token<'a>) {
+        self.0.push_str(&literal.to_string());
+        self.0.push(' ');
+    }
+
+    fn visit_variable(&mut self, name: &Token<'a>) {
+        self.0.push_str(name.lexeme);
+        self.0.push(' ');
+    }
+
+    fn visit_special_variable(&mut self, name: &Token<'a>) {
+        self.0.push_str(name.lexeme);
+        self.0.push(' ');
+    }
+
+    fn visit_unary(&mut self, op: &Token<'a>, rhs: &Expr<'a>) {
+        push_op(&mut self.0, op);
+        self.visit_expr(rhs);
+    }
+
+    fn visit_binary(&mut self, lhs: &Expr<'a>, op: &Token<'a>, rhs: &Expr<'a>) {
+        self.0.push_str(op.lexeme);
+        self.0.push(' ');
+
+        if lhs.is_binary() {
+            self.0.push('(');
+        }
+        self.visit_expr(lhs);
+        if lhs.is_binary() {
+            self.0.push_str(") ");
+        }
+
+        if rhs.is_binary() {
+            self.0.push('(');
+        }
+        self.visit_expr(rhs);
+        if rhs.is_binary() {
+            self.0.push(')');
+        }
+    }
+
+    fn visit_call(&mut self, name: &Token<'a>, args: &[Expr<'a>]) {
+        self.0.push_str(name.lexeme);
+        self.0.push('(');
+        for expr in args.iter() {
+            self.visit_expr(expr);
+            self.0.push_str(", ");
+        }
+        self.0.push_str(") ");
+    }
+
+    fn visit_piecewise(&mut self, branches: &[Branch<'a>]) {
+        self.0.push('{');
+        for branch in branches.iter() {
+            match &branch.guard {
+                Some(guard) => {
+                    self.0.push_str("if ");
+                    self.visit_expr(guard);
+                }
+                None => self.0.push_str("otherwise "),
             }
+            self.visit_expr(&branch.value);
+            self.0.push_str("; ");
+        }
+        self.0.push_str("} ");
+    }
+
+    fn visit_matrix(&mut self, rows: &[Vec<Expr<'a>>]) {
+        push_matrix(&mut self.0, rows, to_prefix);
+        self.0.push(' ');
+    }
+}
+
+struct Postfix(String);
+
+impl<'a> Visitor<'a> for Postfix {
+    fn visit_literal(&mut self, literal: &Token<'a>) {
+        self.0.push_str(&literal.to_string());
+        self.0.push(' ');
+    }
+
+    fn visit_variable(&mut self, name: &Token<'a>) {
+        self.0.push_str(name.lexeme);
+        self.0.push(' ');
+    }
+
+    fn visit_special_variable(&mut self, name: &Token<'a>) {
+        self.0.push_str(name.lexeme);
+        self.0.push(' ');
+    }
 
-            if rhs.is_binary() {
-                print!("(");
+    fn visit_unary(&mut self, op: &Token<'a>, rhs: &Expr<'a>) {
+        push_op(&mut self.0, op);
+
+        if rhs.is_binary() {
+            self.0.push('(');
+        }
+        self.visit_expr(rhs);
+        if rhs.is_binary() {
+            self.0.push(')');
+        }
+    }
+
+    fn visit_binary(&mut self, lhs: &Expr<'a>, op: &Token<'a>, rhs: &Expr<'a>) {
+        for side in [lhs, rhs].iter() {
+            if side.is_binary() {
+                self.0.push('(');
             }
-            print_postfix(rhs);
-            if rhs.is_binary() {
-                print!(") ");
+            self.visit_expr(side);
+            if side.is_binary() {
+                self.0.push_str(") ");
             }
+        }
+
+        self.0.push_str(op.lexeme);
+        self.0.push(' ');
+    }
 
-            print!("{} ", op.lexeme);
+    fn visit_call(&mut self, name: &Token<'a>, args: &[Expr<'a>]) {
+        self.0.push_str(name.lexeme);
+        self.0.push('(');
+        for expr in args.iter() {
+            self.visit_expr(expr);
+            self.0.push_str(", ");
         }
-        Expr::Call { name, args } => {
-            print!("{}(", name.lexeme);
-            for expr in args.iter() {
-                print_postfix(expr);
-                print!(", ");
+        self.0.push_str(") ");
+    }
+
+    fn visit_piecewise(&mut self, branches: &[Branch<'a>]) {
+        self.0.push('{');
+        for branch in branches.iter() {
+            self.visit_expr(&branch.value);
+            match &branch.guard {
+                Some(guard) => {
+                    self.visit_expr(guard);
+                    self.0.push_str("if");
+                }
+                None => self.0.push_str("otherwise"),
             }
-            print!(") ");
+            self.0.push_str("; ");
         }
+        self.0.push_str("} ");
+    }
+
+    fn visit_matrix(&mut self, rows: &[Vec<Expr<'a>>]) {
+        push_matrix(&mut self.0, rows, to_postfix);
+        self.0.push(' ');
     }
 }
 
-#[allow(dead_code)]
-pub fn print_expr(expr: &Expr<'_>) {
-    print_expr_(expr, 0);
+struct Tree {
+    out: String,
+    level: usize,
 }
 
-fn print_space(level: usize) {
-    let mut x = String::with_capacity(level);
-    for _ in 0..level * 3 {
-        x.push(' ');
+impl Tree {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.level * 3 {
+            self.out.push(' ');
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn nested(&mut self, exprs: &[&Expr<'_>]) {
+        self.level += 1;
+        for expr in exprs.iter() {
+            self.visit_expr(expr);
+        }
+        self.level -= 1;
     }
-    print!("{}", x);
 }
 
-fn print_expr_(expr: &Expr<'_>, level: usize) {
-    print_space(level);
-    match expr {
-        Expr::Literal { literal } => println!("{}", literal.lexeme),
-        Expr::Variable { name } => println!("{}", name.lexeme),
-        Expr::SpecialVariable { name } => println!("{}", name.lexeme),
-        Expr::Unary { op, rhs } => {
-            println!("unary {}", op.lexeme);
-            print_expr_(rhs.as_ref(), level + 1);
-        }
-        Expr::Binary { lhs, op, rhs } => {
-            println!("binary {}", op.lexeme);
-            print_expr_(lhs, level + 1);
-            print_expr_(rhs, level + 1);
-        }
-        Expr::Call { name, args } => {
-            println!("builtin {}", name.lexeme);
-            for expr in args.iter() {
-                print_expr_(expr, level + 1);
+impl<'a> Visitor<'a> for Tree {
+    fn visit_literal(&mut self, literal: &Token<'a>) {
+        self.line(&literal.to_string());
+    }
+
+    fn visit_variable(&mut self, name: &Token<'a>) {
+        self.line(name.lexeme);
+    }
+
+    fn visit_special_variable(&mut self, name: &Token<'a>) {
+        self.line(name.lexeme);
+    }
+
+    fn visit_unary(&mut self, op: &Token<'a>, rhs: &Expr<'a>) {
+        self.line(&format!("unary {}", op.lexeme));
+        self.nested(&[rhs]);
+    }
+
+    fn visit_binary(&mut self, lhs: &Expr<'a>, op: &Token<'a>, rhs: &Expr<'a>) {
+        self.line(&format!("binary {}", op.lexeme));
+        self.nested(&[lhs, rhs]);
+    }
+
+    fn visit_call(&mut self, name: &Token<'a>, args: &[Expr<'a>]) {
+        self.line(&format!("builtin {}", name.lexeme));
+        self.nested(&args.iter().collect::<Vec<_>>());
+    }
+
+    fn visit_piecewise(&mut self, branches: &[Branch<'a>]) {
+        self.line("piecewise");
+        self.level += 1;
+        for branch in branches.iter() {
+            match &branch.guard {
+                Some(guard) => {
+                    self.line("if");
+                    self.nested(&[guard, &branch.value]);
+                }
+                None => {
+                    self.line("otherwise");
+                    self.nested(&[&branch.value]);
+                }
             }
         }
+        self.level -= 1;
     }
+
+    fn visit_matrix(&mut self, rows: &[Vec<Expr<'a>>]) {
+        self.line(&format!("matrix {}x{}", rows.len(), rows[0].len()));
+        self.level += 1;
+        for row in rows.iter() {
+            self.line("row");
+            self.nested(&row.iter().collect::<Vec<_>>());
+        }
+        self.level -= 1;
+    }
+}
+
+pub fn to_infix(expr: &Expr<'_>) -> String {
+    let mut infix = Infix(String::new());
+    infix.visit_expr(expr);
+    infix.0
+}
+
+pub fn to_prefix(expr: &Expr<'_>) -> String {
+    let mut prefix = Prefix(String::new());
+    prefix.visit_expr(expr);
+    prefix.0
+}
+
+pub fn to_postfix(expr: &Expr<'_>) -> String {
+    let mut postfix = Postfix(String::new());
+    postfix.visit_expr(expr);
+    postfix.0
+}
+
+pub fn to_tree(expr: &Expr<'_>) -> String {
+    let mut tree = Tree {
+        out: String::new(),
+        level: 0,
+    };
+    tree.visit_expr(expr);
+    tree.out
+}
+
+pub fn print_infix(expr: &Expr<'_>) {
+    print!("{}", to_infix(expr));
+}
+
+pub fn print_prefix(expr: &Expr<'_>) {
+    print!("{}", to_prefix(expr));
+}
+
+pub fn print_postfix(expr: &Expr<'_>) {
+    print!("{}", to_postfix(expr));
+}
+
+pub fn print_expr(expr: &Expr<'_>) {
+    print!("{}", to_tree(expr));
 }
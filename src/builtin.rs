@@ -0,0 +1,184 @@
+use super::ast::*;
+use super::eval;
+use super::integral;
+use super::limit::{self, Point, Side};
+use super::quadrature;
+use super::scan::*;
+use super::series::{self, tidy};
+use super::solve;
+
+// replaces calls to symbolic builtins like expand(...) with their result,
+// innermost first. numeric builtins like sin are left alone.
+pub fn apply(expr: Expr<'_>) -> Result<Expr<'_>, &'static str> {
+    let mut builtins = Builtins { error: None };
+    let expr = builtins.fold_expr(expr);
+    match builtins.error {
+        Some(err) => Err(err),
+        None => Ok(expr),
+    }
+}
+
+struct Builtins {
+    error: Option<&'static str>,
+}
+
+impl<'a> Fold<'a> for Builtins {
+    fn fold_call(&mut self, name: Token<'a>, args: Vec<Expr<'a>>) -> Expr<'a> {
+        let args: Vec<Expr<'a>> = args.into_iter().map(|arg| self.fold_expr(arg)).collect();
+
+        let result = match name.lexeme {
+            "expand" => expand(&args),
+            "factor" => factor(&args),
+            "diff" => diff(&args),
+            "limit" => limit(&args),
+            "grad" => grad(&args),
+            "jacobian" => jacobian(&args),
+            "hessian" => hessian(&args),
+            "series" => series::call(&args).map(|series| series.polynomial),
+            // with limits it's a number, see quadrature
+            "integrate" | "int" | "∫" if args.len() <= 2 => integrate(&args),
+            _ => return Expr::Call { name, args },
+        };
+
+        match result {
+            Ok(expr) => expr,
+            Err(err) => {
+                self.error.get_or_insert(err);
+                Expr::Call { name, args }
+            }
+        }
+    }
+}
+
+fn expand<'a>(args: &[Expr<'a>]) -> Result<Expr<'a>, &'static str> {
+    match args {
+        [expr] => expr.expand(),
+        _ => Err("expand takes one argument"),
+    }
+}
+
+fn factor<'a>(args: &[Expr<'a>]) -> Result<Expr<'a>, &'static str> {
+    match args {
+        [expr] => expr.factor(),
+        _ => Err("factor takes one argument"),
+    }
+}
+
+// diff(expr, x), or diff(expr) to pick the variable like solve does
+fn diff<'a>(args: &[Expr<'a>]) -> Result<Expr<'a>, &'static str> {
+    match args {
+        [expr] => expr.derivative(solve::default_variable(expr).unwrap_or("x")),
+        [expr, Expr::Variable { name } | Expr::SpecialVariable { name }] => {
+            expr.derivative(name.lexeme)
+        }
+        _ => Err("diff takes an expression and a variable"),
+    }
+}
+
+// integrate(expr, x), int(expr dx), or integrate(expr) to pick the variable
+fn integrate<'a>(args: &[Expr<'a>]) -> Result<Expr<'a>, &'static str> {
+    match args {
+        [expr, var @ (Expr::Variable { .. } | Expr::SpecialVariable { .. })] => {
+            integral::integrate(expr, var)
+        }
+        [expr] => match quadrature::differential(expr) {
+            Some((integrand, var)) => {
+                integral::integrate(&integrand, &integral::variable(expr, var))
+            }
+            None => {
+                let var = solve::default_variable(expr).unwrap_or("x");
+                integral::integrate(expr, &integral::variable(expr, var))
+            }
+        },
+        _ => Err("integrate takes an expression and a variable"),
+    }
+}
+
+// limit(expr, x, a), with `left` or `right` after for one side. a can be ∞.
+fn limit<'a>(args: &[Expr<'a>]) -> Result<Expr<'a>, &'static str> {
+    let (expr, var, a, side) = match args {
+        [expr, Expr::Variable { name } | Expr::SpecialVariable { name }, a] => {
+            (expr, name.lexeme, a, Side::Both)
+        }
+        [expr, Expr::Variable { name } | Expr::SpecialVariable { name }, a, Expr::Variable { name: side }] =>
+        {
+            let side = match side.lexeme {
+                "left" => Side::Left,
+                "right" => Side::Right,
+                _ => return Err("a limit is from the left or the right"),
+            };
+            (expr, name.lexeme, a, side)
+        }
+        _ => return Err("limit takes an expression, a variable and a point"),
+    };
+
+    let point = match eval::eval_number(a, &eval::Env::new())? {
+        v if v == f64::INFINITY => Point::Infinity,
+        v if v == f64::NEG_INFINITY => Point::NegativeInfinity,
+        v => Point::Finite(v),
+    };
+    limit::limit(expr, var, point, a.clone(), side).map(|limit| limit.to_expr())
+}
+
+// the entries of [x, y, ...], which all have to be variables
+fn variables<'a>(vars: &Expr<'a>) -> Result<Vec<&'a str>, &'static str> {
+    let err = "expected a vector of variables like [x, y]";
+    match vars {
+        Expr::Matrix { rows } => rows
+            .iter()
+            .flatten()
+            .map(|var| match var {
+                Expr::Variable { name } | Expr::SpecialVariable { name } => Ok(name.lexeme),
+                _ => Err(err),
+            })
+            .collect(),
+        _ => Err(err),
+    }
+}
+
+fn partials<'a>(f: &Expr<'a>, vars: &[&str]) -> Result<Vec<Expr<'a>>, &'static str> {
+    vars.iter().map(|var| f.derivative(var).map(tidy)).collect()
+}
+
+// grad(f, [x, y, z]) as a row vector
+fn grad<'a>(args: &[Expr<'a>]) -> Result<Expr<'a>, &'static str> {
+    match args {
+        [f, vars] => Ok(Expr::Matrix {
+            rows: vec![partials(f, &variables(vars)?)?],
+        }),
+        _ => Err("grad takes a function and a vector of variables"),
+    }
+}
+
+// jacobian([f, g], [x, y]), a row for each function
+fn jacobian<'a>(args: &[Expr<'a>]) -> Result<Expr<'a>, &'static str> {
+    match args {
+        [Expr::Matrix { rows }, vars] => {
+            let vars = variables(vars)?;
+            Ok(Expr::Matrix {
+                rows: rows
+                    .iter()
+                    .flatten()
+                    .map(|f| partials(f, &vars))
+                    .collect::<Result<_, _>>()?,
+            })
+        }
+        _ => Err("jacobian takes a vector of functions and a vector of variables"),
+    }
+}
+
+// hessian(f, [x, y]), the jacobian of the gradient
+fn hessian<'a>(args: &[Expr<'a>]) -> Result<Expr<'a>, &'static str> {
+    match args {
+        [f, vars] => {
+            let vars = variables(vars)?;
+            Ok(Expr::Matrix {
+                rows: partials(f, &vars)?
+                    .iter()
+                    .map(|fx| partials(fx, &vars))
+                    .collect::<Result<_, _>>()?,
+            })
+        }
+        _ => Err("hessian takes a function and a vector of variables"),
+    }
+}
@@ -0,0 +1,85 @@
+use super::command::{self, View};
+
+pub const USAGE: &str = "usage: nums [options] [file]
+
+with no file or expressions, starts the repl. otherwise the file, a script
+like a.nums, or stdin for - is run without a prompt, stopping at the first
+error. # starts a comment.
+
+  -e, --eval EXPR      run EXPR and exit, can be given more than once
+  -o, --format VIEWS   what to show, like infix,latex,value or all, or json
+                       for one json object per statement. with sexpr,
+                       lines can be s-expressions too
+      --data-dir DIR   where the repl keeps history and the session, and
+                       where extra rewrite rules are read from rules.txt
+  -h, --help           show this";
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Input {
+    Repl,
+    // lines from -e
+    Eval(Vec<String>),
+    // a file, or stdin if there isn't one
+    Lines(Option<String>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Options {
+    pub input: Input,
+    pub views: Option<Vec<View>>,
+    pub json: bool,
+    pub data_dir: Option<String>,
+    pub help: bool,
+}
+
+// the arguments after the program name
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Options, &'static str> {
+    let mut options = Options {
+        input: Input::Repl,
+        views: None,
+        json: false,
+        data_dir: None,
+        help: false,
+    };
+    let mut eval = Vec::new();
+    let mut file = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        // --format=latex is the same as --format latex
+        let (flag, mut value) = match arg.find('=') {
+            Some(i) if arg.starts_with("--") => {
+                (arg[..i].to_string(), Some(arg[i + 1..].to_string()))
+            }
+            _ => (arg, None),
+        };
+        let mut value = |missing| value.take().or_else(|| args.next()).ok_or(missing);
+
+        match flag.as_str() {
+            "-h" | "--help" => options.help = true,
+            "-e" | "--eval" => eval.push(value("--eval needs an expression")?),
+            "-o" | "--format" => {
+                let views = value("--format needs a list of views")?;
+                if views == "json" {
+                    options.json = true;
+                    continue;
+                }
+                let names: Vec<&str> = views.split(',').map(str::trim).collect();
+                options.views = Some(command::views_named(&names)?);
+            }
+            "--data-dir" => options.data_dir = Some(value("--data-dir needs a directory")?),
+            "-" => file = Some(None),
+            flag if flag.starts_with('-') => return Err("unknown option, try --help"),
+            _ if file.is_some() => return Err("only one file can be run at a time"),
+            path => file = Some(Some(path.to_string())),
+        }
+    }
+
+    options.input = match (eval.is_empty(), file) {
+        (true, None) => Input::Repl,
+        (false, None) => Input::Eval(eval),
+        (true, Some(file)) => Input::Lines(file),
+        (false, Some(_)) => return Err("expressions and a file can't be run together"),
+    };
+    Ok(options)
+}
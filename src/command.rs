@@ -0,0 +1,149 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+// repl commands, lines starting with a colon
+#[derive(Debug, PartialEq, Clone)]
+pub enum Command {
+    // no views to just show the current mode
+    Mode(Vec<View>),
+    Help,
+    Vars,
+    Clear,
+    Quit,
+    Load(String),
+    // no file saves the session file
+    Save(Option<String>),
+}
+
+// what gets printed about each expression. results like the simplified form
+// and solutions are always printed.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum View {
+    Tree,
+    Infix,
+    Prefix,
+    Postfix,
+    Sexpr,
+    Latex,
+    Dot,
+    Value,
+}
+
+const VIEWS: [(&str, View); 8] = [
+    ("tree", View::Tree),
+    ("infix", View::Infix),
+    ("prefix", View::Prefix),
+    ("postfix", View::Postfix),
+    ("sexpr", View::Sexpr),
+    ("latex", View::Latex),
+    ("dot", View::Dot),
+    ("value", View::Value),
+];
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Settings {
+    pub views: BTreeSet<View>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            views: [
+                View::Infix,
+                View::Prefix,
+                View::Postfix,
+                View::Latex,
+                View::Value,
+            ]
+            .iter()
+            .copied()
+            .collect(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn shows(&self, view: View) -> bool {
+        self.views.contains(&view)
+    }
+}
+
+// the mode as a command that sets it again
+impl fmt::Display for Settings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, ":mode")?;
+        for (name, view) in VIEWS.iter() {
+            if self.shows(*view) {
+                write!(f, " {}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub const COMMANDS: [(&str, &str); 7] = [
+    (
+        ":mode",
+        "pick what's shown: tree infix prefix postfix sexpr latex dot value all",
+    ),
+    (":help", "show this list"),
+    (":vars", "list variables and functions"),
+    (":clear", "forget every variable and function"),
+    (":quit", "leave"),
+    (":load", "run every line of a file"),
+    (
+        ":save",
+        "write variables, functions and the mode to a file, or the session",
+    ),
+];
+
+pub fn help() -> String {
+    let mut help = String::new();
+    for (name, description) in COMMANDS.iter() {
+        help.push_str(&format!("{:<8} {}\n", name, description));
+    }
+    help.push_str("x := expr assigns a variable, f(x) = expr defines a function");
+    help
+}
+
+// views by name, with all for every one of them
+pub fn views_named(names: &[&str]) -> Result<Vec<View>, &'static str> {
+    let mut views = Vec::new();
+    for name in names.iter() {
+        match *name {
+            "all" => views.extend(VIEWS.iter().map(|(_, view)| *view)),
+            name => {
+                match VIEWS.iter().find(|(view, _)| *view == name) {
+                    Some((_, view)) => views.push(*view),
+                    None => return Err(
+                        "unknown mode, try tree infix prefix postfix sexpr latex dot value or all",
+                    ),
+                }
+            }
+        }
+    }
+    Ok(views)
+}
+
+pub fn parse(line: &str) -> Result<Command, &'static str> {
+    let mut words = line.trim().trim_start_matches(':').split_whitespace();
+    let name = words.next().unwrap_or("");
+    let rest: Vec<&str> = words.collect();
+
+    let file = |rest: &[&str]| match rest {
+        [] => Err("expected a file name"),
+        _ => Ok(rest.join(" ")),
+    };
+    match (name, rest.as_slice()) {
+        ("mode", views) => views_named(views).map(Command::Mode),
+        ("help", []) => Ok(Command::Help),
+        ("vars", []) => Ok(Command::Vars),
+        ("clear", []) => Ok(Command::Clear),
+        ("quit" | "q", []) => Ok(Command::Quit),
+        ("load", rest) => file(rest).map(Command::Load),
+        ("save", []) => Ok(Command::Save(None)),
+        ("save", rest) => file(rest).map(|file| Command::Save(Some(file))),
+        ("help" | "vars" | "clear" | "quit" | "q", _) => Err("that command doesn't take arguments"),
+        _ => Err("unknown command, try :help"),
+    }
+}
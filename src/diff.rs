@@ -0,0 +1,218 @@
+use super::ast::*;
+use super::poly::{binary, integer};
+use super::scan::*;
+
+impl<'a> Expr<'a> {
+    // symbolic derivative with respect to `var`. the result is only tidied up
+    // as far as dropping zeros and ones, rewrite rules can do the rest.
+    pub fn derivative(&self, var: &str) -> Result<Expr<'a>, &'static str> {
+        if !depends_on(self, var) && !matches!(self, Expr::Matrix { .. }) {
+            return Ok(integer(0));
+        }
+
+        match self {
+            Expr::Literal { .. } => Ok(integer(0)),
+            Expr::Variable { .. } | Expr::SpecialVariable { .. } => Ok(integer(1)),
+
+            Expr::Unary { op, rhs } => match op.kind {
+                TokenKind::Minus => Ok(negate(rhs.derivative(var)?)),
+                _ => Err("can only differentiate arithmetic"),
+            },
+
+            Expr::Binary { lhs, op, rhs } => {
+                let (u, v) = (lhs.as_ref(), rhs.as_ref());
+                match op.kind {
+                    TokenKind::Plus => Ok(sum(u.derivative(var)?, v.derivative(var)?)),
+                    TokenKind::Minus => Ok(difference(u.derivative(var)?, v.derivative(var)?)),
+
+                    // u'v + uv'
+                    TokenKind::Multiply => Ok(sum(
+                        product(u.derivative(var)?, v.clone()),
+                        product(u.clone(), v.derivative(var)?),
+                    )),
+
+                    // (u'v - uv') / v^2
+                    TokenKind::Divide => Ok(quotient(
+                        difference(
+                            product(u.derivative(var)?, v.clone()),
+                            product(u.clone(), v.derivative(var)?),
+                        ),
+                        power(v.clone(), integer(2)),
+                    )),
+
+                    TokenKind::Power => power_derivative(u, v, var),
+                    _ => Err("can only differentiate arithmetic"),
+                }
+            }
+
+            Expr::Call { name, args } => match (name.lexeme, args.as_slice()) {
+                (_, [u]) if name.kind == TokenKind::BuiltinFunction => {
+                    let outer = match name.lexeme {
+                        "sin" => call("cos", u.clone()),
+                        "cos" => negate(call("sin", u.clone())),
+                        "tan" => power(call("sec", u.clone()), integer(2)),
+                        "csc" => negate(product(call("csc", u.clone()), call("cot", u.clone()))),
+                        "sec" => product(call("sec", u.clone()), call("tan", u.clone())),
+                        "cot" => negate(power(call("csc", u.clone()), integer(2))),
+                        "arctan" => {
+                            quotient(integer(1), sum(integer(1), power(u.clone(), integer(2))))
+                        }
+                        "ln" => quotient(integer(1), u.clone()),
+                        "log" => quotient(integer(1), product(u.clone(), call("ln", integer(10)))),
+                        "sqrt" => quotient(integer(1), product(integer(2), self.clone())),
+                        "expand" | "factor" => integer(1),
+                        _ => return Err("can't differentiate that function"),
+                    };
+                    // chain rule
+                    Ok(product(outer, u.derivative(var)?))
+                }
+                _ => Err("can't differentiate that function"),
+            },
+
+            // differentiate each branch, the guards stay the same
+            Expr::Piecewise { branches } => Ok(Expr::Piecewise {
+                branches: branches
+                    .iter()
+                    .map(|branch| {
+                        Ok(Branch {
+                            value: branch.value.derivative(var)?,
+                            guard: branch.guard.clone(),
+                        })
+                    })
+                    .collect::<Result<_, &'static str>>()?,
+            }),
+
+            // entry by entry
+            Expr::Matrix { rows } => Ok(Expr::Matrix {
+                rows: rows
+                    .iter()
+                    .map(|row| row.iter().map(|entry| entry.derivative(var)).collect())
+                    .collect::<Result<_, &'static str>>()?,
+            }),
+        }
+    }
+}
+
+fn power_derivative<'a>(u: &Expr<'a>, v: &Expr<'a>, var: &str) -> Result<Expr<'a>, &'static str> {
+    if !depends_on(v, var) {
+        // v u^(v-1) u'
+        let exponent = match v {
+            Expr::Literal { literal } => match literal.kind {
+                TokenKind::Integer(n) if n > 0 => integer(n as i128 - 1),
+                _ => difference(v.clone(), integer(1)),
+            },
+            _ => difference(v.clone(), integer(1)),
+        };
+        return Ok(product(
+            product(v.clone(), power(u.clone(), exponent)),
+            u.derivative(var)?,
+        ));
+    }
+
+    let this = power(u.clone(), v.clone());
+    if !depends_on(u, var) {
+        // u^v ln(u) v'
+        return Ok(product(
+            product(this, call("ln", u.clone())),
+            v.derivative(var)?,
+        ));
+    }
+
+    // u^v (v' ln(u) + v u'/u)
+    Ok(product(
+        this,
+        sum(
+            product(v.derivative(var)?, call("ln", u.clone())),
+            quotient(product(v.clone(), u.derivative(var)?), u.clone()),
+        ),
+    ))
+}
+
+struct DependsOn<'v> {
+    var: &'v str,
+    found: bool,
+}
+
+impl<'a> Visitor<'a> for DependsOn<'_> {
+    fn visit_variable(&mut self, name: &Token<'a>) {
+        self.found |= name.lexeme == self.var;
+    }
+
+    fn visit_special_variable(&mut self, name: &Token<'a>) {
+        self.found |= name.lexeme == self.var;
+    }
+}
+
+pub fn depends_on(expr: &Expr<'_>, var: &str) -> bool {
+    let mut depends = DependsOn { var, found: false };
+    depends.visit_expr(expr);
+    depends.found
+}
+
+fn is_integer(expr: &Expr<'_>, value: u64) -> bool {
+    matches!(expr, Expr::Literal { literal } if literal.kind == TokenKind::Integer(value))
+}
+
+pub fn sum<'a>(lhs: Expr<'a>, rhs: Expr<'a>) -> Expr<'a> {
+    match (is_integer(&lhs, 0), is_integer(&rhs, 0)) {
+        (true, _) => rhs,
+        (_, true) => lhs,
+        _ => binary(lhs, TokenKind::Plus, "+", rhs),
+    }
+}
+
+pub fn difference<'a>(lhs: Expr<'a>, rhs: Expr<'a>) -> Expr<'a> {
+    match (is_integer(&lhs, 0), is_integer(&rhs, 0)) {
+        (_, true) => lhs,
+        (true, _) => negate(rhs),
+        _ => binary(lhs, TokenKind::Minus, "-", rhs),
+    }
+}
+
+pub fn product<'a>(lhs: Expr<'a>, rhs: Expr<'a>) -> Expr<'a> {
+    if is_integer(&lhs, 0) || is_integer(&rhs, 0) {
+        integer(0)
+    } else if is_integer(&lhs, 1) {
+        rhs
+    } else if is_integer(&rhs, 1) {
+        lhs
+    } else {
+        binary(lhs, TokenKind::Multiply, "*", rhs)
+    }
+}
+
+pub fn quotient<'a>(lhs: Expr<'a>, rhs: Expr<'a>) -> Expr<'a> {
+    if is_integer(&lhs, 0) || is_integer(&rhs, 1) {
+        lhs
+    } else {
+        binary(lhs, TokenKind::Divide, "/", rhs)
+    }
+}
+
+pub fn power<'a>(lhs: Expr<'a>, rhs: Expr<'a>) -> Expr<'a> {
+    if is_integer(&rhs, 1) {
+        lhs
+    } else if is_integer(&rhs, 0) {
+        integer(1)
+    } else {
+        binary(lhs, TokenKind::Power, "^", rhs)
+    }
+}
+
+pub fn negate(expr: Expr<'_>) -> Expr<'_> {
+    match expr {
+        _ if is_integer(&expr, 0) => expr,
+        Expr::Unary { op, rhs } if op.kind == TokenKind::Minus => *rhs,
+        _ => Expr::Unary {
+            op: Token::new(TokenKind::Minus, "-"),
+            rhs: Box::new(expr),
+        },
+    }
+}
+
+pub fn call<'a>(name: &'static str, arg: Expr<'a>) -> Expr<'a> {
+    Expr::Call {
+        name: Token::new(TokenKind::BuiltinFunction, name),
+        args: vec![arg],
+    }
+}
@@ -0,0 +1,93 @@
+use super::ast::*;
+use super::scan::*;
+
+// graphviz for the parse tree, with a node for each token. children are drawn
+// left to right in order, so `dot -Tsvg` shows how precedence and implicit
+// multiplication grouped things.
+struct Dot {
+    out: String,
+    nodes: usize,
+    // the node the next one hangs off
+    parent: Option<usize>,
+}
+
+impl Dot {
+    fn node(&mut self, label: &str) -> usize {
+        let id = self.nodes;
+        self.nodes += 1;
+        let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+        self.out
+            .push_str(&format!("    n{} [label=\"{}\"];\n", id, label));
+        if let Some(parent) = self.parent {
+            self.out.push_str(&format!("    n{} -> n{};\n", parent, id));
+        }
+        id
+    }
+
+    fn children(&mut self, label: &str, exprs: &[&Expr<'_>]) {
+        let id = self.node(label);
+        let parent = self.parent.replace(id);
+        for expr in exprs.iter() {
+            self.visit_expr(expr);
+        }
+        self.parent = parent;
+    }
+}
+
+impl<'a> Visitor<'a> for Dot {
+    fn visit_literal(&mut self, literal: &Token<'a>) {
+        self.node(&literal.to_string());
+    }
+
+    fn visit_variable(&mut self, name: &Token<'a>) {
+        self.node(name.lexeme);
+    }
+
+    fn visit_special_variable(&mut self, name: &Token<'a>) {
+        self.node(name.lexeme);
+    }
+
+    fn visit_unary(&mut self, op: &Token<'a>, rhs: &Expr<'a>) {
+        self.children(op.lexeme, &[rhs]);
+    }
+
+    fn visit_binary(&mut self, lhs: &Expr<'a>, op: &Token<'a>, rhs: &Expr<'a>) {
+        self.children(op.lexeme, &[lhs, rhs]);
+    }
+
+    fn visit_call(&mut self, name: &Token<'a>, args: &[Expr<'a>]) {
+        self.children(name.lexeme, &args.iter().collect::<Vec<_>>());
+    }
+
+    fn visit_piecewise(&mut self, branches: &[Branch<'a>]) {
+        let id = self.node("piecewise");
+        let parent = self.parent.replace(id);
+        for branch in branches.iter() {
+            match &branch.guard {
+                Some(guard) => self.children("if", &[guard, &branch.value]),
+                None => self.children("otherwise", &[&branch.value]),
+            }
+        }
+        self.parent = parent;
+    }
+
+    fn visit_matrix(&mut self, rows: &[Vec<Expr<'a>>]) {
+        let id = self.node(&format!("matrix {}x{}", rows.len(), rows[0].len()));
+        let parent = self.parent.replace(id);
+        for row in rows.iter() {
+            self.children("row", &row.iter().collect::<Vec<_>>());
+        }
+        self.parent = parent;
+    }
+}
+
+pub fn to_dot(expr: &Expr<'_>) -> String {
+    let mut dot = Dot {
+        out: String::from("digraph expr {\n    ordering=out;\n"),
+        nodes: 0,
+        parent: None,
+    };
+    dot.visit_expr(expr);
+    dot.out.push('}');
+    dot.out
+}
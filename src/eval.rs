@@ -0,0 +1,328 @@
+use super::ast::*;
+use super::matrix::Matrix;
+use super::quadrature;
+use super::root;
+use super::scan::*;
+
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Number(f64),
+    Bool(bool),
+    Matrix(Matrix),
+}
+
+impl Value {
+    pub fn number(&self) -> Result<f64, &'static str> {
+        match self {
+            Value::Number(n) => Ok(*n),
+            Value::Bool(_) => Err("expected a number, got a boolean"),
+            Value::Matrix(_) => Err("expected a number, got a matrix"),
+        }
+    }
+
+    // a literal for the value, booleans can't be written as one
+    pub fn to_expr(&self) -> Option<Expr<'static>> {
+        let number = |n: f64| {
+            let literal = if n.fract() == 0.0 && n.abs() < 1e15 {
+                Token::integer(n.abs() as u64)
+            } else {
+                Token::new(TokenKind::Float(n.abs()), "")
+            };
+            let literal = Expr::Literal { literal };
+            if n < 0.0 {
+                Expr::Unary {
+                    op: Token::new(TokenKind::Minus, "-"),
+                    rhs: Box::new(literal),
+                }
+            } else {
+                literal
+            }
+        };
+
+        match self {
+            Value::Number(n) => Some(number(*n)),
+            Value::Bool(_) => None,
+            Value::Matrix(m) => Some(Expr::Matrix {
+                rows: m
+                    .data
+                    .chunks(m.cols)
+                    .map(|row| row.iter().map(|n| number(*n)).collect())
+                    .collect(),
+            }),
+        }
+    }
+
+    pub fn bool(&self) -> Result<bool, &'static str> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            _ => Err("expected a boolean"),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Matrix(m) => write!(f, "{}", m),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Env {
+    pub vars: HashMap<String, Value>,
+}
+
+impl Env {
+    pub fn new() -> Env {
+        Env::default()
+    }
+
+    pub fn with(&self, name: &str, value: f64) -> Env {
+        let mut env = self.clone();
+        env.vars.insert(name.to_string(), Value::Number(value));
+        env
+    }
+}
+
+// numbers this close together compare equal, so 0.1 + 0.2 = 0.3
+const EQUAL_TOLERANCE: f64 = 1e-12;
+
+pub fn eval(expr: &Expr<'_>, env: &Env) -> Result<Value, &'static str> {
+    match expr {
+        Expr::Literal { literal } => match literal.kind {
+            TokenKind::Integer(i) => Ok(Value::Number(i as f64)),
+            TokenKind::Float(f) => Ok(Value::Number(f)),
+            _ => Err("not a number"),
+        },
+
+        Expr::Variable { name } | Expr::SpecialVariable { name } => {
+            match env.vars.get(name.lexeme) {
+                Some(value) => Ok(value.clone()),
+                None => match name.lexeme {
+                    "pi" => Ok(Value::Number(std::f64::consts::PI)),
+                    "e" => Ok(Value::Number(std::f64::consts::E)),
+                    "inf" | "∞" => Ok(Value::Number(f64::INFINITY)),
+                    _ => Err("unbound variable"),
+                },
+            }
+        }
+
+        Expr::Unary { op, rhs } => {
+            let rhs = eval(rhs, env)?;
+            if let (TokenKind::Minus, Value::Matrix(m)) = (op.kind, &rhs) {
+                return Ok(Value::Matrix(m.map(|x| -x)));
+            }
+            match op.kind {
+                TokenKind::Minus => Ok(Value::Number(-rhs.number()?)),
+                TokenKind::Factorial => factorial(rhs.number()?).map(Value::Number),
+                TokenKind::Not => Ok(Value::Bool(!rhs.bool()?)),
+                _ => Err("unknown unary operator"),
+            }
+        }
+
+        Expr::Binary { lhs, op, rhs } => {
+            // and/or only look at the right side if they have to
+            match op.kind {
+                TokenKind::And => {
+                    return Ok(Value::Bool(
+                        eval(lhs, env)?.bool()? && eval(rhs, env)?.bool()?,
+                    ))
+                }
+                TokenKind::Or => {
+                    return Ok(Value::Bool(
+                        eval(lhs, env)?.bool()? || eval(rhs, env)?.bool()?,
+                    ))
+                }
+                _ => {}
+            }
+
+            let (lhs, rhs) = match (eval(lhs, env)?, eval(rhs, env)?) {
+                (lhs @ Value::Matrix(_), rhs) | (lhs, rhs @ Value::Matrix(_)) => {
+                    return matrix_binary(op.kind, lhs, rhs)
+                }
+                (lhs, rhs) => (lhs.number()?, rhs.number()?),
+            };
+            let equal = (lhs - rhs).abs() <= EQUAL_TOLERANCE * lhs.abs().max(rhs.abs()).max(1.0);
+            match op.kind {
+                TokenKind::Plus => Ok(Value::Number(lhs + rhs)),
+                TokenKind::Minus => Ok(Value::Number(lhs - rhs)),
+                TokenKind::Multiply => Ok(Value::Number(lhs * rhs)),
+                TokenKind::Divide => Ok(Value::Number(lhs / rhs)),
+                TokenKind::Modulo => Ok(Value::Number(lhs % rhs)),
+                TokenKind::Power => Ok(Value::Number(lhs.powf(rhs))),
+                TokenKind::Equal => Ok(Value::Bool(equal)),
+                TokenKind::NotEqual => Ok(Value::Bool(!equal)),
+                TokenKind::Less => Ok(Value::Bool(lhs < rhs && !equal)),
+                TokenKind::LessEqual => Ok(Value::Bool(lhs < rhs || equal)),
+                TokenKind::Greater => Ok(Value::Bool(lhs > rhs && !equal)),
+                TokenKind::GreaterEqual => Ok(Value::Bool(lhs > rhs || equal)),
+                _ => Err("unknown binary operator"),
+            }
+        }
+
+        Expr::Call { name, args } => call(name.lexeme, args, env),
+
+        // the first branch whose guard holds
+        Expr::Piecewise { branches } => {
+            for branch in branches.iter() {
+                let holds = match &branch.guard {
+                    Some(guard) => eval(guard, env)?.bool()?,
+                    None => true,
+                };
+                if holds {
+                    return eval(&branch.value, env);
+                }
+            }
+            Err("no branch of the piecewise function applies")
+        }
+
+        Expr::Matrix { rows } => {
+            let data = rows
+                .iter()
+                .flatten()
+                .map(|entry| eval_number(entry, env))
+                .collect::<Result<_, _>>()?;
+            Ok(Value::Matrix(Matrix::new(rows.len(), rows[0].len(), data)))
+        }
+    }
+}
+
+// numbers apply to every entry, matrices add and subtract entry by entry and
+// multiply as matrices, or as a dot product for two vectors of the same
+// length that can't be multiplied otherwise
+fn matrix_binary(op: TokenKind, lhs: Value, rhs: Value) -> Result<Value, &'static str> {
+    let result = match (op, lhs, rhs) {
+        (TokenKind::Plus, Value::Matrix(a), Value::Matrix(b)) => a.zip(&b, |x, y| x + y)?,
+        (TokenKind::Minus, Value::Matrix(a), Value::Matrix(b)) => a.zip(&b, |x, y| x - y)?,
+        (TokenKind::Multiply, Value::Matrix(a), Value::Matrix(b)) => {
+            if a.cols != b.rows && a.is_vector() && b.is_vector() {
+                return Ok(Value::Number(a.dot(&b)?));
+            }
+            a.mul(&b)?
+        }
+
+        (TokenKind::Power, Value::Matrix(a), Value::Number(n)) if n.fract() == 0.0 => {
+            a.pow(n as i64)?
+        }
+
+        (op, Value::Matrix(a), Value::Number(n)) => match op {
+            TokenKind::Plus => a.map(|x| x + n),
+            TokenKind::Minus => a.map(|x| x - n),
+            TokenKind::Multiply => a.map(|x| x * n),
+            TokenKind::Divide => a.map(|x| x / n),
+            _ => return Err("can't do that to a matrix"),
+        },
+        (op, Value::Number(n), Value::Matrix(a)) => match op {
+            TokenKind::Plus => a.map(|x| n + x),
+            TokenKind::Minus => a.map(|x| n - x),
+            TokenKind::Multiply => a.map(|x| n * x),
+            _ => return Err("can't do that to a matrix"),
+        },
+
+        _ => return Err("can't do that to a matrix"),
+    };
+    Ok(Value::Matrix(result))
+}
+
+pub fn eval_number(expr: &Expr<'_>, env: &Env) -> Result<f64, &'static str> {
+    eval(expr, env)?.number()
+}
+
+// 171! is already bigger than an f64 can hold
+const MAX_FACTORIAL: f64 = 170.0;
+
+fn factorial(n: f64) -> Result<f64, &'static str> {
+    if n < 0.0 || n.fract() != 0.0 {
+        return Err("factorial of a non-natural number");
+    }
+    if n > MAX_FACTORIAL {
+        return Ok(f64::INFINITY);
+    }
+    Ok((1..=n as u64).fold(1.0, |acc, i| acc * i as f64))
+}
+
+fn call(name: &str, args: &[Expr<'_>], env: &Env) -> Result<Value, &'static str> {
+    if name == "sigma" {
+        return sigma(args, env).map(Value::Number);
+    }
+    if name == "root" {
+        return root::call(args, env).map(Value::Number);
+    }
+    if matches!(name, "integrate" | "int" | "∫") {
+        return quadrature::call(args, env).map(|estimate| Value::Number(estimate.value));
+    }
+
+    let arg = match args {
+        [arg] => eval(arg, env)?,
+        _ => return Err("wrong number of arguments"),
+    };
+
+    if let Value::Matrix(m) = arg {
+        return match name {
+            "transpose" => Ok(Value::Matrix(m.transpose())),
+            "det" => m.det().map(Value::Number),
+            "inverse" => m.inverse().map(Value::Matrix),
+            "rank" => Ok(Value::Number(m.rank() as f64)),
+            "eigenvalues" => {
+                let eigenvalues = m.eigenvalues()?;
+                if eigenvalues.iter().any(|(_, im)| *im != 0.0) {
+                    return Err("matrix has complex eigenvalues");
+                }
+                let real: Vec<f64> = eigenvalues.iter().map(|(re, _)| *re).collect();
+                Ok(Value::Matrix(Matrix::new(1, real.len(), real)))
+            }
+            "expand" | "factor" => Ok(Value::Matrix(m)),
+            _ => Err("can't do that to a matrix"),
+        };
+    }
+
+    let x = arg.number()?;
+    let value = match name {
+        "sin" => Ok(x.sin()),
+        "cos" => Ok(x.cos()),
+        "tan" => Ok(x.tan()),
+        "csc" => Ok(1.0 / x.sin()),
+        "sec" => Ok(1.0 / x.cos()),
+        "cot" => Ok(1.0 / x.tan()),
+        "arctan" => Ok(x.atan()),
+        "ln" => Ok(x.ln()),
+        "log" => Ok(x.log10()),
+        "sqrt" => Ok(x.sqrt()),
+        "expand" | "factor" => Ok(x),
+        _ => Err("unknown function"),
+    };
+    value.map(Value::Number)
+}
+
+// sums with more terms than this would take too long to add up
+const MAX_SIGMA_TERMS: i64 = 10_000_000;
+
+// sigma(i=0, 100, i^2)
+fn sigma(args: &[Expr<'_>], env: &Env) -> Result<f64, &'static str> {
+    let (var, start, end, body) = match args {
+        [Expr::Binary { lhs, op, rhs }, end, body] if op.kind == TokenKind::Equal => {
+            match lhs.as_ref() {
+                Expr::Variable { name } => (name.lexeme, rhs, end, body),
+                _ => return Err("sigma needs an index variable"),
+            }
+        }
+        _ => return Err("sigma takes an index, an end and a body"),
+    };
+
+    let start = eval_number(start, env)?.round() as i64;
+    let end = eval_number(end, env)?.round() as i64;
+    if end.saturating_sub(start) >= MAX_SIGMA_TERMS {
+        return Err("sigma range is too big, it can have up to 10000000 terms");
+    }
+    let mut sum = 0.0;
+    for i in start..=end {
+        sum += eval_number(body, &env.with(var, i as f64))?;
+    }
+    Ok(sum)
+}
@@ -0,0 +1,543 @@
+use super::ast::*;
+use super::poly::*;
+use super::rational::{Rational, OVERFLOW};
+use super::scan::*;
+
+// coefficients of a polynomial in one variable, lowest degree first, with no
+// trailing zeros. the zero polynomial is empty.
+pub type Dense = Vec<Rational>;
+
+pub fn trim(mut p: Dense) -> Dense {
+    while p.last().is_some_and(|c| c.is_zero()) {
+        p.pop();
+    }
+    p
+}
+
+pub fn degree(p: &[Rational]) -> usize {
+    p.len().saturating_sub(1)
+}
+
+pub fn eval(p: &[Rational], x: Rational) -> Result<Rational, &'static str> {
+    p.iter()
+        .rev()
+        .try_fold(Rational::ZERO, |acc, c| acc.checked_mul(x)?.checked_add(*c))
+}
+
+pub fn add(a: &[Rational], b: &[Rational]) -> Result<Dense, &'static str> {
+    let mut result = a.to_vec();
+    result.resize(a.len().max(b.len()), Rational::ZERO);
+    for (i, c) in b.iter().enumerate() {
+        result[i] = result[i].checked_add(*c)?;
+    }
+    Ok(trim(result))
+}
+
+pub fn sub(a: &[Rational], b: &[Rational]) -> Result<Dense, &'static str> {
+    let negated: Dense = b.iter().map(|c| -*c).collect();
+    add(a, &negated)
+}
+
+pub fn mul(a: &[Rational], b: &[Rational]) -> Result<Dense, &'static str> {
+    if a.is_empty() || b.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut result = vec![Rational::ZERO; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            result[i + j] = result[i + j].checked_add(x.checked_mul(*y)?)?;
+        }
+    }
+    Ok(trim(result))
+}
+
+pub fn scale(p: &[Rational], by: Rational) -> Result<Dense, &'static str> {
+    p.iter()
+        .map(|c| c.checked_mul(by))
+        .collect::<Result<_, _>>()
+        .map(trim)
+}
+
+pub fn divmod(a: &[Rational], b: &[Rational]) -> Result<(Dense, Dense), &'static str> {
+    let mut rem = a.to_vec();
+    if b.len() > a.len() {
+        return Ok((Vec::new(), rem));
+    }
+
+    let lead = *b.last().expect("division by the zero polynomial");
+    let mut quot = vec![Rational::ZERO; a.len() - b.len() + 1];
+    for i in (0..quot.len()).rev() {
+        let c = rem[i + b.len() - 1].checked_div(lead)?;
+        quot[i] = c;
+        for (j, d) in b.iter().enumerate() {
+            rem[i + j] = rem[i + j].checked_sub(c.checked_mul(*d)?)?;
+        }
+    }
+
+    rem.truncate(b.len() - 1);
+    Ok((trim(quot), trim(rem)))
+}
+
+pub fn derivative(p: &[Rational]) -> Result<Dense, &'static str> {
+    p.iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, c)| c.checked_mul(Rational::integer(i as i128)))
+        .collect::<Result<_, _>>()
+        .map(trim)
+}
+
+pub fn monic(p: &[Rational]) -> Result<Dense, &'static str> {
+    match p.last() {
+        Some(lead) => scale(p, lead.recip()),
+        None => Ok(Vec::new()),
+    }
+}
+
+// remainders are made primitive as they go, which only changes the gcd by a
+// constant and keeps the coefficients from blowing up
+pub fn gcd(a: &[Rational], b: &[Rational]) -> Result<Dense, &'static str> {
+    let (mut a, mut b) = (a.to_vec(), b.to_vec());
+    while !b.is_empty() {
+        let (_, r) = divmod(&a, &b)?;
+        a = b;
+        b = if r.is_empty() { r } else { primitive(&r)? };
+    }
+    monic(&a)
+}
+
+// scale to integer coefficients with no common divisor and a positive
+// leading coefficient
+pub fn primitive(p: &[Rational]) -> Result<Dense, &'static str> {
+    let mut den: i128 = 1;
+    for c in p.iter() {
+        den = (den / super::rational::gcd(den, c.den()))
+            .checked_mul(c.den())
+            .ok_or(OVERFLOW)?;
+    }
+    let mut num = 0;
+    for c in p.iter() {
+        num = super::rational::gcd(num, c.checked_mul(Rational::integer(den))?.num());
+    }
+    let sign = if p.last().is_some_and(|c| c.is_negative()) {
+        -1
+    } else {
+        1
+    };
+    scale(p, Rational::new(sign * den, num))
+}
+
+#[derive(Debug, Clone)]
+pub struct Factorization<'a> {
+    pub unit: Rational,
+    pub factors: Vec<(Poly<'a>, u32)>,
+}
+
+impl<'a> Factorization<'a> {
+    pub fn to_expr(&self) -> Expr<'a> {
+        let mut factors = self.factors.iter().map(|(poly, multiplicity)| {
+            let base = poly.to_expr();
+            if *multiplicity == 1 {
+                base
+            } else {
+                binary(base, TokenKind::Power, "^", integer(*multiplicity as i128))
+            }
+        });
+
+        let product = match factors.next() {
+            Some(first) => {
+                factors.fold(first, |lhs, rhs| binary(lhs, TokenKind::Multiply, "*", rhs))
+            }
+            None => return Poly::constant(self.unit).to_expr(),
+        };
+
+        let unit = self.unit.abs();
+        let mut expr = if unit.num() == 1 {
+            product
+        } else {
+            binary(integer(unit.num()), TokenKind::Multiply, "*", product)
+        };
+        if !unit.is_integer() {
+            expr = binary(expr, TokenKind::Divide, "/", integer(unit.den()));
+        }
+
+        if self.unit.is_negative() {
+            Expr::Unary {
+                op: Token::new(TokenKind::Minus, "-"),
+                rhs: Box::new(expr),
+            }
+        } else {
+            expr
+        }
+    }
+}
+
+pub fn factor_poly<'a>(poly: &Poly<'a>) -> Result<Factorization<'a>, &'static str> {
+    let (content, common, rest) = poly.common_factor()?;
+    let mut factors: Vec<(Poly<'a>, u32)> = common
+        .0
+        .iter()
+        .map(|(atom, exp)| (Poly::atom(atom.expr.clone()), *exp))
+        .collect();
+
+    match rest.univariate() {
+        Some((Some(atom), coefficients)) => {
+            for (factor, multiplicity) in factor_dense(&coefficients)? {
+                factors.push((Poly::from_dense(&atom, &factor), multiplicity));
+            }
+        }
+        _ => {
+            if rest.as_constant() != Some(Rational::ONE) {
+                factors.push((rest, 1));
+            }
+        }
+    }
+
+    factors.sort_by_cached_key(|(poly, multiplicity)| {
+        let degree = poly.terms.keys().next().map_or(0, |m| m.degree());
+        (degree, to_infix(&poly.to_expr()), *multiplicity)
+    });
+
+    Ok(Factorization {
+        unit: content,
+        factors,
+    })
+}
+
+// irreducible factors of a primitive integer polynomial with their
+// multiplicities. the product of the factors is the input exactly.
+pub fn factor_dense(p: &[Rational]) -> Result<Vec<(Dense, u32)>, &'static str> {
+    let mut factors = Vec::new();
+    for (part, multiplicity) in square_free(p)? {
+        for factor in split(&primitive(&part)?)? {
+            factors.push((factor, multiplicity));
+        }
+    }
+    Ok(factors)
+}
+
+// yun's algorithm: p = a1 * a2^2 * a3^3 ... with every ai square-free
+pub fn square_free(p: &[Rational]) -> Result<Vec<(Dense, u32)>, &'static str> {
+    if square_free_mod(p) {
+        return Ok(vec![(p.to_vec(), 1)]);
+    }
+
+    let mut parts = Vec::new();
+    let dp = derivative(p)?;
+    let a = gcd(p, &dp)?;
+    let mut b = divmod(p, &a)?.0;
+    let c = divmod(&dp, &a)?.0;
+    let mut d = sub(&c, &derivative(&b)?)?;
+
+    let mut multiplicity = 1;
+    while degree(&b) > 0 {
+        let a = gcd(&b, &d)?;
+        b = divmod(&b, &a)?.0;
+        let c = divmod(&d, &a)?.0;
+        d = sub(&c, &derivative(&b)?)?;
+
+        if degree(&a) > 0 {
+            parts.push((a, multiplicity));
+        }
+        multiplicity += 1;
+    }
+
+    Ok(parts)
+}
+
+// a prime for square_free_mod, small enough that products fit in an i128
+const PRIME: i128 = 1_000_000_007;
+
+// a cheap test that skips yun's algorithm most of the time, where the exact
+// gcds can overflow. if p = a^2 b then the same holds mod a prime that doesn't
+// divide the leading coefficient, so a gcd of 1 with p' mod the prime means p
+// is square-free. false means it may not be.
+fn square_free_mod(p: &[Rational]) -> bool {
+    if degree(p) < 1 || p.iter().any(|c| !c.is_integer()) {
+        return false;
+    }
+    let reduce = |p: Vec<i128>| {
+        let mut p: Vec<i128> = p.into_iter().map(|c| c.rem_euclid(PRIME)).collect();
+        while p.last() == Some(&0) {
+            p.pop();
+        }
+        p
+    };
+    let a = reduce(p.iter().map(|c| c.num()).collect());
+    if a.len() != p.len() {
+        return false;
+    }
+    let b = reduce(
+        a.iter()
+            .enumerate()
+            .skip(1)
+            .map(|(i, c)| c * i as i128)
+            .collect(),
+    );
+
+    // euclid's algorithm mod the prime
+    let (mut a, mut b) = (a, b);
+    while !b.is_empty() {
+        let inverse = inverse_mod(*b.last().unwrap());
+        while a.len() >= b.len() {
+            let c = a[a.len() - 1] * inverse % PRIME;
+            let shift = a.len() - b.len();
+            for (j, d) in b.iter().enumerate() {
+                a[shift + j] = (a[shift + j] - c * d).rem_euclid(PRIME);
+            }
+            a = reduce(a);
+        }
+        std::mem::swap(&mut a, &mut b);
+    }
+    a.len() == 1
+}
+
+// x^(prime - 2) is 1/x mod the prime
+fn inverse_mod(x: i128) -> i128 {
+    let (mut result, mut base, mut exp) = (1, x, PRIME - 2);
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = result * base % PRIME;
+        }
+        base = base * base % PRIME;
+        exp >>= 1;
+    }
+    result
+}
+
+// split a square-free primitive polynomial into irreducible factors
+fn split(p: &[Rational]) -> Result<Vec<Dense>, &'static str> {
+    let mut factors = Vec::new();
+    let mut p = p.to_vec();
+
+    // linear factors from the rational root test
+    while degree(&p) > 1 {
+        match rational_root(&p)? {
+            Some(root) => {
+                let linear = vec![
+                    -Rational::integer(root.num()),
+                    Rational::integer(root.den()),
+                ];
+                p = divmod(&p, &linear)?.0;
+                factors.push(linear);
+            }
+            None => break,
+        }
+    }
+
+    // with no rational roots a quadratic has a discriminant that isn't a
+    // perfect square and a cubic has no linear factor, so both are
+    // irreducible. anything bigger needs a general search.
+    if degree(&p) >= 4 {
+        match kronecker(&p)? {
+            Some((f, g)) => {
+                factors.extend(split(&f)?);
+                factors.extend(split(&g)?);
+            }
+            None => factors.push(p),
+        }
+    } else if degree(&p) > 0 {
+        factors.push(p);
+    }
+
+    Ok(factors)
+}
+
+// any root p/q of an integer polynomial has p dividing the constant term and
+// q dividing the leading coefficient
+fn rational_root(p: &[Rational]) -> Result<Option<Rational>, &'static str> {
+    let constant = p[0].num();
+    if constant == 0 {
+        return Ok(Some(Rational::ZERO));
+    }
+
+    let lead = p[p.len() - 1].num();
+    let (nums, dens) = match (divisors(constant), divisors(lead)) {
+        (Some(nums), Some(dens)) => (nums, dens),
+        _ => return Ok(None),
+    };
+    for num in nums.iter() {
+        for den in dens.iter() {
+            for sign in [1, -1].iter() {
+                let root = Rational::new(sign * num, *den);
+                if eval(p, root)?.is_zero() {
+                    return Ok(Some(root));
+                }
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+// give up on numbers whose divisors take too long to find
+const MAX_TRIAL_DIVISOR: i128 = 1_000_000;
+
+fn divisors(n: i128) -> Option<Vec<i128>> {
+    let n = n.abs();
+    let mut small = Vec::new();
+    let mut large = Vec::new();
+
+    let mut i = 1;
+    while i * i <= n {
+        if i > MAX_TRIAL_DIVISOR {
+            return None;
+        }
+        if n % i == 0 {
+            small.push(i);
+            if i * i != n {
+                large.push(n / i);
+            }
+        }
+        i += 1;
+    }
+
+    small.extend(large.into_iter().rev());
+    Some(small)
+}
+
+// stop searching for factors after trying this many candidates
+const MAX_KRONECKER_CANDIDATES: usize = 200_000;
+
+// kronecker's method: a factor of degree d is determined by its values at d+1
+// points, and each of those values has to divide the value of p there. try
+// every combination of divisors, interpolate, and test by division. values
+// too big to handle mean giving up, leaving p as it is.
+fn kronecker(p: &[Rational]) -> Result<Option<(Dense, Dense)>, &'static str> {
+    let points: Vec<Rational> = (0..=degree(p) / 2 + 1)
+        .map(|i| {
+            let x = (i as i128 + 1) / 2;
+            Rational::integer(if i % 2 == 0 { -x } else { x })
+        })
+        .collect();
+
+    // a root the rational root test gave up on, which divisors(0) couldn't
+    // describe anyway
+    for x in points.iter() {
+        if eval(p, *x).is_ok_and(|value| value.is_zero()) {
+            let linear = vec![-*x, Rational::ONE];
+            return Ok(Some((linear.clone(), divmod(p, &linear)?.0)));
+        }
+    }
+
+    for d in 2..=degree(p) / 2 {
+        let xs = &points[..=d];
+        let mut choices = Vec::new();
+        for (i, x) in xs.iter().enumerate() {
+            let value = match eval(p, *x) {
+                Ok(value) => value.num(),
+                Err(_) => return Ok(None),
+            };
+            let mut options: Vec<i128> = match divisors(value) {
+                Some(options) => options,
+                None => return Ok(None),
+            };
+            // a factor and its negation are the same factor, so fix the sign
+            // of the first value
+            if i > 0 {
+                let negated: Vec<i128> = options.iter().map(|v| -v).collect();
+                options.extend(negated);
+            }
+            choices.push(options);
+        }
+
+        let total = choices
+            .iter()
+            .try_fold(1usize, |acc, c| acc.checked_mul(c.len()));
+        match total {
+            Some(total) if total <= MAX_KRONECKER_CANDIDATES => {}
+            _ => return Ok(None),
+        }
+
+        let mut index = vec![0; choices.len()];
+        loop {
+            let ys: Vec<Rational> = index
+                .iter()
+                .zip(choices.iter())
+                .map(|(i, c)| Rational::integer(c[*i]))
+                .collect();
+            if let Ok(Some(factors)) = try_candidate(p, xs, &ys, d) {
+                return Ok(Some(factors));
+            }
+
+            // next combination, like counting in a mixed radix
+            let mut digit = 0;
+            loop {
+                if digit == index.len() {
+                    break;
+                }
+                index[digit] += 1;
+                if index[digit] < choices[digit].len() {
+                    break;
+                }
+                index[digit] = 0;
+                digit += 1;
+            }
+            if digit == index.len() {
+                break;
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+// the factor of degree d through (xs[i], ys[i]) and what's left, if it is one
+fn try_candidate(
+    p: &[Rational],
+    xs: &[Rational],
+    ys: &[Rational],
+    d: usize,
+) -> Result<Option<(Dense, Dense)>, &'static str> {
+    let candidate = interpolate(xs, ys)?;
+    if degree(&candidate) == d && candidate.iter().all(|c| c.is_integer()) {
+        let (quot, rem) = divmod(p, &candidate)?;
+        if rem.is_empty() {
+            return Ok(Some((primitive(&candidate)?, primitive(&quot)?)));
+        }
+    }
+    Ok(None)
+}
+
+// lagrange interpolation through (xs[i], ys[i])
+fn interpolate(xs: &[Rational], ys: &[Rational]) -> Result<Dense, &'static str> {
+    let mut result = Vec::new();
+    for (i, (xi, yi)) in xs.iter().zip(ys.iter()).enumerate() {
+        let mut basis = vec![*yi];
+        for (j, xj) in xs.iter().enumerate() {
+            if i != j {
+                let denom = xi.checked_sub(*xj)?;
+                basis = mul(
+                    &basis,
+                    &[
+                        (-*xj).checked_div(denom)?,
+                        Rational::ONE.checked_div(denom)?,
+                    ],
+                )?;
+            }
+        }
+        result = add(&result, &basis)?;
+    }
+    Ok(result)
+}
+
+impl<'a> Expr<'a> {
+    // product of irreducible factors over the rationals. the two sides of a
+    // comparison are factored separately. anything too big to factor exactly
+    // is left as it is.
+    pub fn factor(&self) -> Result<Expr<'a>, &'static str> {
+        Ok(match self {
+            Expr::Binary { lhs, op, rhs } if op.kind.is_comparison() => Expr::Binary {
+                lhs: Box::new(lhs.factor()?),
+                op: *op,
+                rhs: Box::new(rhs.factor()?),
+            },
+            _ => match Poly::from_expr(self).and_then(|poly| factor_poly(&poly)) {
+                Ok(factors) => factors.to_expr(),
+                Err(OVERFLOW) => self.clone(),
+                Err(err) => return Err(err),
+            },
+        })
+    }
+}
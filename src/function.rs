@@ -0,0 +1,70 @@
+use super::ast::*;
+use super::rewrite::{substitute, Bindings};
+use super::scan::*;
+
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Function<'a> {
+    pub params: Vec<&'a str>,
+    pub body: Expr<'a>,
+}
+
+impl Function<'_> {
+    pub fn signature(&self, name: &str) -> String {
+        format!("{}({})", name, self.params.join(", "))
+    }
+}
+
+pub type Functions<'a> = HashMap<String, Function<'a>>;
+
+// calls nested deeper than this are probably a function calling itself
+const MAX_INLINE_DEPTH: usize = 32;
+
+// replace every call to a user function with its body, arguments substituted
+// for the parameters
+pub fn inline<'a>(expr: Expr<'a>, functions: &Functions<'a>) -> Result<Expr<'a>, &'static str> {
+    let mut inline = Inline {
+        functions,
+        depth: 0,
+        error: None,
+    };
+    let expr = inline.fold_expr(expr);
+    match inline.error {
+        Some(err) => Err(err),
+        None => Ok(expr),
+    }
+}
+
+struct Inline<'f, 'a> {
+    functions: &'f Functions<'a>,
+    depth: usize,
+    error: Option<&'static str>,
+}
+
+impl<'a> Fold<'a> for Inline<'_, 'a> {
+    fn fold_call(&mut self, name: Token<'a>, args: Vec<Expr<'a>>) -> Expr<'a> {
+        let args: Vec<Expr<'a>> = args.into_iter().map(|arg| self.fold_expr(arg)).collect();
+
+        let function = match self.functions.get(name.lexeme) {
+            Some(function) if name.kind == TokenKind::Function => function,
+            _ => return Expr::Call { name, args },
+        };
+
+        if function.params.len() != args.len() {
+            self.error.get_or_insert("wrong number of arguments");
+            return Expr::Call { name, args };
+        }
+        if self.depth == MAX_INLINE_DEPTH {
+            self.error
+                .get_or_insert("recursive functions aren't supported");
+            return Expr::Call { name, args };
+        }
+
+        let bindings: Bindings<'a> = function.params.iter().copied().zip(args).collect();
+        self.depth += 1;
+        let body = self.fold_expr(substitute(&function.body, &bindings));
+        self.depth -= 1;
+        body
+    }
+}
@@ -0,0 +1,350 @@
+use super::ast::*;
+use super::command;
+use super::eval::{self, Value};
+use super::function::{self, Functions};
+use super::parse::{self, Statement};
+use super::scan::*;
+
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::{Hint, Hinter};
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::Context;
+
+use std::borrow::Cow::{self, Borrowed, Owned};
+use std::collections::BTreeMap;
+
+// colors tokens, hints the value of the line so far, and keeps reading lines
+// while brackets are open. the repl copies its functions and variables in
+// after every line.
+#[derive(Default)]
+pub struct Helper {
+    pub functions: Functions<'static>,
+    pub variables: BTreeMap<String, Expr<'static>>,
+}
+
+impl Helper {
+    fn scanner<'a>(&self, line: &'a str) -> Scanner<'a> {
+        Scanner::with_functions(line, self.functions.keys().cloned().collect())
+    }
+
+    // the value of a plain expression, if it has one. this runs on every
+    // keystroke, so anything that can take a while, like expand, sigma or
+    // integrate, isn't hinted.
+    pub fn value(&self, line: &str) -> Option<Value> {
+        let expr = match parse::parse_statement(&mut self.scanner(line)) {
+            Ok(Statement::Expr(expr)) => expr,
+            _ => return None,
+        };
+        let expr = crate::bind(
+            &function::inline(expr, &self.functions).ok()?,
+            &self.variables,
+        );
+        let mut quick = Quick(true);
+        quick.visit_expr(&expr);
+        if !quick.0 {
+            return None;
+        }
+        eval::eval(&expr, &eval::Env::new()).ok()
+    }
+}
+
+// false if there's a call to anything but the elementary functions
+struct Quick(bool);
+
+impl<'a> Visitor<'a> for Quick {
+    fn visit_call(&mut self, name: &Token<'a>, args: &[Expr<'a>]) {
+        let elementary = matches!(
+            name.lexeme,
+            "sin" | "cos" | "tan" | "csc" | "sec" | "cot" | "arctan" | "ln" | "log" | "sqrt"
+        );
+        if !elementary {
+            self.0 = false;
+        }
+        for arg in args.iter() {
+            self.visit_expr(arg);
+        }
+    }
+}
+
+fn is_command(line: &str) -> bool {
+    line.trim_start().starts_with(':')
+}
+
+fn color(kind: TokenKind) -> Option<&'static str> {
+    use TokenKind::*;
+    match kind {
+        Integer(_) | Float(_) => Some("33"),
+        BuiltinFunction => Some("36"),
+        Function => Some("95"),
+        SpecialVariable => Some("32"),
+        If | Otherwise | And | Or | Not => Some("35"),
+        Minus | Plus | Multiply | Divide | Power | Modulo | Equal | Factorial | Less | Greater
+        | LessEqual | GreaterEqual | NotEqual | Partial | Assign => Some("1"),
+        _ => None,
+    }
+}
+
+// where the bracket matching the one at or just before the cursor is
+pub fn matching_bracket(line: &str, pos: usize) -> Option<usize> {
+    let bytes = line.as_bytes();
+    let is_bracket = |i: usize| bytes.get(i).is_some_and(|c| b"()[]{}".contains(c));
+    let at = if is_bracket(pos) {
+        pos
+    } else if pos > 0 && is_bracket(pos - 1) {
+        pos - 1
+    } else {
+        return None;
+    };
+
+    let (open, close, forward) = match bytes[at] {
+        b'(' => (b'(', b')', true),
+        b'[' => (b'[', b']', true),
+        b'{' => (b'{', b'}', true),
+        b')' => (b'(', b')', false),
+        b']' => (b'[', b']', false),
+        _ => (b'{', b'}', false),
+    };
+    let mut depth = 0;
+    let mut i = at;
+    loop {
+        match bytes[i] {
+            c if c == open => depth += if forward { 1 } else { -1 },
+            c if c == close => depth += if forward { -1 } else { 1 },
+            _ => {}
+        }
+        if depth == 0 {
+            return Some(i);
+        }
+        if forward {
+            i += 1;
+            if i == bytes.len() {
+                return None;
+            }
+        } else {
+            i = i.checked_sub(1)?;
+        }
+    }
+}
+
+// more brackets opened than closed outside comments, so there's more to type
+pub fn unclosed(input: &str) -> bool {
+    let mut depth = 0;
+    for c in input.lines().flat_map(|line| strip_comment(line).chars()) {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth -= 1,
+            _ => {}
+        }
+    }
+    depth > 0
+}
+
+impl Highlighter for Helper {
+    fn highlight<'l>(&self, line: &'l str, pos: usize) -> Cow<'l, str> {
+        if is_command(line) {
+            return Borrowed(line);
+        }
+        let tokens = match self.scanner(line).scan_all() {
+            Ok(tokens) => tokens,
+            Err(_) => return Borrowed(line),
+        };
+
+        let bracket = matching_bracket(line, pos);
+        let mut out = String::with_capacity(line.len() * 2);
+        let mut at = 0;
+        for token in tokens.iter().filter(|token| !token.lexeme.is_empty()) {
+            let start = match line[at..].find(token.lexeme) {
+                Some(start) => at + start,
+                None => break,
+            };
+            out.push_str(&line[at..start]);
+            let color = if bracket == Some(start) {
+                Some("1;34")
+            } else {
+                color(token.kind)
+            };
+            match color {
+                Some(color) => out.push_str(&format!("\x1b[{}m{}\x1b[0m", color, token.lexeme)),
+                None => out.push_str(token.lexeme),
+            }
+            at = start + token.lexeme.len();
+        }
+        out.push_str(&line[at..]);
+        Owned(out)
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Owned(format!("\x1b[2m{}\x1b[0m", hint))
+    }
+
+    // colors depend on the whole line, so every change redraws it
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        true
+    }
+}
+
+// shown after the line but never inserted into it
+pub struct ValueHint(String);
+
+impl Hint for ValueHint {
+    fn display(&self) -> &str {
+        &self.0
+    }
+
+    fn completion(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Hinter for Helper {
+    type Hint = ValueHint;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<ValueHint> {
+        if pos < line.len() || line.trim().is_empty() || is_command(line) {
+            return None;
+        }
+        match self.value(line)? {
+            Value::Matrix(_) => None,
+            value => Some(ValueHint(format!(" = {}", value))),
+        }
+    }
+}
+
+impl Validator for Helper {
+    fn validate(&self, ctx: &mut ValidationContext) -> rustyline::Result<ValidationResult> {
+        if !is_command(ctx.input()) && unclosed(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+// a line about each builtin for the completion list
+fn describe(name: &str) -> &'static str {
+    match name {
+        "sin" => "sine",
+        "cos" => "cosine",
+        "tan" => "tangent",
+        "csc" => "cosecant",
+        "sec" => "secant",
+        "cot" => "cotangent",
+        "arctan" => "inverse tangent",
+        "sigma" => "sum, sigma(i=0, 100, i^2)",
+        "ln" => "natural log",
+        "log" => "log base 10",
+        "sqrt" => "square root",
+        "expand" => "multiply out products and powers",
+        "factor" => "factor a polynomial",
+        "solve" => "roots of an equation, solve(expr, x)",
+        "csolve" => "solve with complex roots too",
+        "diff" => "derivative, diff(expr, x)",
+        "transpose" => "matrix transpose",
+        "det" => "matrix determinant",
+        "inverse" => "matrix inverse",
+        "rank" => "matrix rank",
+        "eigenvalues" => "eigenvalues of a symmetric matrix",
+        "root" => "a root found numerically, root(expr, x, a, b) or root(expr, x, guess)",
+        "integrate" | "int" | "∫" => "integral, integrate(expr, x) or int(expr dx)",
+        "limit" => "limit(expr, x, a), left or right for one side",
+        "series" => "taylor series, series(expr, x, a, n)",
+        "grad" => "gradient, grad(f, [x, y])",
+        "jacobian" => "jacobian([f, g], [x, y])",
+        "hessian" => "hessian(f, [x, y])",
+        "theta" | "dx" | "dy" | "dtheta" => "special variable",
+        "if" | "otherwise" => "piecewise, {x if x > 0; -x otherwise}",
+        _ => "condition",
+    }
+}
+
+const CONSTANTS: [(&str, &str); 3] = [
+    ("pi", "3.14159..."),
+    ("e", "2.71828..."),
+    ("inf", "infinity"),
+];
+
+fn pair(name: &str, description: &str, replacement: String) -> Pair {
+    Pair {
+        display: format!("{:<12} {}", name, description),
+        replacement,
+    }
+}
+
+impl Helper {
+    // everything that could finish `word`, sorted by name
+    pub fn candidates(&self, word: &str, command: bool) -> Vec<Pair> {
+        if command {
+            return command::COMMANDS
+                .iter()
+                .filter(|(name, _)| name.starts_with(word))
+                .map(|(name, description)| pair(name, description, name.to_string()))
+                .collect();
+        }
+
+        let mut candidates = Vec::new();
+        for (name, kind) in KEYWORDS.iter().filter(|(name, _)| name.starts_with(word)) {
+            let replacement = match kind {
+                TokenKind::BuiltinFunction => format!("{}(", name),
+                _ => name.to_string(),
+            };
+            candidates.push(pair(name, describe(name), replacement));
+        }
+        for (name, description) in CONSTANTS.iter().filter(|(name, _)| name.starts_with(word)) {
+            candidates.push(pair(name, description, name.to_string()));
+        }
+        for (name, function) in self.functions.iter() {
+            if name.starts_with(word) {
+                candidates.push(pair(
+                    &function.signature(name),
+                    &format!("= {}", to_infix(&function.body).trim()),
+                    format!("{}(", name),
+                ));
+            }
+        }
+        for (name, value) in self.variables.iter() {
+            if name.starts_with(word) {
+                candidates.push(pair(
+                    name,
+                    &format!("= {}", to_infix(value).trim()),
+                    name.clone(),
+                ));
+            }
+        }
+        candidates.sort_by(|a, b| a.display.cmp(&b.display));
+        candidates
+    }
+}
+
+impl Completer for Helper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| c.is_alphanumeric() || *c == '_' || *c == '∫')
+            .last()
+            .map_or(pos, |(i, _)| i);
+        // 2pi is 2 times pi
+        let start = pos
+            - line[start..pos]
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        let command = line[..start].trim_start() == ":";
+        let start = if command { start - 1 } else { start };
+
+        let word = &line[start..pos];
+        if word.is_empty() {
+            return Ok((pos, Vec::new()));
+        }
+        Ok((start, self.candidates(word, command)))
+    }
+}
+
+impl rustyline::Helper for Helper {}
@@ -0,0 +1,590 @@
+use super::ast::*;
+use super::diff::{call, depends_on, difference, negate, power, product, quotient, sum};
+use super::factor::{self, Dense};
+use super::poly::*;
+use super::rational::Rational;
+use super::rewrite::{self, Bindings};
+use super::scan::*;
+use super::solve;
+
+// the antiderivative plus a constant C, or an error when none of the rules
+// apply. logarithms are written ln(u) rather than ln(|u|).
+pub fn integrate<'a>(expr: &Expr<'a>, var: &Expr<'a>) -> Result<Expr<'a>, &'static str> {
+    match antiderivative(expr, var) {
+        Some(result) => Ok(binary(
+            result,
+            TokenKind::Plus,
+            "+",
+            Expr::Variable {
+                name: Token::new(TokenKind::Variable, "C"),
+            },
+        )),
+        None => Err("couldn't integrate that, it may have no elementary antiderivative"),
+    }
+}
+
+pub fn antiderivative<'a>(expr: &Expr<'a>, var: &Expr<'a>) -> Option<Expr<'a>> {
+    let x = name(var);
+    if !depends_on(expr, x) {
+        return Some(product(expr.clone(), var.clone()));
+    }
+
+    match expr {
+        Expr::Variable { .. } | Expr::SpecialVariable { .. } => polynomial(expr, var),
+
+        Expr::Unary { op, rhs } if op.kind == TokenKind::Minus => {
+            Some(negate(antiderivative(rhs, var)?))
+        }
+
+        Expr::Binary { lhs, op, rhs } => match op.kind {
+            TokenKind::Plus | TokenKind::Minus if polynomial(expr, var).is_some() => {
+                polynomial(expr, var)
+            }
+            TokenKind::Plus => Some(sum(antiderivative(lhs, var)?, antiderivative(rhs, var)?)),
+            TokenKind::Minus => Some(difference(
+                antiderivative(lhs, var)?,
+                antiderivative(rhs, var)?,
+            )),
+
+            TokenKind::Multiply if !depends_on(lhs, x) => {
+                constant_multiple(lhs, antiderivative(rhs, var)?)
+            }
+            TokenKind::Multiply if !depends_on(rhs, x) => {
+                constant_multiple(rhs, antiderivative(lhs, var)?)
+            }
+            TokenKind::Multiply => substitution(expr, var)
+                .or_else(|| polynomial(expr, var))
+                .or_else(|| by_parts(lhs, rhs, var))
+                .or_else(|| by_parts(rhs, lhs, var)),
+
+            TokenKind::Divide if !depends_on(rhs, x) => {
+                Some(quotient(antiderivative(lhs, var)?, rhs.as_ref().clone()))
+            }
+            TokenKind::Divide => rational_function(lhs, rhs, var)
+                .or_else(|| logarithmic(lhs, rhs, var))
+                .or_else(|| substitution(expr, var)),
+
+            TokenKind::Power => power_rule(lhs, rhs, var)
+                .or_else(|| substitution(expr, var))
+                .or_else(|| polynomial(expr, var)),
+
+            _ => None,
+        },
+
+        Expr::Call { name, args } if name.kind == TokenKind::BuiltinFunction => {
+            match args.as_slice() {
+                [u] => elementary(name.lexeme, u, var).or_else(|| substitution(expr, var)),
+                _ => None,
+            }
+        }
+
+        _ => None,
+    }
+}
+
+fn name<'a>(var: &Expr<'a>) -> &'a str {
+    match var {
+        Expr::Variable { name } | Expr::SpecialVariable { name } => name.lexeme,
+        _ => "",
+    }
+}
+
+struct FindVariable<'a, 'n> {
+    name: &'n str,
+    found: Option<Expr<'a>>,
+}
+
+impl<'a> Visitor<'a> for FindVariable<'a, '_> {
+    fn visit_variable(&mut self, name: &Token<'a>) {
+        if name.lexeme == self.name {
+            self.found.get_or_insert(Expr::Variable { name: *name });
+        }
+    }
+
+    fn visit_special_variable(&mut self, name: &Token<'a>) {
+        if name.lexeme == self.name {
+            self.found
+                .get_or_insert(Expr::SpecialVariable { name: *name });
+        }
+    }
+}
+
+// the variable called `name` as it's written in `expr`, since theta is a
+// special variable but x isn't
+pub fn variable<'a>(expr: &Expr<'a>, name: &'a str) -> Expr<'a> {
+    let mut find = FindVariable { name, found: None };
+    find.visit_expr(expr);
+    find.found.unwrap_or(Expr::Variable {
+        name: Token::new(TokenKind::Variable, name),
+    })
+}
+
+// the constant factor on the outside of expr and what's left. none if the
+// constant is too big to multiply out.
+fn split_constant(expr: Expr<'_>) -> Option<(Rational, Expr<'_>)> {
+    let constant = |expr: &Expr<'_>| {
+        Poly::from_expr(expr)
+            .ok()?
+            .as_constant()
+            .filter(|c| !c.is_zero())
+    };
+    match expr {
+        Expr::Unary { op, rhs } if op.kind == TokenKind::Minus => {
+            let (c, rest) = split_constant(*rhs)?;
+            Some((-c, rest))
+        }
+        Expr::Binary { lhs, op, rhs }
+            if op.kind == TokenKind::Divide && constant(&rhs).is_some() =>
+        {
+            let (c, rest) = split_constant(*lhs)?;
+            Some((c.checked_div(constant(&rhs)?).ok()?, rest))
+        }
+        Expr::Binary { lhs, op, rhs }
+            if op.kind == TokenKind::Multiply && constant(&lhs).is_some() =>
+        {
+            let (c, rest) = split_constant(*rhs)?;
+            Some((c.checked_mul(constant(&lhs)?).ok()?, rest))
+        }
+        _ => Some((Rational::ONE, expr)),
+    }
+}
+
+fn scale(by: Rational, expr: Expr<'_>) -> Option<Expr<'_>> {
+    if by == Rational::ONE {
+        return Some(expr);
+    }
+    let (c, rest) = split_constant(expr)?;
+    Some(
+        Poly::atom(rest)
+            .scale(by.checked_mul(c).ok()?)
+            .ok()?
+            .to_expr(),
+    )
+}
+
+fn constant_multiple<'a>(constant: &Expr<'a>, expr: Expr<'a>) -> Option<Expr<'a>> {
+    match Poly::from_expr(constant).ok()?.as_constant() {
+        Some(c) => scale(c, expr),
+        None => Some(product(constant.clone(), expr)),
+    }
+}
+
+// a, if u is a x + b for a constant a
+fn linear(u: &Expr<'_>, var: &Expr<'_>) -> Option<Rational> {
+    match Poly::from_expr(u)
+        .ok()?
+        .coefficients_in(name(var))?
+        .as_slice()
+    {
+        [_, a] => a.as_constant().filter(|a| !a.is_zero()),
+        _ => None,
+    }
+}
+
+// k where a = k b
+fn ratio(a: &Poly<'_>, b: &Poly<'_>) -> Option<Rational> {
+    let (monomial, coefficient) = b.terms.iter().next()?;
+    let k = a.terms.get(monomial)?.checked_div(*coefficient).ok()?;
+    (*a == b.scale(k).ok()?).then_some(k)
+}
+
+// term by term, the coefficients can involve other variables
+fn polynomial<'a>(expr: &Expr<'a>, var: &Expr<'a>) -> Option<Expr<'a>> {
+    let coefficients = Poly::from_expr(expr).ok()?.coefficients_in(name(var))?;
+    let x = Poly::atom(var.clone());
+    let mut result = Poly::zero();
+    for (degree, coefficient) in coefficients.iter().enumerate() {
+        let term = coefficient.mul(&x.pow(degree as u32 + 1).ok()?).ok()?;
+        let term = term
+            .scale(Rational::integer(degree as i128 + 1).recip())
+            .ok()?;
+        result = result.add(&term).ok()?;
+    }
+    Some(result.to_expr())
+}
+
+// f(u) for one of the builtins, where u is linear
+fn elementary<'a>(function: &str, u: &Expr<'a>, var: &Expr<'a>) -> Option<Expr<'a>> {
+    let a = linear(u, var)?;
+    let u = u.clone();
+    let result = match function {
+        "sin" => negate(call("cos", u)),
+        "cos" => call("sin", u),
+        "tan" => negate(call("ln", call("cos", u))),
+        "cot" => call("ln", call("sin", u)),
+        "sec" => call("ln", sum(call("sec", u.clone()), call("tan", u))),
+        "csc" => negate(call("ln", sum(call("csc", u.clone()), call("cot", u)))),
+        "ln" => difference(product(u.clone(), call("ln", u.clone())), u),
+        "log" => quotient(
+            difference(product(u.clone(), call("ln", u.clone())), u),
+            call("ln", integer(10)),
+        ),
+        "sqrt" => scale(
+            Rational::new(2, 3),
+            power(u, quotient(integer(3), integer(2))),
+        )?,
+        _ => return None,
+    };
+    scale(a.recip(), result)
+}
+
+// u^n and b^u for linear u, and the squares of sin, cos, sec and csc
+fn power_rule<'a>(base: &Expr<'a>, exponent: &Expr<'a>, var: &Expr<'a>) -> Option<Expr<'a>> {
+    let x = name(var);
+
+    if !depends_on(exponent, x) {
+        let n = Poly::from_expr(exponent).ok()?.as_constant();
+        if let (Expr::Call { name, args }, Some(n)) = (base, n) {
+            if let ([u], true) = (args.as_slice(), n == Rational::integer(2)) {
+                let a = linear(u, var)?;
+                // sin(u)^2 = (1 - cos(2u)) / 2, cos(u)^2 = (1 + cos(2u)) / 2
+                let half = quotient(u.clone(), integer(2));
+                let double = quotient(call("sin", product(integer(2), u.clone())), integer(4));
+                let result = match name.lexeme {
+                    "sin" => difference(half, double),
+                    "cos" => sum(half, double),
+                    "sec" => call("tan", u.clone()),
+                    "csc" => negate(call("cot", u.clone())),
+                    _ => return None,
+                };
+                return scale(a.recip(), result);
+            }
+        }
+
+        let a = linear(base, var)?;
+        let result = match n {
+            Some(n) if n == -Rational::ONE => call("ln", base.clone()),
+            Some(n) => {
+                let next = n.checked_add(Rational::ONE).ok()?;
+                scale(
+                    next.recip(),
+                    power(base.clone(), Poly::constant(next).to_expr()),
+                )?
+            }
+            None => {
+                let next = sum(exponent.clone(), integer(1));
+                quotient(power(base.clone(), next.clone()), next)
+            }
+        };
+        return scale(a.recip(), result);
+    }
+
+    if !depends_on(base, x) {
+        let a = linear(exponent, var)?;
+        let this = power(base.clone(), exponent.clone());
+        let result = match base {
+            Expr::Variable { name } if name.lexeme == "e" => this,
+            _ => quotient(this, call("ln", base.clone())),
+        };
+        return scale(a.recip(), result);
+    }
+
+    None
+}
+
+// integration by parts for a polynomial times something we can integrate,
+// which lowers the degree each time, or a polynomial times a logarithm
+fn by_parts<'a>(p: &Expr<'a>, other: &Expr<'a>, var: &Expr<'a>) -> Option<Expr<'a>> {
+    let x = name(var);
+    if Poly::from_expr(p).ok()?.coefficients_in(x)?.len() < 2 {
+        return None;
+    }
+
+    match other {
+        // P ln(u) - integral of P a / u
+        Expr::Call { name, args } if name.lexeme == "ln" => {
+            let u = args.first()?;
+            let a = linear(u, var)?;
+            let big_p = polynomial(p, var)?;
+            let rest = antiderivative(&quotient(scale(a, big_p.clone())?, u.clone()), var)?;
+            Some(difference(product(big_p, other.clone()), rest))
+        }
+
+        // p G - integral of p' G
+        Expr::Call { .. } | Expr::Binary { .. } => {
+            let g = antiderivative(other, var)?;
+            let rest = antiderivative(&product(p.derivative(x).ok()?, g.clone()), var)?;
+            Some(difference(product(p.clone(), g), rest))
+        }
+
+        _ => None,
+    }
+}
+
+// the factors of a product, with a denominator as a negative power
+fn factors<'a>(expr: &Expr<'a>, out: &mut Vec<Expr<'a>>) {
+    match expr {
+        Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Multiply => {
+            factors(lhs, out);
+            factors(rhs, out);
+        }
+        Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Divide => {
+            factors(lhs, out);
+            out.push(match rhs.as_ref() {
+                Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Power => {
+                    power(lhs.as_ref().clone(), negate(rhs.as_ref().clone()))
+                }
+                _ => power(rhs.as_ref().clone(), negate(integer(1))),
+            });
+        }
+        _ => out.push(expr.clone()),
+    }
+}
+
+// u substitution: a factor f(g) where the rest of the product is a constant
+// times g' integrates to F(g). a function of x alone is g itself, with f(u) = u.
+fn substitution<'a>(expr: &Expr<'a>, var: &Expr<'a>) -> Option<Expr<'a>> {
+    let x = name(var);
+    let u = Expr::Variable {
+        name: Token::new(TokenKind::Variable, "_u"),
+    };
+
+    let mut all = Vec::new();
+    factors(expr, &mut all);
+    for (i, factor) in all.iter().enumerate() {
+        let (inner, outer) = match factor {
+            Expr::Call { args, .. } if args.len() == 1 && linear(&args[0], var).is_some() => {
+                (factor, u.clone())
+            }
+            Expr::Call { name, args } if args.len() == 1 => (
+                &args[0],
+                Expr::Call {
+                    name: *name,
+                    args: vec![u.clone()],
+                },
+            ),
+            Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Power => {
+                if !depends_on(rhs, x) {
+                    (lhs.as_ref(), power(u.clone(), rhs.as_ref().clone()))
+                } else if !depends_on(lhs, x) {
+                    (rhs.as_ref(), power(lhs.as_ref().clone(), u.clone()))
+                } else {
+                    continue;
+                }
+            }
+            _ => continue,
+        };
+        if linear(inner, var).is_some() {
+            continue;
+        }
+
+        let rest = all
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .fold(integer(1), |rest, (_, factor)| {
+                product(rest, factor.clone())
+            });
+        let derivative = match inner.derivative(x) {
+            Ok(derivative) => derivative,
+            Err(_) => continue,
+        };
+        let k = match (Poly::from_expr(&rest), Poly::from_expr(&derivative)) {
+            (Ok(rest), Ok(derivative)) => match ratio(&rest, &derivative) {
+                Some(k) => k,
+                None => continue,
+            },
+            _ => continue,
+        };
+
+        let outer = antiderivative(&outer, &u)?;
+        let mut bindings = Bindings::new();
+        bindings.insert("_u", inner.clone());
+        return scale(k, rewrite::substitute(&outer, &bindings));
+    }
+
+    None
+}
+
+// k u' / u is k ln(u)
+fn logarithmic<'a>(
+    numerator: &Expr<'a>,
+    denominator: &Expr<'a>,
+    var: &Expr<'a>,
+) -> Option<Expr<'a>> {
+    let derivative = denominator.derivative(name(var)).ok()?;
+    let k = ratio(
+        &Poly::from_expr(numerator).ok()?,
+        &Poly::from_expr(&derivative).ok()?,
+    )?;
+    scale(k, call("ln", denominator.clone()))
+}
+
+fn dense(expr: &Expr<'_>, var: &Expr<'_>) -> Option<Dense> {
+    Poly::from_expr(expr)
+        .ok()?
+        .coefficients_in(name(var))?
+        .iter()
+        .map(|coefficient| coefficient.as_constant())
+        .collect::<Option<Dense>>()
+        .map(factor::trim)
+}
+
+// the first n coefficients of p(r + t)
+fn taylor(p: &[Rational], r: Rational, n: usize) -> Result<Dense, &'static str> {
+    let mut p = p.to_vec();
+    let mut factorial = Rational::ONE;
+    let mut coefficients = Vec::new();
+    for k in 0..n {
+        if k > 0 {
+            p = factor::derivative(&p)?;
+            factorial = factorial.checked_mul(Rational::integer(k as i128))?;
+        }
+        coefficients.push(factor::eval(&p, r)?.checked_div(factorial)?);
+    }
+    Ok(coefficients)
+}
+
+// polynomial division, then partial fractions over the rational roots of the
+// denominator, or arctan when it's an irreducible quadratic
+fn rational_function<'a>(
+    numerator: &Expr<'a>,
+    denominator: &Expr<'a>,
+    var: &Expr<'a>,
+) -> Option<Expr<'a>> {
+    let p = dense(numerator, var)?;
+    let q = dense(denominator, var)?;
+    if factor::degree(&q) == 0 {
+        return None;
+    }
+
+    let mut roots = Vec::new();
+    let mut irreducible = false;
+    for (f, multiplicity) in factor::factor_dense(&factor::primitive(&q).ok()?).ok()? {
+        if factor::degree(&f) == 2 && factor::degree(&q) == 2 {
+            irreducible = true;
+            continue;
+        }
+        if factor::degree(&f) != 1 {
+            return None;
+        }
+        roots.push(((-f[0]).checked_div(f[1]).ok()?, multiplicity as usize));
+    }
+
+    // p / q = whole + remainder / q, and q = lead (x - r1)^m1 (x - r2)^m2 ...
+    let (whole, remainder) = factor::divmod(&p, &q).ok()?;
+    let remainder = factor::scale(&remainder, q[q.len() - 1].recip()).ok()?;
+
+    let atom = Atom::new(var.clone());
+    let mut integral = vec![Rational::ZERO];
+    integral.extend(
+        whole
+            .iter()
+            .enumerate()
+            .map(|(degree, c)| c.checked_div(Rational::integer(degree as i128 + 1)))
+            .collect::<Result<Dense, _>>()
+            .ok()?,
+    );
+    let mut result = Poly::from_dense(&atom, &integral);
+    if irreducible {
+        let monic = factor::scale(&q, q[2].recip()).ok()?;
+        let rest = arctangent(&remainder, &monic, &atom)?;
+        return Some(result.add(&rest).ok()?.to_expr());
+    }
+
+    for (i, (root, multiplicity)) in roots.iter().enumerate() {
+        let mut others = vec![Rational::ONE];
+        for (j, (other, m)) in roots.iter().enumerate() {
+            for _ in 0..(if i == j { 0 } else { *m }) {
+                others = factor::mul(&others, &[-*other, Rational::ONE]).ok()?;
+            }
+        }
+
+        // remainder / others around the root, divided as power series. the
+        // kth coefficient goes with 1 / (x - root)^(multiplicity - k).
+        let n = taylor(&remainder, *root, *multiplicity).ok()?;
+        let d = taylor(&others, *root, *multiplicity).ok()?;
+        let mut coefficients: Dense = Vec::new();
+        for k in 0..*multiplicity {
+            let mut c = n[k];
+            for j in 1..=k {
+                c = c
+                    .checked_sub(d[j].checked_mul(coefficients[k - j]).ok()?)
+                    .ok()?;
+            }
+            coefficients.push(c.checked_div(d[0]).ok()?);
+        }
+
+        let shifted = Poly::atom(var.clone())
+            .sub(&Poly::constant(*root))
+            .ok()?
+            .to_expr();
+        for (k, c) in coefficients.iter().enumerate() {
+            let exp = multiplicity - k;
+            if c.is_zero() {
+                continue;
+            }
+            let term = if exp == 1 {
+                Poly::atom(call("ln", shifted.clone())).scale(*c).ok()?
+            } else {
+                let exp = Rational::integer(exp as i128 - 1);
+                Poly::atom(quotient(
+                    integer(1),
+                    power(shifted.clone(), Poly::constant(exp).to_expr()),
+                ))
+                .scale((-*c).checked_div(exp).ok()?)
+                .ok()?
+            };
+            result = result.add(&term).ok()?;
+        }
+    }
+
+    Some(result.to_expr())
+}
+
+// (m x + n) / (x^2 + b x + c) with no real roots, which is m/2 ln of the
+// denominator plus a multiple of arctan((2x + b) / sqrt(4c - b^2))
+fn arctangent<'a>(p: &[Rational], q: &[Rational], atom: &Atom<'a>) -> Option<Poly<'a>> {
+    let n = p.first().copied().unwrap_or(Rational::ZERO);
+    let m = p.get(1).copied().unwrap_or(Rational::ZERO);
+    let (c, b) = (q[0], q[1]);
+    let d = Rational::integer(4)
+        .checked_mul(c)
+        .ok()?
+        .checked_sub(b.checked_mul(b).ok()?)
+        .ok()?;
+    if d.is_negative() || d.is_zero() {
+        return None;
+    }
+
+    // sqrt(d) = k sqrt(s), with sqrt(n/d) = sqrt(n*d)/d
+    let (k, s) = solve::square_part(d.num().checked_mul(d.den())?);
+    let k = Rational::new(k, d.den());
+    let surd = |expr| match s {
+        1 => expr,
+        _ => quotient(expr, call("sqrt", integer(s))),
+    };
+
+    let x = Poly::from_dense(atom, &[Rational::ZERO, Rational::ONE]);
+    let inner = x
+        .scale(Rational::integer(2))
+        .ok()?
+        .add(&Poly::constant(b))
+        .ok()?
+        .scale(k.recip())
+        .ok()?;
+    // n - m b / 2 over the whole sqrt(d) / 2
+    let half_b = b.checked_div(Rational::integer(2)).ok()?;
+    let coefficient = n
+        .checked_sub(m.checked_mul(half_b).ok()?)
+        .ok()?
+        .checked_mul(Rational::integer(2))
+        .ok()?
+        .checked_div(k)
+        .ok()?;
+
+    let mut result = Poly::zero();
+    if !m.is_zero() {
+        let log = call("ln", Poly::from_dense(atom, q).to_expr());
+        result = Poly::atom(log)
+            .scale(m.checked_div(Rational::integer(2)).ok()?)
+            .ok()?;
+    }
+    if !coefficient.is_zero() {
+        let arctan = surd(call("arctan", surd(inner.to_expr())));
+        result = result
+            .add(&Poly::atom(arctan).scale(coefficient).ok()?)
+            .ok()?;
+    }
+    Some(result)
+}
@@ -0,0 +1,307 @@
+use super::ast::*;
+use super::poly::*;
+use super::scan::*;
+use super::solve::{self, Solutions};
+
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct Endpoint<'a> {
+    pub value: f64,
+    pub exact: Option<Expr<'a>>,
+}
+
+impl<'a> Endpoint<'a> {
+    pub fn new(value: f64) -> Endpoint<'a> {
+        Endpoint { value, exact: None }
+    }
+}
+
+impl fmt::Display for Endpoint<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.exact {
+            _ if self.value == f64::INFINITY => write!(f, "∞"),
+            _ if self.value == f64::NEG_INFINITY => write!(f, "-∞"),
+            Some(exact) => write!(f, "{}", to_infix(exact).trim()),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Interval<'a> {
+    pub lo: Endpoint<'a>,
+    pub lo_closed: bool,
+    pub hi: Endpoint<'a>,
+    pub hi_closed: bool,
+}
+
+impl Interval<'_> {
+    fn is_point(&self) -> bool {
+        self.lo.value == self.hi.value
+    }
+}
+
+impl fmt::Display for Interval<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_point() {
+            return write!(f, "{{{}}}", self.lo);
+        }
+        write!(
+            f,
+            "{}{}, {}{}",
+            if self.lo_closed { "[" } else { "(" },
+            self.lo,
+            self.hi,
+            if self.hi_closed { "]" } else { ")" }
+        )
+    }
+}
+
+// sorted, disjoint intervals on the real line
+#[derive(Debug, Clone, Default)]
+pub struct IntervalSet<'a> {
+    pub intervals: Vec<Interval<'a>>,
+}
+
+impl<'a> IntervalSet<'a> {
+    // the real line cut at `points` is made of the points themselves and the
+    // open gaps around them, gap i being just before point i. glue together
+    // the pieces that are in the set.
+    fn from_pieces(
+        points: Vec<Endpoint<'a>>,
+        point_in: &[bool],
+        gap_in: &[bool],
+    ) -> IntervalSet<'a> {
+        let mut intervals = Vec::new();
+        let mut start = if gap_in[0] {
+            Some((Endpoint::new(f64::NEG_INFINITY), false))
+        } else {
+            None
+        };
+
+        for (i, point) in points.into_iter().enumerate() {
+            let next_in = gap_in[i + 1];
+            match start.take() {
+                Some((lo, lo_closed)) => {
+                    if point_in[i] && next_in {
+                        start = Some((lo, lo_closed));
+                        continue;
+                    }
+                    intervals.push(Interval {
+                        lo,
+                        lo_closed,
+                        hi: point.clone(),
+                        hi_closed: point_in[i],
+                    });
+                    if next_in {
+                        start = Some((point, false));
+                    }
+                }
+                None => {
+                    if next_in {
+                        start = Some((point, point_in[i]));
+                    } else if point_in[i] {
+                        intervals.push(Interval {
+                            lo: point.clone(),
+                            lo_closed: true,
+                            hi: point,
+                            hi_closed: true,
+                        });
+                    }
+                }
+            }
+        }
+
+        if let Some((lo, lo_closed)) = start {
+            intervals.push(Interval {
+                lo,
+                lo_closed,
+                hi: Endpoint::new(f64::INFINITY),
+                hi_closed: false,
+            });
+        }
+
+        IntervalSet { intervals }
+    }
+
+    pub fn contains(&self, x: f64) -> bool {
+        self.intervals.iter().any(|interval| {
+            (interval.lo.value < x || (interval.lo_closed && interval.lo.value == x))
+                && (x < interval.hi.value || (interval.hi_closed && interval.hi.value == x))
+        })
+    }
+
+    pub fn union(&self, other: &IntervalSet<'a>) -> IntervalSet<'a> {
+        self.combine(other, |a, b| a || b)
+    }
+
+    pub fn intersect(&self, other: &IntervalSet<'a>) -> IntervalSet<'a> {
+        self.combine(other, |a, b| a && b)
+    }
+
+    pub fn complement(&self) -> IntervalSet<'a> {
+        self.combine(&IntervalSet::default(), |a, _| !a)
+    }
+
+    // cut the line at every endpoint of either set and keep the pieces where
+    // `keep` says so
+    fn combine(
+        &self,
+        other: &IntervalSet<'a>,
+        keep: impl Fn(bool, bool) -> bool,
+    ) -> IntervalSet<'a> {
+        let mut points: Vec<Endpoint<'a>> = self
+            .intervals
+            .iter()
+            .chain(other.intervals.iter())
+            .flat_map(|interval| vec![interval.lo.clone(), interval.hi.clone()])
+            .filter(|point| point.value.is_finite())
+            .collect();
+        points.sort_by(|a, b| {
+            a.value
+                .partial_cmp(&b.value)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        points.dedup_by(|a, b| a.value == b.value);
+
+        let values: Vec<f64> = points.iter().map(|point| point.value).collect();
+        let holds = |x: f64| keep(self.contains(x), other.contains(x));
+        let point_in: Vec<bool> = values.iter().map(|x| holds(*x)).collect();
+        let gap_in: Vec<bool> = samples(&values).into_iter().map(holds).collect();
+
+        IntervalSet::from_pieces(points, &point_in, &gap_in)
+    }
+
+    pub fn to_set_builder(&self, var: &str) -> String {
+        if self.intervals.is_empty() {
+            return "∅".to_string();
+        }
+
+        let conditions: Vec<String> = self
+            .intervals
+            .iter()
+            .map(|interval| {
+                let lo = if interval.lo_closed { "≤" } else { "<" };
+                let hi = if interval.hi_closed { "≤" } else { "<" };
+                match (interval.lo.value.is_finite(), interval.hi.value.is_finite()) {
+                    _ if interval.is_point() => format!("{} = {}", var, interval.lo),
+                    (false, false) => format!("{} ∈ ℝ", var),
+                    (false, true) => format!("{} {} {}", var, hi, interval.hi),
+                    (true, false) => format!("{} {} {}", interval.lo, lo, var),
+                    (true, true) => {
+                        format!("{} {} {} {} {}", interval.lo, lo, var, hi, interval.hi)
+                    }
+                }
+            })
+            .collect();
+
+        format!("{{{} | {}}}", var, conditions.join(" or "))
+    }
+}
+
+impl fmt::Display for IntervalSet<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.intervals.is_empty() {
+            return write!(f, "∅");
+        }
+
+        let intervals: Vec<String> = self.intervals.iter().map(|i| i.to_string()).collect();
+        write!(f, "{}", intervals.join(" ∪ "))
+    }
+}
+
+// one point inside each gap between sorted `points`, including the two
+// unbounded ones at the ends
+fn samples(points: &[f64]) -> Vec<f64> {
+    match (points.first(), points.last()) {
+        (Some(first), Some(last)) => {
+            let mut samples = vec![first - 1.0];
+            for pair in points.windows(2) {
+                samples.push((pair[0] + pair[1]) / 2.0);
+            }
+            samples.push(last + 1.0);
+            samples
+        }
+        _ => vec![0.0],
+    }
+}
+
+// solve inequalities joined with and, or and not, a < x < b being
+// a < x and x < b by the time it gets here
+pub fn solve_inequality<'a>(expr: &Expr<'a>, var: &str) -> Result<IntervalSet<'a>, &'static str> {
+    match expr {
+        Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::And => {
+            Ok(solve_inequality(lhs, var)?.intersect(&solve_inequality(rhs, var)?))
+        }
+        Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Or => {
+            Ok(solve_inequality(lhs, var)?.union(&solve_inequality(rhs, var)?))
+        }
+        Expr::Unary { op, rhs } if op.kind == TokenKind::Not => {
+            Ok(solve_inequality(rhs, var)?.complement())
+        }
+        Expr::Binary { lhs, op, rhs } if op.kind.is_comparison() => {
+            solve_comparison(lhs, op, rhs, var)
+        }
+        _ => Err("not an inequality"),
+    }
+}
+
+// solve a single polynomial comparison in `var` by finding where lhs - rhs
+// changes sign. only the sign of lhs - rhs between its roots is ever looked
+// at, so multiplying through by something negative can't flip the answer.
+fn solve_comparison<'a>(
+    lhs: &Expr<'a>,
+    op: &Token<'a>,
+    rhs: &Expr<'a>,
+    var: &str,
+) -> Result<IntervalSet<'a>, &'static str> {
+    let difference = Poly::from_expr(lhs)?.sub(&Poly::from_expr(rhs)?)?;
+    let coefficients: Vec<f64> = difference
+        .coefficients_in(var)
+        .ok_or(if difference.too_high_power(var) {
+            "the polynomial's degree is too high, it can be up to 64"
+        } else {
+            "can only solve polynomial inequalities"
+        })?
+        .iter()
+        .map(|c| c.as_constant().map(|c| c.to_f64()))
+        .collect::<Option<_>>()
+        .ok_or("can only solve inequalities with numeric coefficients")?;
+
+    let holds = |value: f64| match op.kind {
+        TokenKind::Less => value < 0.0,
+        TokenKind::LessEqual => value <= 0.0,
+        TokenKind::Greater => value > 0.0,
+        TokenKind::GreaterEqual => value >= 0.0,
+        TokenKind::Equal => value == 0.0,
+        _ => value != 0.0,
+    };
+
+    let mut points = Vec::new();
+    if coefficients.len() > 1 {
+        let equation = Expr::Binary {
+            lhs: Box::new(lhs.clone()),
+            op: Token::new(TokenKind::Equal, "="),
+            rhs: Box::new(rhs.clone()),
+        };
+        if let Solutions::Roots(roots) = solve::solve(&equation, var, false)? {
+            for root in roots {
+                points.push(Endpoint {
+                    value: root.re,
+                    exact: root.exact,
+                });
+            }
+        }
+    }
+    points.dedup_by(|a, b| a.value == b.value);
+
+    let values: Vec<f64> = points.iter().map(|point| point.value).collect();
+    let point_in: Vec<bool> = values.iter().map(|_| holds(0.0)).collect();
+    let gap_in: Vec<bool> = samples(&values)
+        .iter()
+        .map(|x| holds(coefficients.iter().rev().fold(0.0, |acc, c| acc * x + c)))
+        .collect();
+
+    Ok(IntervalSet::from_pieces(points, &point_in, &gap_in))
+}
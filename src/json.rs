@@ -0,0 +1,502 @@
+use super::ast::*;
+use super::eval::Value;
+use super::scan::*;
+
+use std::fmt;
+
+// json for other programs to read. with --format json every statement prints
+// one object on its own line:
+//
+//   {"input": "2x + 1", "tokens": [token], "expr": expr, "simplified": expr,
+//    "value": value}
+//     an expression. value is null when it can't be evaluated, like when it
+//     has unbound variables.
+//   {"input": "f(x) = x^2", "define": "f", "params": ["x"], "body": expr}
+//   {"input": "a := 3", "assign": "a", "value": value}
+//   {"input": "x + y = 1; x - y = 2", "equations": [expr]}
+//   {"input": "dy/dx = x y", "ode": {"y": "y", "x": "x", "rhs": expr}}
+//   {"error": "message", "location": "a.nums:3:5"}
+//     location is only there for scripts
+//
+// a token is {"kind": "Integer", "lexeme": "2"}, with kinds named like
+// TokenKind. an expr is an object tagged by its type:
+//
+//   {"type": "number", "value": 2}       whole numbers have no decimal point
+//   {"type": "variable", "name": "x"}
+//   {"type": "special", "name": "theta"}
+//   {"type": "unary", "op": "-", "rhs": expr}
+//   {"type": "binary", "op": "+", "lhs": expr, "rhs": expr}
+//   {"type": "call", "name": "sin", "args": [expr]}
+//   {"type": "piecewise", "branches": [{"value": expr, "guard": expr}]}
+//     guard is null for the otherwise branch
+//   {"type": "matrix", "rows": [[expr]]}
+//
+// a value is a number, a boolean, or {"rows": 2, "cols": 1, "data": [1, 2]}
+// for a matrix with the data row by row. numbers that aren't finite are null.
+// exprs can be read back with to_expr.
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Integer(u64),
+    Float(f64),
+    String(String),
+    Array(Vec<Json>),
+    // fields stay in the order they were written
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    pub fn string(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn field(&self, key: &str) -> Result<&Json, &'static str> {
+        self.get(key).ok_or("expr is missing a field")
+    }
+
+    fn str(&self, key: &str) -> Result<&str, &'static str> {
+        match self.field(key)? {
+            Json::String(s) => Ok(s),
+            _ => Err("expected a string in expr"),
+        }
+    }
+
+    fn array(&self, key: &str) -> Result<&[Json], &'static str> {
+        match self.field(key)? {
+            Json::Array(items) => Ok(items),
+            _ => Err("expected an array in expr"),
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Integer(i) => write!(f, "{}", i),
+            Json::Float(x) if x.is_finite() => write!(f, "{:?}", x),
+            Json::Float(_) => write!(f, "null"),
+            Json::String(s) => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"' => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\r' => write!(f, "\\r")?,
+                        '\t' => write!(f, "\\t")?,
+                        c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                        c => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", Json::string(key), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+pub fn parse(source: &str) -> Result<Json, &'static str> {
+    let mut parser = Parser {
+        source: source.as_bytes(),
+        current: 0,
+    };
+    let json = parser.value()?;
+    parser.whitespace();
+    if parser.current < parser.source.len() {
+        return Err("unexpected text after the json");
+    }
+    Ok(json)
+}
+
+struct Parser<'s> {
+    source: &'s [u8],
+    current: usize,
+}
+
+impl Parser<'_> {
+    fn whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+            self.current += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.source.get(self.current).copied()
+    }
+
+    fn eat(&mut self, c: u8) -> bool {
+        self.whitespace();
+        if self.peek() == Some(c) {
+            self.current += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), &'static str> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err("malformed json")
+        }
+    }
+
+    fn keyword(&mut self, word: &str, json: Json) -> Result<Json, &'static str> {
+        if self.source[self.current..].starts_with(word.as_bytes()) {
+            self.current += word.len();
+            Ok(json)
+        } else {
+            Err("malformed json")
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, &'static str> {
+        self.whitespace();
+        match self.peek().ok_or("unexpected end of json")? {
+            b'n' => self.keyword("null", Json::Null),
+            b't' => self.keyword("true", Json::Bool(true)),
+            b'f' => self.keyword("false", Json::Bool(false)),
+            b'"' => self.string().map(Json::String),
+            b'[' => {
+                self.current += 1;
+                let mut items = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.value()?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(Json::Array(items))
+            }
+            b'{' => {
+                self.current += 1;
+                let mut fields = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.whitespace();
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        fields.push((key, self.value()?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(Json::Object(fields))
+            }
+            b'-' | b'0'..=b'9' => self.number(),
+            _ => Err("malformed json"),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, &'static str> {
+        let start = self.current;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || b"+-.eE".contains(&c))
+        {
+            self.current += 1;
+        }
+        let text = std::str::from_utf8(&self.source[start..self.current]).unwrap();
+        if let Ok(i) = text.parse::<u64>() {
+            return Ok(Json::Integer(i));
+        }
+        text.parse::<f64>()
+            .map(Json::Float)
+            .map_err(|_| "malformed number in json")
+    }
+
+    fn string(&mut self) -> Result<String, &'static str> {
+        if self.peek() != Some(b'"') {
+            return Err("expected a string in json");
+        }
+        self.current += 1;
+
+        let mut bytes = Vec::new();
+        loop {
+            let c = self.peek().ok_or("unterminated string in json")?;
+            self.current += 1;
+            match c {
+                b'"' => break,
+                b'\\' => {
+                    let escape = self.peek().ok_or("unterminated string in json")?;
+                    self.current += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode()?,
+                        _ => return Err("unknown escape in json string"),
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                c => bytes.push(c),
+            }
+        }
+        String::from_utf8(bytes).map_err(|_| "invalid utf-8 in json")
+    }
+
+    // the hex after \u, and the second half of a surrogate pair
+    fn unicode(&mut self) -> Result<char, &'static str> {
+        let high = self.hex()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return std::char::from_u32(high).ok_or("malformed \\u escape in json");
+        }
+        if self.source.get(self.current..self.current + 2) != Some(b"\\u") {
+            return Err("malformed \\u escape in json");
+        }
+        self.current += 2;
+        let low = self.hex()?;
+        std::char::from_u32(0x10000 + ((high - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff))
+            .ok_or("malformed \\u escape in json")
+    }
+
+    fn hex(&mut self) -> Result<u32, &'static str> {
+        let digits = self
+            .source
+            .get(self.current..self.current + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok())
+            .ok_or("malformed \\u escape in json")?;
+        self.current += 4;
+        Ok(digits)
+    }
+}
+
+// the name of a token kind without its value
+fn kind_name(kind: TokenKind) -> String {
+    let name = format!("{:?}", kind);
+    match name.find('(') {
+        Some(paren) => name[..paren].to_string(),
+        None => name,
+    }
+}
+
+pub fn tokens(tokens: &[Token<'_>]) -> Json {
+    Json::Array(
+        tokens
+            .iter()
+            .map(|token| {
+                Json::object(vec![
+                    ("kind", Json::String(kind_name(token.kind))),
+                    ("lexeme", Json::String(token.to_string())),
+                ])
+            })
+            .collect(),
+    )
+}
+
+pub fn from_expr(expr: &Expr<'_>) -> Json {
+    let exprs = |exprs: &[Expr<'_>]| Json::Array(exprs.iter().map(from_expr).collect());
+    match expr {
+        Expr::Literal { literal } => Json::object(vec![
+            ("type", Json::string("number")),
+            (
+                "value",
+                match literal.kind {
+                    TokenKind::Integer(i) => Json::Integer(i),
+                    TokenKind::Float(x) => Json::Float(x),
+                    _ => Json::Null,
+                },
+            ),
+        ]),
+        Expr::Variable { name } => Json::object(vec![
+            ("type", Json::string("variable")),
+            ("name", Json::string(name.lexeme)),
+        ]),
+        Expr::SpecialVariable { name } => Json::object(vec![
+            ("type", Json::string("special")),
+            ("name", Json::string(name.lexeme)),
+        ]),
+        Expr::Unary { op, rhs } => Json::object(vec![
+            ("type", Json::string("unary")),
+            ("op", Json::string(op.lexeme)),
+            ("rhs", from_expr(rhs)),
+        ]),
+        Expr::Binary { lhs, op, rhs } => Json::object(vec![
+            ("type", Json::string("binary")),
+            ("op", Json::string(op.lexeme)),
+            ("lhs", from_expr(lhs)),
+            ("rhs", from_expr(rhs)),
+        ]),
+        Expr::Call { name, args } => Json::object(vec![
+            ("type", Json::string("call")),
+            ("name", Json::string(name.lexeme)),
+            ("args", exprs(args)),
+        ]),
+        Expr::Piecewise { branches } => Json::object(vec![
+            ("type", Json::string("piecewise")),
+            (
+                "branches",
+                Json::Array(
+                    branches
+                        .iter()
+                        .map(|branch| {
+                            Json::object(vec![
+                                ("value", from_expr(&branch.value)),
+                                ("guard", branch.guard.as_ref().map_or(Json::Null, from_expr)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+        ]),
+        Expr::Matrix { rows } => Json::object(vec![
+            ("type", Json::string("matrix")),
+            (
+                "rows",
+                Json::Array(rows.iter().map(|row| exprs(row)).collect()),
+            ),
+        ]),
+    }
+}
+
+pub fn from_value(value: &Value) -> Json {
+    match value {
+        Value::Number(x) => Json::Float(*x),
+        Value::Bool(b) => Json::Bool(*b),
+        Value::Matrix(m) => Json::object(vec![
+            ("rows", Json::Integer(m.rows as u64)),
+            ("cols", Json::Integer(m.cols as u64)),
+            (
+                "data",
+                Json::Array(m.data.iter().map(|x| Json::Float(*x)).collect()),
+            ),
+        ]),
+    }
+}
+
+// an operator from its text, like the scanner would read it
+fn operator(op: &str) -> Result<Token<'_>, &'static str> {
+    let token = Scanner::new(op).next_token()?;
+    if token.lexeme != op {
+        return Err("unknown operator in expr");
+    }
+    Ok(token)
+}
+
+// exprs borrow their names from the json
+pub fn to_expr(json: &Json) -> Result<Expr<'_>, &'static str> {
+    match json.str("type")? {
+        "number" => Ok(Expr::Literal {
+            literal: match json.field("value")? {
+                Json::Integer(i) => Token::integer(*i),
+                Json::Float(x) => Token::new(TokenKind::Float(*x), ""),
+                _ => return Err("expected a number in expr"),
+            },
+        }),
+        "variable" => Ok(Expr::Variable {
+            name: Token::new(TokenKind::Variable, json.str("name")?),
+        }),
+        "special" => Ok(Expr::SpecialVariable {
+            name: Token::new(TokenKind::SpecialVariable, json.str("name")?),
+        }),
+        "unary" => Ok(Expr::Unary {
+            op: operator(json.str("op")?)?,
+            rhs: Box::new(to_expr(json.field("rhs")?)?),
+        }),
+        "binary" => Ok(Expr::Binary {
+            lhs: Box::new(to_expr(json.field("lhs")?)?),
+            op: operator(json.str("op")?)?,
+            rhs: Box::new(to_expr(json.field("rhs")?)?),
+        }),
+        "call" => {
+            let name = json.str("name")?;
+            let builtin = KEYWORDS
+                .iter()
+                .any(|(keyword, kind)| *keyword == name && *kind == TokenKind::BuiltinFunction);
+            let kind = if builtin {
+                TokenKind::BuiltinFunction
+            } else {
+                TokenKind::Function
+            };
+            Ok(Expr::Call {
+                name: Token::new(kind, name),
+                args: to_exprs(json.array("args")?)?,
+            })
+        }
+        "piecewise" => Ok(Expr::Piecewise {
+            branches: json
+                .array("branches")?
+                .iter()
+                .map(|branch| {
+                    Ok(Branch {
+                        value: to_expr(branch.field("value")?)?,
+                        guard: match branch.get("guard") {
+                            None | Some(Json::Null) => None,
+                            Some(guard) => Some(to_expr(guard)?),
+                        },
+                    })
+                })
+                .collect::<Result<_, &'static str>>()?,
+        }),
+        "matrix" => {
+            let rows = json
+                .array("rows")?
+                .iter()
+                .map(|row| match row {
+                    Json::Array(items) => to_exprs(items),
+                    _ => Err("expected an array in expr"),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if rows.is_empty() || rows.iter().any(|row| row.len() != rows[0].len()) {
+                return Err("matrix rows have to be the same length");
+            }
+            Ok(Expr::Matrix { rows })
+        }
+        _ => Err("unknown type of expr"),
+    }
+}
+
+fn to_exprs(items: &[Json]) -> Result<Vec<Expr<'_>>, &'static str> {
+    items.iter().map(to_expr).collect()
+}
@@ -0,0 +1,309 @@
+use super::ast::*;
+use super::scan::*;
+
+// how tightly an expression binds, so children that bind looser than their
+// parent get parenthesized
+fn precedence(expr: &Expr<'_>) -> u8 {
+    match expr {
+        Expr::Binary { op, .. } => match op.kind {
+            TokenKind::Or => 1,
+            TokenKind::And => 2,
+            TokenKind::Plus | TokenKind::Minus => 5,
+            TokenKind::Multiply | TokenKind::Divide | TokenKind::Modulo => 6,
+            TokenKind::Power => 7,
+            _ => 4,
+        },
+        Expr::Unary { op, .. } => match op.kind {
+            TokenKind::Not => 3,
+            TokenKind::Minus => 5,
+            _ => 9,
+        },
+        _ => 10,
+    }
+}
+
+const GREEK: [&str; 12] = [
+    "alpha", "beta", "gamma", "delta", "epsilon", "theta", "lambda", "mu", "pi", "sigma", "phi",
+    "omega",
+];
+
+struct Latex(String);
+
+impl Latex {
+    fn grouped(&mut self, expr: &Expr<'_>, min: u8) {
+        if precedence(expr) < min {
+            self.0.push_str("\\left(");
+            self.visit_expr(expr);
+            self.0.push_str("\\right)");
+        } else {
+            self.visit_expr(expr);
+        }
+    }
+
+    fn name(&mut self, name: &str) {
+        if matches!(name, "inf" | "∞") {
+            self.0.push_str("\\infty");
+        } else if GREEK.contains(&name) {
+            self.0.push('\\');
+            self.0.push_str(name);
+        } else {
+            self.0.push_str(name);
+        }
+    }
+
+    // \int_{a}^{b} f \, dx, the dx is either separate or already in the body
+    fn integral(
+        &mut self,
+        body: &Expr<'_>,
+        var: Option<&Expr<'_>>,
+        bounds: Option<(&Expr<'_>, &Expr<'_>)>,
+    ) {
+        self.0.push_str("\\int");
+        if let Some((a, b)) = bounds {
+            self.0.push_str("_{");
+            self.visit_expr(a);
+            self.0.push_str("}^{");
+            self.visit_expr(b);
+            self.0.push('}');
+        }
+        self.0.push(' ');
+        match var {
+            Some(var) => {
+                self.grouped(body, 6);
+                self.0.push_str(" \\, d");
+                self.visit_expr(var);
+            }
+            None => match body {
+                Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Multiply => {
+                    self.grouped(lhs, 6);
+                    self.0.push_str(" \\, ");
+                    self.grouped(rhs, 6);
+                }
+                _ => self.visit_expr(body),
+            },
+        }
+    }
+}
+
+impl<'a> Visitor<'a> for Latex {
+    fn visit_literal(&mut self, literal: &Token<'a>) {
+        self.0.push_str(&literal.to_string());
+    }
+
+    fn visit_variable(&mut self, name: &Token<'a>) {
+        self.name(name.lexeme);
+    }
+
+    fn visit_special_variable(&mut self, name: &Token<'a>) {
+        // dtheta is d\theta
+        match name.lexeme.strip_prefix('d') {
+            Some(var) if !var.is_empty() => {
+                self.0.push('d');
+                self.name(var);
+            }
+            _ => self.name(name.lexeme),
+        }
+    }
+
+    fn visit_unary(&mut self, op: &Token<'a>, rhs: &Expr<'a>) {
+        match op.kind {
+            TokenKind::Minus => {
+                self.0.push('-');
+                self.grouped(rhs, 6);
+            }
+            TokenKind::Not => {
+                self.0.push_str("\\lnot ");
+                self.grouped(rhs, 4);
+            }
+            _ => {
+                self.grouped(rhs, 10);
+                self.0.push_str(op.lexeme);
+            }
+        }
+    }
+
+    fn visit_binary(&mut self, lhs: &Expr<'a>, op: &Token<'a>, rhs: &Expr<'a>) {
+        let (symbol, left, right) = match op.kind {
+            TokenKind::Divide => {
+                self.0.push_str("\\frac{");
+                self.visit_expr(lhs);
+                self.0.push_str("}{");
+                self.visit_expr(rhs);
+                self.0.push('}');
+                return;
+            }
+            TokenKind::Power => {
+                self.grouped(lhs, 8);
+                self.0.push_str("^{");
+                self.visit_expr(rhs);
+                self.0.push('}');
+                return;
+            }
+            // 2x rather than 2 \cdot x
+            TokenKind::Multiply
+                if matches!(lhs, Expr::Literal { .. })
+                    && !matches!(rhs, Expr::Literal { .. } | Expr::Unary { .. }) =>
+            {
+                ("", 6, 6)
+            }
+            TokenKind::Multiply => (" \\cdot ", 6, 6),
+            TokenKind::Modulo => (" \\bmod ", 6, 7),
+            TokenKind::Plus => (" + ", 5, 5),
+            TokenKind::Minus => (" - ", 5, 6),
+            TokenKind::Equal => (" = ", 5, 5),
+            TokenKind::Less => (" < ", 5, 5),
+            TokenKind::Greater => (" > ", 5, 5),
+            TokenKind::LessEqual => (" \\le ", 5, 5),
+            TokenKind::GreaterEqual => (" \\ge ", 5, 5),
+            TokenKind::NotEqual => (" \\ne ", 5, 5),
+            TokenKind::And => (" \\land ", 2, 3),
+            TokenKind::Or => (" \\lor ", 1, 2),
+            _ => (op.lexeme, 5, 5),
+        };
+
+        self.grouped(lhs, left);
+        self.0.push_str(symbol);
+        self.grouped(rhs, right);
+    }
+
+    fn visit_call(&mut self, name: &Token<'a>, args: &[Expr<'a>]) {
+        match (name.lexeme, args) {
+            ("sqrt", [arg]) => {
+                self.0.push_str("\\sqrt{");
+                self.visit_expr(arg);
+                self.0.push('}');
+                return;
+            }
+            ("sigma", [start, end, body]) => {
+                self.0.push_str("\\sum_{");
+                self.visit_expr(start);
+                self.0.push_str("}^{");
+                self.visit_expr(end);
+                self.0.push_str("} ");
+                self.grouped(body, 6);
+                return;
+            }
+            ("integrate" | "int" | "∫", [body, var, a, b]) => {
+                self.integral(body, Some(var), Some((a, b)));
+                return;
+            }
+            ("int" | "∫", [body, a, b]) => {
+                self.integral(body, None, Some((a, b)));
+                return;
+            }
+            ("integrate" | "int" | "∫", [body, var]) => {
+                self.integral(body, Some(var), None);
+                return;
+            }
+            ("integrate" | "int" | "∫", [body]) => {
+                self.integral(body, None, None);
+                return;
+            }
+            ("limit", [body, var, a, ..]) => {
+                self.0.push_str("\\lim_{");
+                self.visit_expr(var);
+                self.0.push_str(" \\to ");
+                self.visit_expr(a);
+                match args.get(3) {
+                    Some(Expr::Variable { name }) if name.lexeme == "left" => {
+                        self.0.push_str("^{-}")
+                    }
+                    Some(Expr::Variable { name }) if name.lexeme == "right" => {
+                        self.0.push_str("^{+}")
+                    }
+                    _ => {}
+                }
+                self.0.push_str("} ");
+                self.grouped(body, 6);
+                return;
+            }
+            // partial when there's more than one variable
+            ("diff", [arg, var]) => {
+                let x = match var {
+                    Expr::Variable { name } | Expr::SpecialVariable { name } => name.lexeme,
+                    _ => "",
+                };
+                let d = if free_variables(arg).iter().any(|v| *v != x) {
+                    "\\partial "
+                } else {
+                    "d"
+                };
+                self.0.push_str("\\frac{");
+                self.0.push_str(d.trim_end());
+                self.0.push_str("}{");
+                self.0.push_str(d);
+                self.visit_expr(var);
+                self.0.push_str("} ");
+                self.grouped(arg, 10);
+                return;
+            }
+            ("grad", [f, _]) => {
+                self.0.push_str("\\nabla ");
+                self.grouped(f, 10);
+                return;
+            }
+            (
+                "sin" | "cos" | "tan" | "csc" | "sec" | "cot" | "arctan" | "ln" | "log" | "det",
+                _,
+            ) => {
+                self.0.push('\\');
+                self.0.push_str(name.lexeme);
+            }
+            _ if name.lexeme.len() > 1 => {
+                self.0.push_str("\\operatorname{");
+                self.0.push_str(name.lexeme);
+                self.0.push('}');
+            }
+            _ => self.0.push_str(name.lexeme),
+        }
+
+        self.0.push_str("\\left(");
+        for (i, arg) in args.iter().enumerate() {
+            if i > 0 {
+                self.0.push_str(", ");
+            }
+            self.visit_expr(arg);
+        }
+        self.0.push_str("\\right)");
+    }
+
+    fn visit_piecewise(&mut self, branches: &[Branch<'a>]) {
+        self.0.push_str("\\begin{cases} ");
+        for (i, branch) in branches.iter().enumerate() {
+            if i > 0 {
+                self.0.push_str(" \\\\ ");
+            }
+            self.visit_expr(&branch.value);
+            match &branch.guard {
+                Some(guard) => {
+                    self.0.push_str(" & \\text{if } ");
+                    self.visit_expr(guard);
+                }
+                None => self.0.push_str(" & \\text{otherwise}"),
+            }
+        }
+        self.0.push_str(" \\end{cases}");
+    }
+
+    fn visit_matrix(&mut self, rows: &[Vec<Expr<'a>>]) {
+        self.0.push_str("\\begin{bmatrix} ");
+        for (i, row) in rows.iter().enumerate() {
+            if i > 0 {
+                self.0.push_str(" \\\\ ");
+            }
+            for (j, entry) in row.iter().enumerate() {
+                if j > 0 {
+                    self.0.push_str(" & ");
+                }
+                self.visit_expr(entry);
+            }
+        }
+        self.0.push_str(" \\end{bmatrix}");
+    }
+}
+
+pub fn to_latex(expr: &Expr<'_>) -> String {
+    let mut latex = Latex(String::new());
+    latex.visit_expr(expr);
+    latex.0
+}
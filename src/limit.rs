@@ -0,0 +1,684 @@
+use super::ast::*;
+use super::diff::{call, depends_on, negate, power, product, quotient, sum};
+use super::eval::{self, Env};
+use super::factor;
+use super::integral;
+use super::poly::*;
+use super::rational::Rational;
+use super::rewrite::{self, Bindings};
+use super::scan::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Left,
+    Right,
+    Both,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Point {
+    Finite(f64),
+    Infinity,
+    NegativeInfinity,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Limit<'a> {
+    Finite(Expr<'a>),
+    Infinity,
+    NegativeInfinity,
+}
+
+impl<'a> Limit<'a> {
+    fn infinite(positive: bool) -> Limit<'a> {
+        if positive {
+            Limit::Infinity
+        } else {
+            Limit::NegativeInfinity
+        }
+    }
+
+    fn negate(self) -> Limit<'a> {
+        match self {
+            Limit::Finite(expr) => Limit::Finite(negate(expr)),
+            Limit::Infinity => Limit::NegativeInfinity,
+            Limit::NegativeInfinity => Limit::Infinity,
+        }
+    }
+
+    pub fn to_expr(&self) -> Expr<'a> {
+        let infinity = Expr::Variable {
+            name: Token::new(TokenKind::Variable, "∞"),
+        };
+        match self {
+            Limit::Finite(expr) => expr.clone(),
+            Limit::Infinity => infinity,
+            Limit::NegativeInfinity => negate(infinity),
+        }
+    }
+}
+
+// how many times l'hôpital's rule gets applied before giving up
+const MAX_DEPTH: usize = 8;
+
+const DIFFERENT_SIDES: &str = "limit does not exist: the left and right limits differ";
+const OSCILLATES: &str = "limit does not exist: the function oscillates forever";
+const UNKNOWN: &str = "couldn't find the limit";
+
+// where x is heading: `value` is the point as written, `side` is 1 from the
+// right and -1 from the left
+struct Approach<'a> {
+    x: &'a str,
+    point: Point,
+    value: Expr<'a>,
+    side: f64,
+    depth: usize,
+}
+
+impl<'a> Approach<'a> {
+    fn deeper(&self) -> Result<Approach<'a>, &'static str> {
+        if self.depth >= MAX_DEPTH {
+            return Err("couldn't find the limit, l'hôpital's rule didn't settle");
+        }
+        Ok(self.at_depth(self.depth + 1))
+    }
+
+    fn at_depth(&self, depth: usize) -> Approach<'a> {
+        Approach {
+            x: self.x,
+            point: self.point,
+            value: self.value.clone(),
+            side: self.side,
+            depth,
+        }
+    }
+
+    // somewhere very close to the point, on the right side of it
+    fn nearby(&self) -> f64 {
+        match self.point {
+            Point::Finite(a) => a + self.side * 1e-7 * a.abs().max(1.0),
+            Point::Infinity => 1e12,
+            Point::NegativeInfinity => -1e12,
+        }
+    }
+
+    // the sign of expr just next to the point
+    fn sign_near(&self, expr: &Expr<'_>) -> Result<f64, &'static str> {
+        let value = eval::eval_number(expr, &Env::new().with(self.x, self.nearby()))?;
+        if value == 0.0 || value.is_nan() {
+            Err(UNKNOWN)
+        } else {
+            Ok(value.signum())
+        }
+    }
+
+    fn substitute(&self, expr: &Expr<'a>) -> Expr<'a> {
+        let mut bindings = Bindings::new();
+        bindings.insert(self.x, self.value.clone());
+        rewrite::substitute(expr, &bindings)
+    }
+}
+
+// the limit of expr as x approaches the point, from one side or both. a finite
+// answer is kept exact where it can be.
+pub fn limit<'a>(
+    expr: &Expr<'a>,
+    x: &'a str,
+    point: Point,
+    value: Expr<'a>,
+    side: Side,
+) -> Result<Limit<'a>, &'static str> {
+    let from = |side| {
+        approach(
+            expr,
+            &Approach {
+                x,
+                point,
+                value: value.clone(),
+                side,
+                depth: 0,
+            },
+        )
+    };
+
+    let result = match (point, side) {
+        (Point::Finite(_), Side::Both) => {
+            let (left, right) = (from(-1.0)?, from(1.0)?);
+            if !same(&left, &right) {
+                return Err(DIFFERENT_SIDES);
+            }
+            right
+        }
+        (_, Side::Left) => from(-1.0)?,
+        _ => from(1.0)?,
+    };
+
+    Ok(match result {
+        Limit::Finite(expr) => Limit::Finite(exact(expr)),
+        infinite => infinite,
+    })
+}
+
+fn value(expr: &Expr<'_>) -> Result<f64, &'static str> {
+    eval::eval_number(expr, &Env::new())
+        .map_err(|_| "can only take limits of expressions in one variable")
+}
+
+fn is_zero(expr: &Expr<'_>) -> Result<bool, &'static str> {
+    Ok(value(expr)?.abs() < 1e-12)
+}
+
+fn same(a: &Limit<'_>, b: &Limit<'_>) -> bool {
+    match (a, b) {
+        (Limit::Finite(a), Limit::Finite(b)) => match (value(a), value(b)) {
+            (Ok(a), Ok(b)) => (a - b).abs() <= 1e-9 * a.abs().max(1.0),
+            _ => a == b,
+        },
+        _ => a == b,
+    }
+}
+
+// a rational number if the value is one with a small denominator, otherwise
+// the expression as it is
+pub fn exact(expr: Expr<'_>) -> Expr<'_> {
+    if let Some(c) = Poly::from_expr(&expr).ok().and_then(|p| p.as_constant()) {
+        return Poly::constant(c).to_expr();
+    }
+    let v = match value(&expr) {
+        Ok(v) => v,
+        Err(_) => return expr,
+    };
+    for den in 1..=100 {
+        let num = (v * den as f64).round();
+        if (v * den as f64 - num).abs() < 1e-9 && num.abs() < 1e15 {
+            return Poly::constant(Rational::new(num as i128, den)).to_expr();
+        }
+    }
+    expr
+}
+
+fn approach<'a>(expr: &Expr<'a>, at: &Approach<'a>) -> Result<Limit<'a>, &'static str> {
+    if !depends_on(expr, at.x) {
+        return Ok(Limit::Finite(expr.clone()));
+    }
+
+    // continuous at the point, which also means it's defined there
+    if let (Point::Finite(a), false) = (at.point, matches!(expr, Expr::Piecewise { .. })) {
+        if let Ok(v) = eval::eval_number(expr, &Env::new().with(at.x, a)) {
+            if v.is_finite() {
+                return Ok(Limit::Finite(at.substitute(expr)));
+            }
+        }
+    }
+
+    if let Some(result) = rational_function(expr, at) {
+        return result;
+    }
+
+    match expr {
+        Expr::Variable { .. } | Expr::SpecialVariable { .. } => {
+            Ok(Limit::infinite(at.point == Point::Infinity))
+        }
+
+        Expr::Unary { op, rhs } if op.kind == TokenKind::Minus => Ok(approach(rhs, at)?.negate()),
+
+        Expr::Binary { lhs, op, rhs } => match op.kind {
+            TokenKind::Plus | TokenKind::Minus => {
+                let l = approach(lhs, at)?;
+                let r = approach(rhs, at)?;
+                let r = if op.kind == TokenKind::Minus {
+                    r.negate()
+                } else {
+                    r
+                };
+                match (l, r) {
+                    (Limit::Finite(l), Limit::Finite(r)) => Ok(Limit::Finite(sum(l, r))),
+                    (Limit::Finite(_), infinite) | (infinite, Limit::Finite(_)) => Ok(infinite),
+                    (l, r) if l == r => Ok(l),
+                    // ∞ - ∞, which can come out over a common denominator
+                    _ => {
+                        let (n, d) = together(expr)?;
+                        if d.as_constant().is_some() {
+                            return Err("couldn't find the limit of the indeterminate form ∞ - ∞");
+                        }
+                        approach(&quotient(n.to_expr(), d.to_expr()), &at.deeper()?)
+                    }
+                }
+            }
+
+            TokenKind::Multiply => match (approach(lhs, at)?, approach(rhs, at)?) {
+                (Limit::Finite(l), Limit::Finite(r)) => Ok(Limit::Finite(product(l, r))),
+                (Limit::Finite(c), infinite) | (infinite, Limit::Finite(c)) => {
+                    if is_zero(&c)? {
+                        zero_times_infinity(lhs, rhs, at)
+                    } else {
+                        Ok(Limit::infinite(
+                            (value(&c)? > 0.0) == (infinite == Limit::Infinity),
+                        ))
+                    }
+                }
+                (l, r) => Ok(Limit::infinite(l == r)),
+            },
+
+            TokenKind::Divide => match (approach(lhs, at)?, approach(rhs, at)?) {
+                (Limit::Finite(n), Limit::Finite(d)) => match (is_zero(&n)?, is_zero(&d)?) {
+                    (_, false) => Ok(Limit::Finite(quotient(n, d))),
+                    (false, true) => Ok(Limit::infinite(value(&n)? * at.sign_near(rhs)? > 0.0)),
+                    (true, true) => indeterminate(lhs, rhs, at),
+                },
+                (Limit::Finite(_), _) => Ok(Limit::Finite(integer(0))),
+                (infinite, Limit::Finite(d)) => {
+                    let sign = if is_zero(&d)? {
+                        at.sign_near(rhs)?
+                    } else {
+                        value(&d)?.signum()
+                    };
+                    Ok(Limit::infinite(
+                        (sign > 0.0) == (infinite == Limit::Infinity),
+                    ))
+                }
+                _ => indeterminate(lhs, rhs, at),
+            },
+
+            TokenKind::Power => power_limit(lhs, rhs, at),
+
+            _ => Err(UNKNOWN),
+        },
+
+        Expr::Call { name, args } => match args.as_slice() {
+            [u] => call_limit(name, u, at),
+            _ => Err(UNKNOWN),
+        },
+
+        // whichever branch applies just next to the point
+        Expr::Piecewise { branches } => {
+            let env = Env::new().with(at.x, at.nearby());
+            for branch in branches.iter() {
+                let holds = match &branch.guard {
+                    Some(guard) => eval::eval(guard, &env)?.bool()?,
+                    None => true,
+                };
+                if holds {
+                    return approach(&branch.value, at);
+                }
+            }
+            Err("no branch of the piecewise function applies")
+        }
+
+        _ => Err(UNKNOWN),
+    }
+}
+
+fn call_limit<'a>(
+    name: &Token<'a>,
+    u: &Expr<'a>,
+    at: &Approach<'a>,
+) -> Result<Limit<'a>, &'static str> {
+    let function = name.lexeme;
+    match approach(u, at)? {
+        Limit::Finite(u) => {
+            let inner = value(&u)?;
+            match function {
+                "ln" | "log" if inner == 0.0 => Ok(Limit::NegativeInfinity),
+                _ => {
+                    let result = Expr::Call {
+                        name: *name,
+                        args: vec![u],
+                    };
+                    if value(&result)?.is_finite() {
+                        Ok(Limit::Finite(result))
+                    } else {
+                        Err(UNKNOWN)
+                    }
+                }
+            }
+        }
+        Limit::Infinity => match function {
+            "ln" | "log" | "sqrt" => Ok(Limit::Infinity),
+            "sin" | "cos" | "tan" | "csc" | "sec" | "cot" => Err(OSCILLATES),
+            _ => Err(UNKNOWN),
+        },
+        Limit::NegativeInfinity => match function {
+            "sin" | "cos" | "tan" | "csc" | "sec" | "cot" => Err(OSCILLATES),
+            _ => Err("limit does not exist: the function isn't defined there"),
+        },
+    }
+}
+
+fn power_limit<'a>(
+    base: &Expr<'a>,
+    exponent: &Expr<'a>,
+    at: &Approach<'a>,
+) -> Result<Limit<'a>, &'static str> {
+    match (approach(base, at)?, approach(exponent, at)?) {
+        (Limit::Finite(b), Limit::Finite(e)) => {
+            let (vb, ve) = (value(&b)?, value(&e)?);
+            if vb == 0.0 && ve == 0.0 {
+                exp_log(base, exponent, at)
+            } else if vb == 0.0 && ve < 0.0 {
+                Ok(Limit::infinite(
+                    at.sign_near(&power(base.clone(), exponent.clone()))? > 0.0,
+                ))
+            } else if vb.powf(ve).is_finite() {
+                Ok(Limit::Finite(power(b, e)))
+            } else {
+                Err(UNKNOWN)
+            }
+        }
+
+        (Limit::Finite(b), infinite) => {
+            let vb = value(&b)?;
+            let up = infinite == Limit::Infinity;
+            if (vb - 1.0).abs() < 1e-12 {
+                exp_log(base, exponent, at)
+            } else if vb.abs() < 1.0 {
+                if up {
+                    Ok(Limit::Finite(integer(0)))
+                } else if vb == 0.0 {
+                    Ok(Limit::Infinity)
+                } else {
+                    Err(OSCILLATES)
+                }
+            } else if vb > 1.0 {
+                if up {
+                    Ok(Limit::Infinity)
+                } else {
+                    Ok(Limit::Finite(integer(0)))
+                }
+            } else {
+                Err(OSCILLATES)
+            }
+        }
+
+        (infinite, Limit::Finite(e)) => {
+            let ve = value(&e)?;
+            if ve == 0.0 {
+                exp_log(base, exponent, at)
+            } else if ve < 0.0 {
+                Ok(Limit::Finite(integer(0)))
+            } else if infinite == Limit::Infinity {
+                Ok(Limit::Infinity)
+            } else if ve.fract() == 0.0 {
+                Ok(Limit::infinite(ve % 2.0 == 0.0))
+            } else {
+                Err("limit does not exist: the function isn't defined there")
+            }
+        }
+
+        (Limit::Infinity, Limit::Infinity) => Ok(Limit::Infinity),
+        (Limit::Infinity, _) => Ok(Limit::Finite(integer(0))),
+        _ => Err(OSCILLATES),
+    }
+}
+
+// 0^0, 1^∞ and ∞^0 as e^(lim exponent * ln(base))
+fn exp_log<'a>(
+    base: &Expr<'a>,
+    exponent: &Expr<'a>,
+    at: &Approach<'a>,
+) -> Result<Limit<'a>, &'static str> {
+    let e = Expr::Variable {
+        name: Token::new(TokenKind::Variable, "e"),
+    };
+    let log = product(exponent.clone(), call("ln", base.clone()));
+    Ok(match approach(&log, &at.deeper()?)? {
+        Limit::Finite(l) => Limit::Finite(power(e, l)),
+        Limit::Infinity => Limit::Infinity,
+        Limit::NegativeInfinity => Limit::Finite(integer(0)),
+    })
+}
+
+// logs would rather be differentiated, exponentials would rather be flipped
+fn preference(expr: &Expr<'_>, x: &str) -> u8 {
+    match expr {
+        Expr::Call { name, .. } if matches!(name.lexeme, "ln" | "log") => 2,
+        Expr::Binary { lhs, op, .. } if op.kind == TokenKind::Power && !depends_on(lhs, x) => 0,
+        _ => 1,
+    }
+}
+
+fn reciprocal<'a>(expr: &Expr<'a>) -> Expr<'a> {
+    match expr {
+        Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Power => {
+            power(lhs.as_ref().clone(), negate(rhs.as_ref().clone()))
+        }
+        Expr::Binary { lhs, op, rhs }
+            if op.kind == TokenKind::Divide
+                && Poly::from_expr(lhs).ok().and_then(|p| p.as_constant())
+                    == Some(Rational::ONE) =>
+        {
+            rhs.as_ref().clone()
+        }
+        _ => quotient(integer(1), expr.clone()),
+    }
+}
+
+// 0 * ∞ as a quotient of two things that both go to 0 or both to ∞
+fn zero_times_infinity<'a>(
+    lhs: &Expr<'a>,
+    rhs: &Expr<'a>,
+    at: &Approach<'a>,
+) -> Result<Limit<'a>, &'static str> {
+    let (top, bottom) = if preference(lhs, at.x) >= preference(rhs, at.x) {
+        (lhs, rhs)
+    } else {
+        (rhs, lhs)
+    };
+    approach(&quotient(top.clone(), reciprocal(bottom)), &at.deeper()?)
+}
+
+// 0/0 or ∞/∞. at a finite point where both sides are smooth the first terms
+// of their taylor series settle it, otherwise l'hôpital's rule.
+fn indeterminate<'a>(
+    numerator: &Expr<'a>,
+    denominator: &Expr<'a>,
+    at: &Approach<'a>,
+) -> Result<Limit<'a>, &'static str> {
+    // a polynomial on either side loses a degree each time, so those steps
+    // always get somewhere and don't count towards the limit
+    let shrinking = |expr: &Expr<'a>| {
+        Poly::from_expr(expr)
+            .ok()
+            .and_then(|poly| poly.coefficients_in(at.x))
+            .is_some_and(|coefficients| coefficients.len() > 1)
+    };
+    let deeper = if shrinking(numerator) || shrinking(denominator) {
+        at.at_depth(at.depth)
+    } else {
+        at.deeper()?
+    };
+    if let Some(result) = series(numerator, denominator, at) {
+        return result;
+    }
+
+    let top = numerator.derivative(at.x)?;
+    let bottom = denominator.derivative(at.x)?;
+    let (n, d) = together(&quotient(top, bottom))?;
+    let (n, d) = cancel(&n, &d)?;
+    approach(&quotient(n.to_expr(), d.to_expr()), &deeper)
+}
+
+// the order and coefficient of the first nonzero taylor term at the point
+fn leading_term<'a>(expr: &Expr<'a>, at: &Approach<'a>) -> Option<(usize, Expr<'a>, f64)> {
+    let a = match at.point {
+        Point::Finite(a) => a,
+        _ => return None,
+    };
+
+    let mut derivative = expr.clone();
+    let mut factorial = 1.0;
+    for order in 0..=6 {
+        if order > 0 {
+            derivative = derivative.derivative(at.x).ok()?;
+            factorial *= order as f64;
+        }
+        let v = eval::eval_number(&derivative, &Env::new().with(at.x, a)).ok()?;
+        if !v.is_finite() {
+            return None;
+        }
+        if v.abs() > 1e-12 {
+            let coefficient = quotient(at.substitute(&derivative), integer(factorial as i128));
+            return Some((order, coefficient, v / factorial));
+        }
+    }
+    None
+}
+
+fn series<'a>(
+    numerator: &Expr<'a>,
+    denominator: &Expr<'a>,
+    at: &Approach<'a>,
+) -> Option<Result<Limit<'a>, &'static str>> {
+    let (n, top, top_value) = leading_term(numerator, at)?;
+    let (m, bottom, bottom_value) = leading_term(denominator, at)?;
+    Some(Ok(if n == m {
+        Limit::Finite(quotient(top, bottom))
+    } else if n > m {
+        Limit::Finite(integer(0))
+    } else {
+        // a (x - point)^(n - m) with n - m negative
+        let side = if (m - n) % 2 == 1 { at.side } else { 1.0 };
+        Limit::infinite(top_value / bottom_value * side > 0.0)
+    }))
+}
+
+// a ratio of polynomials in x with constant coefficients: compare degrees at
+// infinity, cancel common factors at a finite point
+fn rational_function<'a>(
+    expr: &Expr<'a>,
+    at: &Approach<'a>,
+) -> Option<Result<Limit<'a>, &'static str>> {
+    let dense = |poly: &Poly<'a>| -> Option<factor::Dense> {
+        poly.coefficients_in(at.x)?
+            .iter()
+            .map(|c| c.as_constant())
+            .collect::<Option<_>>()
+            .map(factor::trim)
+    };
+    let (n, d) = match together(expr) {
+        Ok(together) => together,
+        Err(err) => return Some(Err(err)),
+    };
+    let (p, q) = (dense(&n)?, dense(&d)?);
+    if q.is_empty() {
+        return None;
+    }
+
+    match at.point {
+        Point::Finite(_) => {
+            let cancelled = factor::gcd(&p, &q).and_then(|g| {
+                Ok((
+                    g.clone(),
+                    factor::divmod(&p, &g)?.0,
+                    factor::divmod(&q, &g)?.0,
+                ))
+            });
+            let (g, p, q) = match cancelled {
+                Ok(cancelled) => cancelled,
+                Err(err) => return Some(Err(err)),
+            };
+            if factor::degree(&g) == 0 {
+                return None;
+            }
+            let atom = Atom::new(integral::variable(expr, at.x));
+            let simpler = quotient(
+                Poly::from_dense(&atom, &p).to_expr(),
+                Poly::from_dense(&atom, &q).to_expr(),
+            );
+            Some(approach(&simpler, at))
+        }
+        _ => {
+            let (dp, dq) = (factor::degree(&p), factor::degree(&q));
+            let lead = match p.last()?.checked_div(*q.last()?) {
+                Ok(lead) => lead,
+                Err(err) => return Some(Err(err)),
+            };
+            Some(Ok(if dp < dq || p.is_empty() {
+                Limit::Finite(integer(0))
+            } else if dp == dq {
+                Limit::Finite(Poly::constant(lead).to_expr())
+            } else {
+                let negative_x = at.point == Point::NegativeInfinity && (dp - dq) % 2 == 1;
+                Limit::infinite((lead > Rational::ZERO) != negative_x)
+            }))
+        }
+    }
+}
+
+// numerator and denominator of expr over a common denominator
+fn together<'a>(expr: &Expr<'a>) -> Result<(Poly<'a>, Poly<'a>), &'static str> {
+    let one = || Poly::constant(Rational::ONE);
+    Ok(match expr {
+        Expr::Unary { op, rhs } if op.kind == TokenKind::Minus => {
+            let (n, d) = together(rhs)?;
+            (n.neg(), d)
+        }
+        Expr::Binary { lhs, op, rhs } => {
+            let (ln, ld) = together(lhs)?;
+            let (rn, rd) = together(rhs)?;
+            match op.kind {
+                TokenKind::Plus => (ln.mul(&rd)?.add(&rn.mul(&ld)?)?, ld.mul(&rd)?),
+                TokenKind::Minus => (ln.mul(&rd)?.sub(&rn.mul(&ld)?)?, ld.mul(&rd)?),
+                TokenKind::Multiply => (ln.mul(&rn)?, ld.mul(&rd)?),
+                TokenKind::Divide => (ln.mul(&rd)?, ld.mul(&rn)?),
+                TokenKind::Power => match Poly::from_expr(rhs)?.as_constant() {
+                    Some(c) if c.is_integer() && c.num().abs() <= 16 => {
+                        let exp = c.num().unsigned_abs() as u32;
+                        if c.is_negative() {
+                            (ld.pow(exp)?, ln.pow(exp)?)
+                        } else {
+                            (ln.pow(exp)?, ld.pow(exp)?)
+                        }
+                    }
+                    _ => (Poly::from_expr(expr)?, one()),
+                },
+                _ => (Poly::from_expr(expr)?, one()),
+            }
+        }
+        _ => (Poly::from_expr(expr)?, one()),
+    })
+}
+
+// expr as a single fraction in lowest terms, as far as common atoms go
+pub fn cancelled<'a>(expr: &Expr<'a>) -> Result<Expr<'a>, &'static str> {
+    let (n, d) = together(expr)?;
+    let (n, d) = cancel(&n, &d)?;
+    Ok(match d.as_constant() {
+        Some(c) if !c.is_zero() => n.scale(c.recip())?.to_expr(),
+        _ => quotient(n.to_expr(), d.to_expr()),
+    })
+}
+
+fn monomial_poly<'a>(monomial: &Monomial<'a>) -> Result<Poly<'a>, &'static str> {
+    monomial
+        .0
+        .iter()
+        .try_fold(Poly::constant(Rational::ONE), |poly, (atom, exp)| {
+            poly.mul(&Poly::atom(atom.expr.clone()).pow(*exp)?)
+        })
+}
+
+// divide out the atoms the numerator and denominator have in common
+fn cancel<'a>(n: &Poly<'a>, d: &Poly<'a>) -> Result<(Poly<'a>, Poly<'a>), &'static str> {
+    let (cn, mn, rn) = n.common_factor()?;
+    let (cd, md, rd) = d.common_factor()?;
+    let without = |m: &Monomial<'a>, other: &Monomial<'a>| {
+        Monomial(
+            m.0.iter()
+                .filter_map(|(atom, exp)| {
+                    let shared = other
+                        .0
+                        .iter()
+                        .find(|(a, _)| a == atom)
+                        .map_or(0, |(_, e)| (*e).min(*exp));
+                    (*exp > shared).then(|| (atom.clone(), exp - shared))
+                })
+                .collect(),
+        )
+    };
+    let n = rn
+        .mul(&monomial_poly(&without(&mn, &md))?)?
+        .scale(cn.checked_div(cd)?)?;
+    let d = rd.mul(&monomial_poly(&without(&md, &mn))?)?;
+    Ok((n, d))
+}
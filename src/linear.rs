@@ -0,0 +1,135 @@
+use super::ast::*;
+use super::poly::*;
+use super::rational::Rational;
+use super::scan::*;
+
+#[derive(Debug, Clone)]
+pub enum SystemSolution<'a> {
+    Unique(Vec<(&'a str, Rational)>),
+    // the pivot unknowns written in terms of the free ones
+    Parametric {
+        solved: Vec<(&'a str, Expr<'a>)>,
+        free: Vec<&'a str>,
+    },
+    Inconsistent,
+}
+
+// solve a system of linear equations for every variable in it by gaussian
+// elimination over the rationals, so there's no rounding
+pub fn solve_system<'a>(equations: &[Expr<'a>]) -> Result<SystemSolution<'a>, &'static str> {
+    let mut unknowns: Vec<&'a str> = Vec::new();
+    let mut differences = Vec::new();
+    for equation in equations.iter() {
+        match equation {
+            Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Equal => {
+                differences.push(Poly::from_expr(lhs)?.sub(&Poly::from_expr(rhs)?)?);
+            }
+            _ => return Err("every line of a system has to be an equation"),
+        }
+        for var in free_variables(equation) {
+            if !unknowns.contains(&var) {
+                unknowns.push(var);
+            }
+        }
+    }
+
+    // one row per equation, the last column is the right hand side
+    let mut rows = Vec::new();
+    for difference in differences.iter() {
+        let mut row = vec![Rational::ZERO; unknowns.len() + 1];
+        for (monomial, coefficient) in difference.terms.iter() {
+            match monomial.0.as_slice() {
+                [] => row[unknowns.len()] = -*coefficient,
+                [(atom, 1)] => match &atom.expr {
+                    Expr::Variable { name } => {
+                        let column = unknowns
+                            .iter()
+                            .position(|var| *var == name.lexeme)
+                            .ok_or("not a linear system")?;
+                        row[column] = *coefficient;
+                    }
+                    _ => return Err("non-linear term: an unknown inside a function"),
+                },
+                _ => return Err("non-linear term: a product or power of unknowns"),
+            }
+        }
+        rows.push(row);
+    }
+
+    let pivots = reduce(&mut rows, unknowns.len())?;
+
+    // 0 = c with c nonzero
+    if rows
+        .iter()
+        .skip(pivots.len())
+        .any(|row| !row[unknowns.len()].is_zero())
+    {
+        return Ok(SystemSolution::Inconsistent);
+    }
+
+    if pivots.len() == unknowns.len() {
+        return Ok(SystemSolution::Unique(
+            pivots
+                .iter()
+                .enumerate()
+                .map(|(row, column)| (unknowns[*column], rows[row][unknowns.len()]))
+                .collect(),
+        ));
+    }
+
+    let free: Vec<usize> = (0..unknowns.len())
+        .filter(|column| !pivots.contains(column))
+        .collect();
+    let solved = pivots
+        .iter()
+        .enumerate()
+        .map(|(row, column)| {
+            let mut value = Poly::constant(rows[row][unknowns.len()]);
+            for other in free.iter() {
+                let variable = Poly::atom(Expr::Variable {
+                    name: Token::new(TokenKind::Variable, unknowns[*other]),
+                });
+                value = value.sub(&variable.scale(rows[row][*other])?)?;
+            }
+            Ok((unknowns[*column], value.to_expr()))
+        })
+        .collect::<Result<_, &'static str>>()?;
+
+    Ok(SystemSolution::Parametric {
+        solved,
+        free: free.iter().map(|column| unknowns[*column]).collect(),
+    })
+}
+
+// reduced row echelon form. returns the pivot column of each leading row.
+fn reduce(rows: &mut [Vec<Rational>], columns: usize) -> Result<Vec<usize>, &'static str> {
+    let mut pivots = Vec::new();
+
+    for column in 0..columns {
+        let row = pivots.len();
+        let pivot = match (row..rows.len()).find(|r| !rows[*r][column].is_zero()) {
+            Some(pivot) => pivot,
+            None => continue,
+        };
+        rows.swap(row, pivot);
+
+        let scale = rows[row][column].recip();
+        for value in rows[row].iter_mut() {
+            *value = value.checked_mul(scale)?;
+        }
+
+        let pivot_row = rows[row].clone();
+        for (other, values) in rows.iter_mut().enumerate() {
+            let factor = values[column];
+            if other != row && !factor.is_zero() {
+                for (value, pivot_value) in values.iter_mut().zip(pivot_row.iter()) {
+                    *value = value.checked_sub(factor.checked_mul(*pivot_value)?)?;
+                }
+            }
+        }
+
+        pivots.push(column);
+    }
+
+    Ok(pivots)
+}
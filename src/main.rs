@@ -1,75 +1,985 @@
 use rustyline::error::ReadlineError;
-use rustyline::Editor;
+use rustyline::{CompletionType, Config, Editor};
+
+use command::{Command, View};
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 
 mod ast;
+mod builtin;
+mod cli;
+mod command;
+mod diff;
+mod dot;
+mod eval;
+mod factor;
+mod function;
+mod helper;
+mod integral;
+mod interval;
+mod json;
+mod latex;
+mod limit;
+mod linear;
+mod matrix;
+mod ode;
 mod parse;
+mod poly;
+mod quadrature;
+mod rational;
+mod rewrite;
+mod root;
 mod scan;
+mod script;
+mod series;
+mod sexpr;
+mod solve;
+mod storage;
 
 fn main() {
-    if std::env::args().any(|arg| arg == "-f") {
-        let expr = "f(x)!=3x^2-2x+1";
-        //let expr = "(x^2+1)(x^2-2)";
-        //let expr = "-3(x+2)";
-        //let expr = "-3x-6";
-        //let expr = "(x(3l)^3*(4^4)^(4^4))3";
-        //let expr = "sigma(i=0, 100, i^2)";
-        //println!(
-        //    "{:?}",
-        //    scan::Scanner::new(expr).scan_all().and_then(|ok| Ok(ok
-        //        .iter()
-        //        .map(|tk| tk.kind)
-        //        .collect::<Vec<scan::TokenKind>>()))
-        //);
-        print_stuff(expr);
-    } else {
-        let mut rl = Editor::<()>::new();
-        let _ = rl.load_history("inputs.txt");
-        loop {
-            let readline = rl.readline(">> ");
-            match readline {
-                Ok(line) => {
-                    rl.add_history_entry(&line);
-                    print_stuff(&line);
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("err: {}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+
+    JSON.store(options.json, Ordering::Relaxed);
+    let data_dir = storage::data_dir(options.data_dir.as_deref());
+
+    let mut rules = rewrite::RuleSet::parse(rewrite::DEFAULT_RULES).unwrap();
+    let rules_file = storage::rules_file(&data_dir);
+    let user_rules = std::fs::read_to_string(&rules_file).unwrap_or_default();
+    match rewrite::RuleSet::parse(&user_rules) {
+        Ok(user_rules) => rules.extend(user_rules),
+        Err(err) => eprintln!("{}: {}", rules_file.display(), err),
+    }
+
+    let mut session = Session::new(rules);
+    let views = options.views.clone();
+    let set_views = |session: &mut Session<'_>| {
+        if let Some(views) = &views {
+            session.settings.views = views.iter().copied().collect();
+        }
+    };
+
+    match options.input {
+        cli::Input::Repl => {
+            let session_file = storage::session_file(&data_dir);
+            session.file = Some(session_file.clone());
+            if let Ok(source) = std::fs::read_to_string(&session_file) {
+                for (line, err) in restore(&source, &mut session) {
+                    eprintln!("{}:{}: {}", session_file.display(), line, err);
                 }
-                Err(ReadlineError::Interrupted) => {
-                    break;
+            }
+            set_views(&mut session);
+            repl(&data_dir, &mut session);
+        }
+        cli::Input::Eval(lines) => {
+            set_views(&mut session);
+            std::process::exit(run(lines.into_iter(), &mut session));
+        }
+        cli::Input::Lines(None) => {
+            set_views(&mut session);
+            let stdin = std::io::stdin();
+            let lines = stdin.lock().lines().map_while(Result::ok);
+            std::process::exit(run(lines, &mut session));
+        }
+        cli::Input::Lines(Some(path)) => {
+            set_views(&mut session);
+            match std::fs::read_to_string(&path) {
+                Ok(source) => {
+                    run_script(&path, &source, &mut session, false);
+                    std::process::exit(FAILED.load(Ordering::Relaxed) as i32);
+                }
+                Err(err) => {
+                    report(format_args!("{}: {}", path, err));
+                    std::process::exit(1);
                 }
-                Err(ReadlineError::Eof) => {
+            }
+        }
+    }
+}
+
+fn repl(data_dir: &Path, session: &mut Session<'_>) {
+    let history = storage::history_file(data_dir);
+    let config = Config::builder()
+        .completion_type(CompletionType::List)
+        .build();
+    let mut rl = Editor::<helper::Helper>::with_config(config);
+    rl.set_helper(Some(helper::Helper {
+        functions: session.functions.clone(),
+        variables: session.variables.clone(),
+    }));
+    let _ = rl.load_history(&history);
+    let mut block = String::new();
+    loop {
+        let readline = rl.readline(if block.is_empty() { ">> " } else { ".. " });
+        match readline {
+            Ok(line) => {
+                rl.add_history_entry(&line);
+                if !feed(&line, &mut block, session) {
                     break;
                 }
+                if let Some(helper) = rl.helper_mut() {
+                    helper.functions = session.functions.clone();
+                    helper.variables = session.variables.clone();
+                }
+            }
+            Err(ReadlineError::Interrupted) => {
+                break;
+            }
+            Err(ReadlineError::Eof) => {
+                break;
+            }
+            Err(err) => {
+                println!("Error: {:?}", err);
+                break;
+            }
+        }
+    }
+
+    if let Err(err) = std::fs::create_dir_all(data_dir) {
+        report(format_args!("{}: {}", data_dir.display(), err));
+    } else if let Err(err) = rl.save_history(&history) {
+        report(format_args!("{}: {}", history.display(), err));
+    }
+    if let Some(file) = &session.file {
+        save(file, session);
+    }
+}
+
+// lines from -e, a file or stdin, without a prompt. the exit status, which is
+// 1 if any line failed.
+fn run<I: Iterator<Item = String>>(lines: I, session: &mut Session<'_>) -> i32 {
+    let mut block = String::new();
+    for line in lines {
+        let more = feed(&line, &mut block, session);
+        if FAILED.load(Ordering::Relaxed) {
+            return 1;
+        }
+        if !more {
+            return 0;
+        }
+    }
+    if !block.is_empty() {
+        print_stuff(&block, session);
+    }
+    FAILED.load(Ordering::Relaxed) as i32
+}
+
+// set by report, so scripts can stop at the first error
+static FAILED: AtomicBool = AtomicBool::new(false);
+
+// file:line:col of the script statement being run
+static LOCATION: Mutex<Option<String>> = Mutex::new(None);
+
+// --format json, where errors are json on stdout too
+static JSON: AtomicBool = AtomicBool::new(false);
+
+fn report(err: impl fmt::Display) {
+    let location = LOCATION.lock().unwrap();
+    if JSON.load(Ordering::Relaxed) {
+        let mut error = vec![("error", json::Json::String(err.to_string()))];
+        if let Some(location) = location.as_ref() {
+            error.push(("location", json::Json::string(location)));
+        }
+        println!("{}", json::Json::object(error));
+    } else {
+        match location.as_ref() {
+            Some(location) => eprintln!("{}: err: {}", location, err),
+            None => eprintln!("err: {}", err),
+        }
+    }
+    FAILED.store(true, Ordering::Relaxed);
+}
+
+fn locate(path: &str, line: usize, column: usize) {
+    *LOCATION.lock().unwrap() = Some(format!("{}:{}:{}", path, line, column));
+}
+
+// runs a script top to bottom, with errors pointing at where they are. in the
+// repl each statement is echoed and errors don't stop it. false after :quit,
+// or after the first error outside the repl.
+fn run_script(path: &str, source: &str, session: &mut Session<'_>, repl: bool) -> bool {
+    for line in script::lines(source) {
+        if repl {
+            for text in line.text.lines() {
+                println!(">> {}", text);
+            }
+        }
+        locate(path, line.line, line.column);
+
+        let more = if line.text.starts_with(':') {
+            run_command(&line.text, session)
+        } else {
+            let mut scanner = scanner(&line.text, &session.functions);
+            let parsed = parse::parse_statement(&mut scanner).and_then(|_| {
+                match scanner.next_token()?.kind {
+                    scan::TokenKind::End => Ok(()),
+                    _ => Err("expected the end of the statement"),
+                }
+            });
+            match parsed {
+                Ok(()) => print_stuff(&line.text, session),
                 Err(err) => {
-                    println!("Error: {:?}", err);
-                    break;
+                    let (row, column) = line.position(scanner.offset());
+                    locate(path, row, column);
+                    report(err);
+                }
+            }
+            true
+        };
+
+        *LOCATION.lock().unwrap() = None;
+        if !more || (!repl && FAILED.load(Ordering::Relaxed)) {
+            return false;
+        }
+    }
+    true
+}
+
+// everything the repl remembers from one line to the next
+struct Session<'r> {
+    rules: rewrite::RuleSet<'r>,
+    functions: function::Functions<'static>,
+    variables: BTreeMap<String, ast::Expr<'static>>,
+    // the line each function was defined on, oldest first, for :save
+    definitions: Vec<(String, &'static str)>,
+    settings: command::Settings,
+    // where the session is kept between runs
+    file: Option<PathBuf>,
+}
+
+impl<'r> Session<'r> {
+    fn new(rules: rewrite::RuleSet<'r>) -> Session<'r> {
+        Session {
+            rules,
+            functions: function::Functions::new(),
+            variables: BTreeMap::new(),
+            definitions: Vec::new(),
+            settings: command::Settings::default(),
+            file: None,
+        }
+    }
+
+    // lines that get back to this session when they're loaded
+    fn script(&self) -> String {
+        let mut script = format!("{}\n", self.settings);
+        for (name, value) in self.variables.iter() {
+            script.push_str(&format!("{} := {}\n", name, ast::to_infix(value).trim()));
+        }
+        for (_, line) in self.definitions.iter() {
+            script.push_str(line.trim());
+            script.push('\n');
+        }
+        script
+    }
+
+    fn bind<'a>(&'a self, expr: &ast::Expr<'a>) -> ast::Expr<'a> {
+        bind(expr, &self.variables)
+    }
+}
+
+// variables replaced with their values, except where a builtin binds the name
+// itself, like x in integrate(x^2, x, 0, 1) or i in sigma(i=0, 3, i)
+fn bind<'a>(
+    expr: &ast::Expr<'a>,
+    variables: &'a BTreeMap<String, ast::Expr<'static>>,
+) -> ast::Expr<'a> {
+    let bindings: rewrite::Bindings<'a> = variables
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.clone()))
+        .collect();
+    ast::Fold::fold_expr(&mut Bind(bindings), expr.clone())
+}
+
+struct Bind<'a>(rewrite::Bindings<'a>);
+
+impl<'a> ast::Fold<'a> for Bind<'a> {
+    fn fold_variable(&mut self, name: scan::Token<'a>) -> ast::Expr<'a> {
+        match self.0.get(name.lexeme) {
+            Some(expr) => expr.clone(),
+            None => ast::Expr::Variable { name },
+        }
+    }
+
+    fn fold_special_variable(&mut self, name: scan::Token<'a>) -> ast::Expr<'a> {
+        match self.0.get(name.lexeme) {
+            Some(expr) => expr.clone(),
+            None => ast::Expr::SpecialVariable { name },
+        }
+    }
+
+    fn fold_call(&mut self, name: scan::Token<'a>, args: Vec<ast::Expr<'a>>) -> ast::Expr<'a> {
+        use ast::Expr;
+
+        let names = |expr: &Expr<'a>| match expr {
+            Expr::Variable { name } | Expr::SpecialVariable { name } => vec![name.lexeme],
+            Expr::Matrix { rows } => rows
+                .iter()
+                .flatten()
+                .filter_map(|entry| match entry {
+                    Expr::Variable { name } => Some(name.lexeme),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        // the names the builtin binds, the argument they're used in, and
+        // arguments that are kept as they are: variables and words like left
+        let (bound, kept): (Vec<&'a str>, &[usize]) = match (name.lexeme, args.as_slice()) {
+            ("integrate" | "int" | "∫", [body, ..]) => match quadrature::differential(body) {
+                Some((_, var)) => (vec![var], &[]),
+                None => (args.get(1).map(names).unwrap_or_default(), &[1]),
+            },
+            (
+                "diff" | "solve" | "csolve" | "series" | "grad" | "jacobian" | "hessian",
+                [_, var, ..],
+            ) => (names(var), &[1]),
+            ("limit", [_, var, ..]) => (names(var), &[1, 3]),
+            ("root", [_, var, ..]) => (names(var), &[1, 4]),
+            ("sigma", [Expr::Binary { lhs, op, rhs }, end, body])
+                if op.kind == scan::TokenKind::Equal =>
+            {
+                let mut inner = Bind(self.0.clone());
+                for var in names(lhs) {
+                    inner.0.remove(var);
+                }
+                let index = Expr::Binary {
+                    lhs: lhs.clone(),
+                    op: *op,
+                    rhs: Box::new(self.fold_expr(rhs.as_ref().clone())),
+                };
+                let args = vec![
+                    index,
+                    self.fold_expr(end.clone()),
+                    inner.fold_expr(body.clone()),
+                ];
+                return Expr::Call { name, args };
+            }
+            _ => (Vec::new(), &[]),
+        };
+
+        let mut inner = Bind(self.0.clone());
+        for var in bound.iter() {
+            inner.0.remove(var);
+        }
+        let args = args
+            .into_iter()
+            .enumerate()
+            .map(|(i, arg)| match i {
+                _ if kept.contains(&i) && !bound.is_empty() => arg,
+                0 if !bound.is_empty() => inner.fold_expr(arg),
+                _ => self.fold_expr(arg),
+            })
+            .collect();
+        Expr::Call { name, args }
+    }
+}
+
+// a line that was typed or loaded. commands run straight away, and lines
+// ending in ; are collected into a block, like a system of equations spread
+// over several lines. false after :quit.
+fn feed(line: &str, block: &mut String, session: &mut Session<'_>) -> bool {
+    if block.is_empty() && line.trim_start().starts_with(':') {
+        return run_command(line, session);
+    }
+    let code = scan::strip_comment(line);
+    if block.is_empty() && code.trim().is_empty() {
+        return true;
+    }
+
+    block.push_str(code);
+    block.push('\n');
+    if !code.trim_end().ends_with(';') {
+        print_stuff(block, session);
+        block.clear();
+    }
+    true
+}
+
+fn run_command(line: &str, session: &mut Session<'_>) -> bool {
+    match command::parse(line) {
+        Ok(Command::Mode(views)) if views.is_empty() => println!("{}", session.settings),
+        Ok(Command::Mode(views)) => session.settings.views = views.into_iter().collect(),
+        Ok(Command::Help) => println!("{}", command::help()),
+        Ok(Command::Vars) => print_vars(session),
+        Ok(Command::Clear) => {
+            session.functions.clear();
+            session.variables.clear();
+            session.definitions.clear();
+        }
+        Ok(Command::Quit) => return false,
+        Ok(Command::Load(path)) => match std::fs::read_to_string(&path) {
+            Ok(source) => return run_script(&path, &source, session, true),
+            Err(err) => report(format_args!("{}: {}", path, err)),
+        },
+        Ok(Command::Save(path)) => match path.map(PathBuf::from).or_else(|| session.file.clone()) {
+            Some(path) => {
+                if save(&path, session) {
+                    println!("saved {}", path.display());
+                }
+            }
+            None => report("expected a file name"),
+        },
+        Err(err) => report(err),
+    }
+    true
+}
+
+fn save(path: &Path, session: &Session<'_>) -> bool {
+    match storage::write(path, &session.script()) {
+        Ok(()) => true,
+        Err(err) => {
+            report(format_args!("{}: {}", path.display(), err));
+            false
+        }
+    }
+}
+
+// the session file from last time, read back without printing anything
+// the session file's lines that couldn't be restored, with their line numbers
+fn restore(source: &str, session: &mut Session<'_>) -> Vec<(usize, &'static str)> {
+    let mut failed = vec![];
+    for (number, line) in source.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line.trim_start().starts_with(':') {
+            match command::parse(line) {
+                Ok(Command::Mode(views)) => session.settings.views = views.into_iter().collect(),
+                Ok(_) => {}
+                Err(err) => failed.push((number + 1, err)),
+            }
+            continue;
+        }
+        let err = match parse::parse_statement(&mut scanner(line, &session.functions)) {
+            Ok(parse::Statement::Define { .. }) => {
+                learn(line, session);
+                continue;
+            }
+            Ok(parse::Statement::Assign { name, value }) => {
+                let name = name.to_string();
+                match evaluate(value, session).map(|v| v.to_expr()) {
+                    Ok(Some(expr)) => {
+                        session.variables.insert(name, expr);
+                        continue;
+                    }
+                    Ok(None) => "can only assign numbers and matrices",
+                    Err(err) => err,
                 }
             }
+            Ok(_) => "expected a definition or an assignment",
+            Err(err) => err,
+        };
+        failed.push((number + 1, err));
+    }
+    failed
+}
+
+fn print_vars(session: &Session<'_>) {
+    if session.variables.is_empty() && session.functions.is_empty() {
+        println!("no variables or functions");
+    }
+    for (name, value) in session.variables.iter() {
+        println!("{} = {}", name, ast::to_infix(value).trim());
+    }
+    for (name, _) in session.definitions.iter() {
+        let function = &session.functions[name];
+        println!(
+            "{} = {}",
+            function.signature(name),
+            ast::to_infix(&function.body)
+        );
+    }
+}
+
+// definitions outlive the line they were typed on, so the line is leaked and
+// parsed again
+fn define(line: &str, session: &mut Session<'_>) {
+    if let Some(name) = learn(line, session) {
+        let function = &session.functions[name];
+        let signature = function.signature(name);
+        println!("defined: {} = {}", signature, ast::to_infix(&function.body));
+        if session.settings.shows(View::Latex) {
+            println!("latex: {} = {}", signature, latex::to_latex(&function.body));
+        }
+    }
+}
+
+// adds the function defined on the line, and gives its name
+fn learn(line: &str, session: &mut Session<'_>) -> Option<&'static str> {
+    let line: &'static str = Box::leak(line.to_string().into_boxed_str());
+    match parse::parse_statement(&mut scanner(line, &session.functions)) {
+        Ok(parse::Statement::Define { name, function }) => {
+            session.functions.insert(name.to_string(), function);
+            session.definitions.retain(|(defined, _)| defined != name);
+            session.definitions.push((name.to_string(), line));
+            Some(name)
         }
-        rl.save_history("inputs.txt").unwrap();
+        _ => None,
+    }
+}
+
+fn evaluate(value: ast::Expr<'_>, session: &Session<'_>) -> Result<eval::Value, &'static str> {
+    function::inline(value, &session.functions)
+        .map(|value| session.bind(&value))
+        .and_then(builtin::apply)
+        .and_then(|value| eval::eval(&value, &eval::Env::new()))
+}
+
+// x := expr, which has to evaluate to a number or a matrix
+fn assign(name: &str, value: ast::Expr<'_>, session: &mut Session<'_>) {
+    match evaluate(value, session) {
+        Ok(value) => match value.to_expr() {
+            Some(expr) => {
+                match value {
+                    eval::Value::Matrix(m) => println!("{} =\n{}", name, m),
+                    value => println!("{} = {}", name, value),
+                }
+                session.variables.insert(name.to_string(), expr);
+            }
+            None => report("can only assign numbers and matrices"),
+        },
+        Err(err) => report(err),
     }
 }
 
-fn print_stuff(line: &str) {
-    let expr = parse::parse(&mut scan::Scanner::new(line));
-    if let Ok(expr) = expr {
-        //println!("{:#?}", expr);
-        //ast::print_expr(&expr);
+fn scanner<'a>(line: &'a str, functions: &function::Functions<'_>) -> scan::Scanner<'a> {
+    scan::Scanner::with_functions(line, functions.keys().cloned().collect())
+}
+
+fn print_stuff(line: &str, session: &mut Session<'_>) {
+    if JSON.load(Ordering::Relaxed) {
+        print_json(line, session);
+        return;
+    }
+
+    // with sexpr output, what it prints can be read back in too
+    let converted;
+    let line = match sexpr_line(line, &session.settings) {
+        Some(expr) => {
+            converted = ast::to_infix(&expr);
+            converted.as_str()
+        }
+        None => line,
+    };
+    let expr = match parse::parse_statement(&mut scanner(line, &session.functions)) {
+        Ok(parse::Statement::Define { .. }) => {
+            define(line, session);
+            return;
+        }
+        Ok(parse::Statement::Assign { name, value }) => {
+            assign(name, value, session);
+            return;
+        }
+        Ok(parse::Statement::System(equations)) => {
+            print_system(&equations);
+            return;
+        }
+        Ok(parse::Statement::Ode(ode)) => {
+            print_ode(*ode, &session.functions);
+            return;
+        }
+        Ok(parse::Statement::Expr(expr)) => expr,
+        Err(err) => {
+            report(err);
+            return;
+        }
+    };
+    //println!("{:#?}", expr);
+    let settings = &session.settings;
+    if settings.shows(View::Tree) {
+        println!("tree:");
+        ast::print_expr(&expr);
+    }
+    if settings.shows(View::Infix) {
         print!("infix: ");
         ast::print_infix(&expr);
-        print!("\nprefix: ");
+        println!();
+    }
+    if settings.shows(View::Prefix) {
+        print!("prefix: ");
         ast::print_prefix(&expr);
-        print!("\npostfix: ");
+        println!();
+    }
+    if settings.shows(View::Postfix) {
+        print!("postfix: ");
         ast::print_postfix(&expr);
         println!();
-    } else {
-        println!("err: {:?}", expr);
+    }
+    if settings.shows(View::Sexpr) {
+        println!("sexpr: {}", sexpr::to_sexpr(&expr));
+    }
+    if settings.shows(View::Latex) {
+        println!("latex: {}", latex::to_latex(&expr));
+    }
+    if settings.shows(View::Dot) {
+        println!("dot:\n{}", dot::to_dot(&expr));
+    }
+
+    let inlined = match function::inline(expr.clone(), &session.functions) {
+        Ok(inlined) => session.bind(&inlined),
+        Err(err) => {
+            report(err);
+            return;
+        }
+    };
+
+    let result = match builtin::apply(inlined.clone()) {
+        Ok(result) => result,
+        Err(err) => {
+            report(err);
+            return;
+        }
+    };
+
+    // the rewrite is only shown, the value comes from the result as it is
+    let simplified = rewrite::simplify(&session.rules, result.clone(), rewrite::DEFAULT_STEP_LIMIT);
+    if simplified.expr != expr {
+        print!("simplified: ");
+        ast::print_infix(&simplified.expr);
+        println!();
+    }
+    if !simplified.converged {
+        println!("stopped after {} rewrites", simplified.steps);
+    }
+
+    // the remainder isn't part of the result since it can't be evaluated
+    if let ast::Expr::Call { name, args } = &inlined {
+        if name.lexeme == "series" {
+            let args: Result<Vec<_>, _> = args.iter().cloned().map(builtin::apply).collect();
+            if let Ok(series) = args.and_then(|args| series::call(&args)) {
+                print!("with remainder: ");
+                ast::print_infix(&series.with_remainder());
+                println!();
+            }
+        }
+    }
+
+    // numeric integrals come with an error estimate
+    if let ast::Expr::Call { name, args } = &result {
+        if matches!(name.lexeme, "integrate" | "int" | "∫") && args.len() >= 3 {
+            match quadrature::call(args, &eval::Env::new()) {
+                Ok(estimate) if settings.shows(View::Value) => {
+                    println!("value: {}", estimate.value);
+                    println!("estimated error: {:.1e}", estimate.error);
+                }
+                Ok(_) => {}
+                Err(err) => report(err),
+            }
+            return;
+        }
+    }
+
+    match eval::eval(&result, &eval::Env::new()) {
+        Ok(_) if !settings.shows(View::Value) => {}
+        Ok(eval::Value::Matrix(m)) => println!("value:\n{}", m),
+        Ok(value) => println!("value: {}", value),
+        Err(err) if symbolic(&result, err) => {}
+        Err(err) => report(err),
+    }
+
+    print_solutions(&inlined);
+}
+
+// one object for the line, see json.rs. a line that's an expr as json is read
+// like the expression it describes.
+fn print_json(line: &str, session: &mut Session<'_>) {
+    use json::Json;
+
+    let converted;
+    let line = match json::parse(line) {
+        Ok(expr) if line.trim_start().starts_with('{') => match json::to_expr(&expr) {
+            Ok(expr) => {
+                converted = ast::to_infix(&expr);
+                converted.as_str()
+            }
+            Err(err) => return report(err),
+        },
+        _ => line,
+    };
+
+    let tokens = match scanner(line, &session.functions).scan_all() {
+        Ok(tokens) => tokens,
+        Err(err) => return report(err),
+    };
+    let statement = match parse::parse_statement(&mut scanner(line, &session.functions)) {
+        Ok(statement) => statement,
+        Err(err) => return report(err),
+    };
+
+    let mut object = vec![("input", Json::string(line.trim()))];
+    match statement {
+        parse::Statement::Define { name, function } => {
+            object.push(("define", Json::string(name)));
+            object.push((
+                "params",
+                Json::Array(function.params.iter().map(|p| Json::string(p)).collect()),
+            ));
+            object.push(("body", json::from_expr(&function.body)));
+            learn(line, session);
+        }
+        parse::Statement::Assign { name, value } => {
+            let name = name.to_string();
+            let value = match evaluate(value, session) {
+                Ok(value) => value,
+                Err(err) => return report(err),
+            };
+            match value.to_expr() {
+                Some(expr) => session.variables.insert(name.clone(), expr),
+                None => return report("can only assign numbers and matrices"),
+            };
+            object.push(("assign", Json::String(name)));
+            object.push(("value", json::from_value(&value)));
+        }
+        parse::Statement::System(equations) => {
+            let equations = equations.iter().map(json::from_expr).collect();
+            object.push(("equations", Json::Array(equations)));
+        }
+        parse::Statement::Ode(ode) => {
+            object.push((
+                "ode",
+                Json::object(vec![
+                    ("y", Json::string(ode.y.lexeme)),
+                    ("x", Json::string(ode.x.lexeme)),
+                    ("rhs", json::from_expr(&ode.rhs)),
+                ]),
+            ));
+        }
+        parse::Statement::Expr(expr) => {
+            let result = function::inline(expr.clone(), &session.functions)
+                .map(|inlined| session.bind(&inlined))
+                .and_then(builtin::apply);
+            let result = match result {
+                Ok(result) => result,
+                Err(err) => return report(err),
+            };
+            let simplified =
+                rewrite::simplify(&session.rules, result.clone(), rewrite::DEFAULT_STEP_LIMIT).expr;
+            let value = match eval::eval(&result, &eval::Env::new()) {
+                Ok(value) => json::from_value(&value),
+                Err(err) if symbolic(&result, err) => Json::Null,
+                Err(err) => return report(err),
+            };
+
+            object.push(("tokens", json::tokens(&tokens)));
+            object.push(("expr", json::from_expr(&expr)));
+            object.push(("simplified", json::from_expr(&simplified)));
+            object.push(("value", value));
+        }
+    }
+    println!("{}", Json::object(object));
+}
+
+// solve equations and inequalities typed on their own, or solve(eq, var) and
+// csolve(eq, var) for complex roots too
+fn print_solutions(expr: &ast::Expr<'_>) {
+    let (equation, var, complex) = match expr {
+        ast::Expr::Binary { op, .. } | ast::Expr::Unary { op, .. } if is_condition(op.kind) => {
+            // conditions without variables were already evaluated
+            match solve::default_variable(expr) {
+                Some(var) => (expr, Some(var), false),
+                None => return,
+            }
+        }
+        ast::Expr::Call { name, args } if name.lexeme == "solve" || name.lexeme == "csolve" => {
+            let var = match args.get(1) {
+                Some(ast::Expr::Variable { name }) => Some(name.lexeme),
+                Some(_) => {
+                    report(format_args!(
+                        "{} needs a variable to solve for",
+                        name.lexeme
+                    ));
+                    return;
+                }
+                None => args.first().and_then(solve::default_variable),
+            };
+            match args.first() {
+                Some(equation) => (equation, var, name.lexeme == "csolve"),
+                None => return,
+            }
+        }
+        _ => return,
+    };
+
+    let var = match var {
+        Some(var) => var,
+        None => {
+            report("nothing to solve for");
+            return;
+        }
+    };
+
+    if let ast::Expr::Binary { op, .. } | ast::Expr::Unary { op, .. } = equation {
+        if op.kind != scan::TokenKind::Equal && is_condition(op.kind) {
+            match interval::solve_inequality(equation, var) {
+                Ok(set) => {
+                    println!("interval: {}", set);
+                    println!("set: {}", set.to_set_builder(var));
+                }
+                Err(err) => report(err),
+            }
+            return;
+        }
+    }
+
+    match solve::solve(equation, var, complex) {
+        Ok(solve::Solutions::All) => println!("every {} is a solution", var),
+        Ok(solve::Solutions::Roots(roots)) if roots.is_empty() => println!("no solution"),
+        Ok(solve::Solutions::Roots(roots)) => {
+            for root in roots.iter() {
+                let relation = if root.exact.is_some() { "=" } else { "≈" };
+                println!("{} {} {}", var, relation, root);
+            }
+        }
+        Err(err) => report(err),
+    }
+}
+
+fn print_system(equations: &[ast::Expr<'_>]) {
+    for equation in equations.iter() {
+        println!("equation: {}", ast::to_infix(equation));
+    }
+
+    match linear::solve_system(equations) {
+        Ok(linear::SystemSolution::Unique(values)) => {
+            for (var, value) in values.iter() {
+                println!("{} = {}", var, value);
+            }
+        }
+        Ok(linear::SystemSolution::Parametric { solved, free }) => {
+            for (var, value) in solved.iter() {
+                println!("{} = {}", var, ast::to_infix(value).trim());
+            }
+            for var in free.iter() {
+                println!("{} is free", var);
+            }
+        }
+        Ok(linear::SystemSolution::Inconsistent) => println!("no solution"),
+        Err(err) => report(err),
+    }
+}
+
+fn print_ode(mut ode: ode::Ode<'_>, functions: &function::Functions<'static>) {
+    println!(
+        "ode: d{}/d{} = {}",
+        ode.y.lexeme,
+        ode.x.lexeme,
+        ast::to_infix(&ode.rhs).trim()
+    );
+    ode.rhs = match function::inline(ode.rhs, functions) {
+        Ok(rhs) => rhs,
+        Err(err) => {
+            report(err);
+            return;
+        }
+    };
+
+    let print_solution = |label: &str, solution: &ode::Solution<'_>| match &solution.explicit {
+        Some(y) => println!("{}: {} = {}", label, ode.y.lexeme, ast::to_infix(y).trim()),
+        None => println!(
+            "{}: {} = {}",
+            label,
+            ast::to_infix(&solution.lhs).trim(),
+            ast::to_infix(&solution.rhs).trim()
+        ),
+    };
+    match ode::solve(&ode) {
+        Ok(general) => {
+            let method = match general.method {
+                ode::Method::Separable => "separable",
+                ode::Method::Linear => "linear",
+            };
+            println!("method: {}", method);
+            print_solution("general", &general);
+            if let Some((x0, y0)) = &ode.initial {
+                match ode::particular(&ode, &general, x0, y0) {
+                    Ok(particular) => print_solution("particular", &particular),
+                    Err(err) => report(err),
+                }
+            }
+        }
+        Err(err) => report(err),
+    }
+
+    // a table of values needs somewhere to start and stop
+    let (Some((x0, y0)), Some(end)) = (&ode.initial, &ode.end) else {
+        return;
+    };
+    let env = eval::Env::new();
+    let number = |expr: &ast::Expr<'_>| eval::eval_number(expr, &env);
+    let rows = (|| {
+        let step = match &ode.step {
+            Some(step) => Some(number(step)?),
+            None => None,
+        };
+        ode::table(&ode, number(x0)?, number(y0)?, number(end)?, step, &env)
+    })();
+    match rows {
+        Ok(rows) => {
+            match &ode.step {
+                Some(step) => println!("rk4, h = {}:", ast::to_infix(step).trim()),
+                None => println!("rk45:"),
+            }
+            println!("{:>12}  {}", ode.x.lexeme, ode.y.lexeme);
+            for (x, y) in rows.iter() {
+                // so x doesn't come out as 0.30000000000000004
+                println!("{:>12}  {}", (x * 1e12).round() / 1e12, y);
+            }
+        }
+        Err(err) => report(err),
+    }
+}
+
+fn is_condition(kind: scan::TokenKind) -> bool {
+    use scan::TokenKind::*;
+    kind.is_comparison() || matches!(kind, And | Or | Not)
+}
+
+// a line like (+ x 1), if s-expressions are being shown. anything that isn't
+// a whole s-expression is read as usual.
+fn sexpr_line<'a>(line: &'a str, settings: &command::Settings) -> Option<ast::Expr<'a>> {
+    if !settings.shows(View::Sexpr) || !line.trim_start().starts_with('(') {
+        return None;
+    }
+    sexpr::parse(line).ok()
+}
+
+// eval errors that just mean the expression is symbolic rather than wrong:
+// an unbound variable, or solve, which has its own output
+fn symbolic(expr: &ast::Expr<'_>, err: &str) -> bool {
+    match expr {
+        _ if err == "unbound variable" => true,
+        ast::Expr::Call { name, .. } => matches!(name.lexeme, "solve" | "csolve"),
+        _ => false,
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::{
-        ast::Expr,
-        parse::parse,
+        ast::{self, Expr, Fold, Visitor},
+        builtin, cli, command, dot, eval, function, helper, interval, json, latex, linear, matrix,
+        ode,
+        parse::{parse, parse_statement, Statement},
+        quadrature,
+        rewrite::{simplify, RuleSet, DEFAULT_RULES},
+        root,
         scan::{Scanner, Token, TokenKind},
+        script, series, sexpr, solve, storage,
     };
 
     #[test]
@@ -144,6 +1054,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::redundant_pattern_matching)]
     fn parse2() {
         let exprs = [
             "f(x)=3x^2-2x+1",
@@ -160,6 +1071,1328 @@ mod test {
         }
     }
 
+    #[test]
+    fn visit_counts_variables() {
+        struct Count(usize);
+        impl<'a> Visitor<'a> for Count {
+            fn visit_variable(&mut self, _name: &Token<'a>) {
+                self.0 += 1;
+            }
+        }
+
+        let expr = parse(&mut Scanner::new("sin(x)^2 + x*y - 3")).unwrap();
+        let mut count = Count(0);
+        count.visit_expr(&expr);
+        assert_eq!(count.0, 3);
+    }
+
+    #[test]
+    fn fold_renames_variables() {
+        struct Rename;
+        impl<'a> Fold<'a> for Rename {
+            fn fold_variable(&mut self, _name: Token<'a>) -> Expr<'a> {
+                Expr::Variable {
+                    name: Token::new(TokenKind::Variable, "z"),
+                }
+            }
+        }
+
+        let expr = parse(&mut Scanner::new("(x+1)(y-2)")).unwrap();
+        let renamed = Rename.fold_expr(expr);
+        assert_eq!(renamed, parse(&mut Scanner::new("(z+1)(z-2)")).unwrap());
+        assert_eq!(ast::to_infix(&renamed), "(z + 1) * (z - 2)");
+    }
+
+    #[test]
+    fn rewrite_inside_sum() {
+        let rules = RuleSet::parse(DEFAULT_RULES).unwrap();
+        let expr = parse(&mut Scanner::new("cos(a+b)^2 + y + sin(a+b)^2")).unwrap();
+        let result = simplify(&rules, expr, 10);
+        assert!(result.converged);
+        assert_eq!(result.expr, parse(&mut Scanner::new("y + 1")).unwrap());
+    }
+
+    #[test]
+    fn rewrite_commutative_product() {
+        let rules = RuleSet::parse("ln(a*b) -> ln(a) + ln(b)").unwrap();
+        let expr = parse(&mut Scanner::new("ln(2*x*y)")).unwrap();
+        let result = simplify(&rules, expr, 10);
+        assert_eq!(
+            result.expr,
+            parse(&mut Scanner::new("ln(2) + (ln(x) + ln(y))")).unwrap()
+        );
+    }
+
+    #[test]
+    fn rewrite_keeps_values() {
+        let rules = RuleSet::parse(DEFAULT_RULES).unwrap();
+        let value = |input| {
+            let expr = parse(&mut Scanner::new(input)).unwrap();
+            eval::eval(&simplify(&rules, expr, 10).expr, &eval::Env::new())
+        };
+        assert_eq!(value("ln((-2)^2)"), Ok(eval::Value::Number(4f64.ln())));
+        assert_eq!(value("ln((-2)*(-3))"), Ok(eval::Value::Number(6f64.ln())));
+        assert_eq!(
+            value("[1, 2; 3, 4]^0"),
+            Ok(eval::Value::Matrix(matrix::Matrix::identity(2)))
+        );
+        assert!(matches!(
+            value("[1, 2; 3, 4]*0"),
+            Ok(eval::Value::Matrix(_))
+        ));
+
+        // patterns reach into piecewise functions and matrices
+        let rules = RuleSet::parse("[a, b] -> a + b\n{a if c; b otherwise} -> b").unwrap();
+        let expr = parse(&mut Scanner::new("[x, 1] * {x if x > 0; 2 otherwise}")).unwrap();
+        assert_eq!(
+            simplify(&rules, expr, 10).expr,
+            parse(&mut Scanner::new("(x + 1) * 2")).unwrap()
+        );
+    }
+
+    #[test]
+    fn rewrite_step_limit() {
+        let rules = RuleSet::parse("# loops forever\na + b -> b + a").unwrap();
+        let expr = parse(&mut Scanner::new("x + y")).unwrap();
+        let result = simplify(&rules, expr, 5);
+        assert!(!result.converged);
+        assert_eq!(result.steps, 5);
+    }
+
+    #[test]
+    fn expand_product() {
+        let expr = parse(&mut Scanner::new("(x^2+1)(x^2-2)")).unwrap();
+        assert_eq!(
+            expr.expand().unwrap(),
+            parse(&mut Scanner::new("x^4 - x^2 - 2")).unwrap()
+        );
+    }
+
+    #[test]
+    fn expand_binomial() {
+        let expr = parse(&mut Scanner::new("(x-y)^3")).unwrap();
+        assert_eq!(
+            ast::to_infix(&expr.expand().unwrap()),
+            "(((x ^ 3) - (3 * ((x ^ 2) * y))) + (3 * (x * (y ^ 2)))) - (y ^ 3)"
+        );
+    }
+
+    #[test]
+    fn expand_canonical_order() {
+        let expr = parse(&mut Scanner::new("y x + 1/2 + x^2 + sin(x) x/4")).unwrap();
+        assert_eq!(
+            expr.expand().unwrap(),
+            parse(&mut Scanner::new("x^2 + x*y + x*sin(x)/4 + 1/2")).unwrap()
+        );
+    }
+
+    #[test]
+    fn factor_quartic() {
+        let expr = parse(&mut Scanner::new("x^4-x^2-2")).unwrap();
+        assert_eq!(
+            ast::to_infix(&expr.factor().unwrap()),
+            "((x ^ 2) + 1) * ((x ^ 2) - 2)"
+        );
+    }
+
+    #[test]
+    fn factor_irreducible() {
+        let expr = parse(&mut Scanner::new("3x^2-2x+1")).unwrap();
+        assert_eq!(expr.factor().unwrap(), expr.expand().unwrap());
+
+        let expr = parse(&mut Scanner::new("x^4+4")).unwrap();
+        assert_eq!(
+            ast::to_infix(&expr.factor().unwrap()),
+            "(((x ^ 2) + (2 * x)) + 2) * (((x ^ 2) - (2 * x)) + 2)"
+        );
+    }
+
+    #[test]
+    fn factor_repeated_and_common() {
+        let expr = parse(&mut Scanner::new("-2x^4 + 6x^3 - 6x^2 + 2x")).unwrap();
+        assert_eq!(
+            expr.factor().unwrap(),
+            parse(&mut Scanner::new("-(2 * (x * (x - 1)^3))")).unwrap()
+        );
+
+        let expr = parse(&mut Scanner::new("x^2/2 - 1/8")).unwrap();
+        assert_eq!(
+            expr.factor().unwrap(),
+            parse(&mut Scanner::new("((2x + 1)(2x - 1))/8")).unwrap()
+        );
+    }
+
+    #[test]
+    fn exact_overflow() {
+        let expr = parse(&mut Scanner::new("18446744073709551615 x + x")).unwrap();
+        assert_eq!(
+            ast::to_infix(&expr.expand().unwrap()),
+            "18446744073709552000.0 * x"
+        );
+
+        let expr = parse(&mut Scanner::new("(100000000000x + 1)^4")).unwrap();
+        assert_eq!(expr.expand(), Err("number too large for exact arithmetic"));
+        let expr = parse(&mut Scanner::new("(2x+1)^64")).unwrap();
+        assert!(expr.expand().is_ok());
+    }
+
+    #[test]
+    fn factor_past_the_search_bounds() {
+        let factor = |input| {
+            let expr = parse(&mut Scanner::new(input)).unwrap();
+            ast::to_infix(&expr.factor().unwrap())
+        };
+        assert_eq!(
+            factor("x^5 - x^4 + 10000000000000 x - 10000000000000"),
+            "(x - 1) * ((x ^ 4) + 10000000000000)"
+        );
+        assert_eq!(factor("x^4 - 20000000000000"), "(x ^ 4) - 20000000000000");
+        // the degree 60 part isn't irreducible, but splitting it is past the
+        // search bound so it's left whole
+        assert_eq!(
+            factor("x^64 - 1"),
+            "(((x + 1) * (x - 1)) * ((x ^ 2) + 1)) * ((((((((((((((((x ^ 60) + (x ^ 56)) + \
+             (x ^ 52)) + (x ^ 48)) + (x ^ 44)) + (x ^ 40)) + (x ^ 36)) + (x ^ 32)) + (x ^ 28)) + \
+             (x ^ 24)) + (x ^ 20)) + (x ^ 16)) + (x ^ 12)) + (x ^ 8)) + (x ^ 4)) + 1)"
+        );
+        // square-free, so the exact gcds that overflow aren't needed. when they
+        // are, the input is left as it is
+        assert_eq!(factor("x^30 + x + 1"), "((x ^ 30) + x) + 1");
+        assert_eq!(factor("x^40 + x + 1"), "((x ^ 40) + x) + 1");
+        assert_eq!(factor("(x^30 + x + 1)^2"), "(((x ^ 30) + x) + 1) ^ 2");
+    }
+
+    fn roots(input: &str, var: &str, complex: bool) -> Vec<String> {
+        let expr = parse(&mut Scanner::new(input)).unwrap();
+        match solve::solve(&expr, var, complex).unwrap() {
+            solve::Solutions::Roots(roots) => roots.iter().map(|r| r.to_string()).collect(),
+            solve::Solutions::All => vec!["all".to_string()],
+        }
+    }
+
+    #[test]
+    fn solve_linear_and_quadratic() {
+        assert_eq!(roots("3x + 1 = 2 - x", "x", false), vec!["1 / 4"]);
+        assert_eq!(roots("x^2 - 5x + 6 = 0", "x", false), vec!["2", "3"]);
+        assert_eq!(
+            roots("x^2 = 2", "x", false),
+            vec![
+                "-sqrt(2,) ≈ -1.4142135623730951",
+                "sqrt(2,) ≈ 1.4142135623730951"
+            ]
+        );
+        assert_eq!(roots("x^2 + 1 = 0", "x", false), Vec::<String>::new());
+        assert_eq!(roots("x^2 + 1 = 0", "x", true), vec!["-i ≈ -1i", "i ≈ 1i"]);
+        assert_eq!(roots("x + 1 = x + 1", "x", false), vec!["all"]);
+        assert_eq!(roots("x + 1 = x", "x", false), Vec::<String>::new());
+    }
+
+    #[test]
+    fn solve_symbolic() {
+        assert_eq!(roots("a x + b = c", "x", false), vec!["( -b + c) / a"]);
+    }
+
+    #[test]
+    fn solve_higher_degree() {
+        assert_eq!(roots("x^3 = x", "x", false), vec!["-1", "0", "1"]);
+        let numeric = roots("x^5 - x - 1 = 0", "x", false);
+        assert_eq!(numeric.len(), 1);
+        assert!(numeric[0].starts_with("1.1673"));
+
+        // degree 30, and squared so it's too big to factor exactly
+        for input in ["x^30 + x - 1 = 0", "(x^30 + x - 1)^2 = 0"] {
+            let numeric = roots(input, "x", false);
+            assert_eq!(numeric.len(), 2, "{}", input);
+            assert!(numeric[0].starts_with("-1.02377712786"), "{}", input);
+            assert!(numeric[1].starts_with("0.91946135001"), "{}", input);
+        }
+        assert!(inequality("x^30 + x > 1")
+            .0
+            .ends_with("∪ (0.9194613500110681, ∞)"));
+    }
+
+    #[test]
+    fn solve_and_evaluate_limits() {
+        let expr = parse(&mut Scanner::new("x^100 = 1")).unwrap();
+        assert_eq!(
+            solve::solve(&expr, "x", false).unwrap_err(),
+            "the polynomial's degree is too high, it can be up to 64"
+        );
+
+        let value = |input| {
+            let expr = parse(&mut Scanner::new(input)).unwrap();
+            eval::eval_number(&expr, &eval::Env::new())
+        };
+        assert_eq!(value("200!"), Ok(f64::INFINITY));
+        assert!(value("170!").unwrap().is_finite());
+        assert_eq!(
+            value("sigma(i=0, 100000000000, i)"),
+            Err("sigma range is too big, it can have up to 10000000 terms")
+        );
+        assert_eq!(value("sigma(i=1, 100, i)"), Ok(5050.0));
+    }
+
+    fn inequality(input: &str) -> (String, String) {
+        let expr = parse(&mut Scanner::new(input)).unwrap();
+        let set = interval::solve_inequality(&expr, "x").unwrap();
+        (set.to_string(), set.to_set_builder("x"))
+    }
+
+    #[test]
+    fn inequality_quadratic() {
+        assert_eq!(
+            inequality("x^2 - 4 < 0"),
+            ("(-2, 2)".to_string(), "{x | -2 < x < 2}".to_string())
+        );
+        assert_eq!(
+            inequality("x^2 >= 2"),
+            (
+                "(-∞, -sqrt(2,)] ∪ [sqrt(2,), ∞)".to_string(),
+                "{x | x ≤ -sqrt(2,) or sqrt(2,) ≤ x}".to_string()
+            )
+        );
+        assert_eq!(
+            inequality("x^2 + 1 < 0"),
+            ("∅".to_string(), "∅".to_string())
+        );
+        assert_eq!(inequality("x^2 + 1 > 0").1, "{x | x ∈ ℝ}");
+    }
+
+    #[test]
+    fn inequality_sign_flip() {
+        assert_eq!(inequality("-2x + 1 > 5").0, "(-∞, -2)");
+        assert_eq!(inequality("3 - x <= 1").0, "[2, ∞)");
+        assert_eq!(inequality("(x-1)^2 <= 0").0, "{1}");
+        assert_eq!(inequality("(x-1)^2 != 0").0, "(-∞, 1) ∪ (1, ∞)");
+        assert_eq!(inequality("x^3 - x >= 0").0, "[-1, 0] ∪ [1, ∞)");
+    }
+
+    #[test]
+    fn chained_comparison() {
+        let chained = parse(&mut Scanner::new("a < b <= c")).unwrap();
+        let spelled = parse(&mut Scanner::new("a < b and b <= c")).unwrap();
+        assert_eq!(chained, spelled);
+
+        let symbols = parse(&mut Scanner::new("¬a ∨ b ∧ c")).unwrap();
+        let words = parse(&mut Scanner::new("not a or b and c")).unwrap();
+        assert_eq!(ast::to_prefix(&symbols), "∨ ¬a (∧ b c )");
+        assert_eq!(ast::to_prefix(&words), "or not a (and b c )");
+        assert_eq!(ast::to_infix(&words), " not a or (b and c)");
+
+        let not = parse(&mut Scanner::new("not x < 1 or x > 2")).unwrap();
+        assert_eq!(ast::to_prefix(&not), "or not < x 1 (> x 2 )");
+    }
+
+    #[test]
+    fn boolean_eval() {
+        let eval = |input: &str| {
+            let expr = parse(&mut Scanner::new(input)).unwrap();
+            eval::eval(&expr, &eval::Env::new()).unwrap()
+        };
+        assert_eq!(eval("1 < 2 < 3"), eval::Value::Bool(true));
+        assert_eq!(eval("1 < 3 < 2"), eval::Value::Bool(false));
+        assert_eq!(eval("0.1 + 0.2 = 0.3"), eval::Value::Bool(true));
+        assert_eq!(eval("not 1 > 2 and 2 >= 2"), eval::Value::Bool(true));
+        assert_eq!(eval("1 = 2 ∨ 3 != 3"), eval::Value::Bool(false));
+        assert_eq!(eval("2^3 - 1"), eval::Value::Number(7.0));
+    }
+
+    #[test]
+    fn inequality_connectives() {
+        assert_eq!(inequality("0 < x < 2").0, "(0, 2)");
+        assert_eq!(inequality("-1 <= x < 3 and x != 0").0, "[-1, 0) ∪ (0, 3)");
+        assert_eq!(inequality("x < -1 or x >= 1").0, "(-∞, -1) ∪ [1, ∞)");
+        assert_eq!(inequality("not (x^2 < 4)").0, "(-∞, -2] ∪ [2, ∞)");
+        assert_eq!(inequality("x^2 = 4 or x = 0").0, "{-2} ∪ {0} ∪ {2}");
+    }
+
+    fn define(input: &str) -> (&str, function::Function<'_>) {
+        match parse_statement(&mut Scanner::new(input)).unwrap() {
+            Statement::Define { name, function } => (name, function),
+            _ => panic!("not a definition"),
+        }
+    }
+
+    #[test]
+    fn piecewise_eval() {
+        let (name, f) = define("f(x) = { x^2 if x < 0; 2x otherwise }");
+        assert_eq!(name, "f");
+        assert_eq!(f.signature(name), "f(x)");
+
+        let mut functions = function::Functions::new();
+        functions.insert(name.to_string(), f);
+        let eval = |input: &str| {
+            let mut scanner = Scanner::with_functions(input, vec!["f".to_string()]);
+            let expr = function::inline(parse(&mut scanner).unwrap(), &functions).unwrap();
+            eval::eval(&expr, &eval::Env::new())
+        };
+        assert_eq!(eval("f(-3)"), Ok(eval::Value::Number(9.0)));
+        assert_eq!(eval("f(0)"), Ok(eval::Value::Number(0.0)));
+        assert_eq!(eval("f(1) + f(2)"), Ok(eval::Value::Number(6.0)));
+
+        let partial = parse(&mut Scanner::new("{ 1 if x > 0; }")).unwrap();
+        assert!(eval::eval(&partial, &eval::Env::new().with("x", -1.0)).is_err());
+        assert!(parse_statement(&mut Scanner::new("(x)(y) = 2")).is_ok());
+        assert!(matches!(
+            parse_statement(&mut Scanner::new("a(b + 1) = 2")),
+            Ok(Statement::Expr(_))
+        ));
+    }
+
+    #[test]
+    fn piecewise_derivative_and_latex() {
+        let (_, f) = define("f(x) = { x^2 if x < 0; 2x otherwise }");
+        let derivative = f.body.derivative("x").unwrap();
+        assert_eq!(
+            ast::to_infix(&derivative),
+            "{(2 * x) if (x < 0); 2 otherwise}"
+        );
+        assert_eq!(
+            latex::to_latex(&f.body),
+            "\\begin{cases} x^{2} & \\text{if } x < 0 \\\\ 2x & \\text{otherwise} \\end{cases}"
+        );
+    }
+
+    fn system(input: &str) -> linear::SystemSolution<'_> {
+        match parse_statement(&mut Scanner::new(input)).unwrap() {
+            Statement::System(equations) => linear::solve_system(&equations).unwrap(),
+            _ => panic!("not a system"),
+        }
+    }
+
+    #[test]
+    fn linear_system_unique() {
+        match system("x + y = 3; x - y = 1;") {
+            linear::SystemSolution::Unique(values) => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(|(var, value)| format!("{} = {}", var, value))
+                    .collect();
+                assert_eq!(values, vec!["x = 2", "y = 1"]);
+            }
+            other => panic!("{:?}", other),
+        }
+        match system("2a + 3b = 1\n; a/2 - b = 1/3") {
+            linear::SystemSolution::Unique(values) => {
+                assert_eq!(values[0].1.to_string(), "4/7");
+                assert_eq!(values[1].1.to_string(), "-1/21");
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn linear_system_parametric_and_inconsistent() {
+        match system("x + y + z = 1; x - y = 3") {
+            linear::SystemSolution::Parametric { solved, free } => {
+                let solved: Vec<String> = solved
+                    .iter()
+                    .map(|(var, value)| format!("{} = {}", var, ast::to_infix(value).trim()))
+                    .collect();
+                assert_eq!(solved, vec!["x = -(z / 2) + 2", "y = -(z / 2) - 1"]);
+                assert_eq!(free, vec!["z"]);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(
+            system("x + y = 1; 2x + 2y = 3"),
+            linear::SystemSolution::Inconsistent
+        ));
+
+        let equations = vec![parse(&mut Scanner::new("x^2 + y = 1")).unwrap()];
+        assert!(linear::solve_system(&equations).is_err());
+    }
+
+    fn matrix_value(input: &str) -> Result<eval::Value, &'static str> {
+        eval::eval(&parse(&mut Scanner::new(input)).unwrap(), &eval::Env::new())
+    }
+
+    #[test]
+    fn numeric_roots() {
+        let value = |input| matrix_value(input).and_then(|value| value.number());
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-10;
+
+        // brent with a bracket, newton from a guess
+        assert!(close(
+            value("root(cos(x) = x, x, 0, 1)").unwrap(),
+            0.7390851332151607
+        ));
+        assert!(close(value("root(x^2 - 2, x, 1)").unwrap(), 2f64.sqrt()));
+        assert_eq!(
+            value("root(x^2 + 1, x, 0, 1)"),
+            Err("the function has the same sign at both ends of the bracket")
+        );
+        assert_eq!(
+            value("root(x^2 + 1, x, 0)"),
+            Err("newton's method hit a point where the derivative is zero")
+        );
+        assert!(close(
+            value("root(cos(x) = x, x, 0, 1, bisection)").unwrap(),
+            0.7390851332151607
+        ));
+        assert_eq!(
+            value("root(x^2 - 2, x, 0, 2, newton)"),
+            Err("a bracket is narrowed down by brent or bisection")
+        );
+
+        let options = root::Options::default();
+        let f = |x: f64| Ok(x * x * x - x - 2.0);
+        let bisected = root::bisection(f, 1.0, 2.0, &options).unwrap();
+        let brent = root::brent(f, 1.0, 2.0, &options).unwrap();
+        assert!(close(bisected.value, brent.value));
+        assert!(brent.iterations < bisected.iterations);
+
+        let few = root::Options {
+            max_iterations: 3,
+            ..options
+        };
+        assert!(root::bisection(f, 1.0, 2.0, &few).is_err());
+    }
+
+    #[test]
+    fn numeric_integrals() {
+        let value = |input| matrix_value(input).and_then(|value| value.number());
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+        assert!(close(value("integrate(x^2, x, 0, 1)").unwrap(), 1.0 / 3.0));
+        assert!(close(value("int(x^2 dx, 0, 1)").unwrap(), 1.0 / 3.0));
+        assert!(close(value("∫(sin(theta) dtheta, 0, pi)").unwrap(), 2.0));
+        assert!(close(value("integrate(1/x^2, x, 1, inf)").unwrap(), 1.0));
+        assert!(close(
+            value("integrate(e^(-(x^2)), x, -∞, ∞)").unwrap(),
+            std::f64::consts::PI.sqrt()
+        ));
+        assert_eq!(
+            value("integrate(1/x, x, 1, inf)"),
+            Err("integral didn't converge, it may diverge")
+        );
+        assert_eq!(
+            latex::to_latex(&parse(&mut Scanner::new("int(x^2 dx, 0, 1)")).unwrap()),
+            "\\int_{0}^{1} x^{2} \\, dx"
+        );
+
+        let options = quadrature::Options::default();
+        let f = |x: f64| Ok(x.sin());
+        let simpson = quadrature::simpson(f, 0.0, 1.0, &options).unwrap();
+        let kronrod = quadrature::gauss_kronrod(f, 0.0, 1.0, &options).unwrap();
+        assert!(close(simpson.value, 1.0 - 1f64.cos()));
+        assert!(close(kronrod.value, 1.0 - 1f64.cos()));
+        assert!(simpson.error < 1e-9 && kronrod.error < 1e-9);
+
+        // the integrand underflows to 0 long before the substitution's ends
+        let f = |x: f64| Ok((-x * x).exp());
+        let pi = std::f64::consts::PI;
+        let inf = f64::INFINITY;
+        let whole = quadrature::gauss_kronrod(f, -inf, inf, &options).unwrap();
+        let half = quadrature::gauss_kronrod(f, 0.0, inf, &options).unwrap();
+        assert!(close(whole.value, pi.sqrt()));
+        assert!(close(half.value, pi.sqrt() / 2.0));
+        let step = |x: f64| Ok(if x.abs() < 1.0 { 1.0 } else { 0.0 });
+        let step = quadrature::gauss_kronrod(step, -inf, inf, &options).unwrap();
+        assert!((step.value - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn antiderivatives() {
+        let integrate =
+            |input: &'static str| builtin::apply(parse(&mut Scanner::new(input)).unwrap()).unwrap();
+
+        // differentiating the result gives back the integrand
+        for (integrand, var) in [
+            ("3x^2 - 2x + 5", "x"),
+            ("sin(2x) + e^(3x)", "x"),
+            ("x e^x", "x"),
+            ("x^2 cos(x)", "x"),
+            ("x ln(x)", "x"),
+            ("2x cos(x^2)", "x"),
+            ("x / (x^2 + 1)", "x"),
+            ("(x^3 + 1) / (x^2 - 3x + 2)", "x"),
+            ("1 / (x^3 - x^2)", "x"),
+            ("sec(theta)^2 + csc(theta)", "theta"),
+            ("sqrt(2x + 1) + 2^x", "x"),
+            ("sin(x) cos(x)", "x"),
+            ("sin(x)^2 + cos(3x)^2", "x"),
+            ("(x + 3) / (x^2 + 2x + 5)", "x"),
+            ("1 / (2x^2 + 3)", "x"),
+        ] {
+            let input = Box::leak(format!("integrate({}, {})", integrand, var).into_boxed_str());
+            let result = match integrate(input) {
+                Expr::Binary { lhs, rhs, .. } if ast::to_infix(&rhs) == "C" => *lhs,
+                other => panic!("{} wasn't integrated: {}", input, ast::to_infix(&other)),
+            };
+            let derivative = result.derivative(var).unwrap();
+            let integrand = parse(&mut Scanner::new(integrand)).unwrap();
+            for x in [2.5, 3.7] {
+                let env = eval::Env::new().with(var, x);
+                let expected = eval::eval_number(&integrand, &env).unwrap();
+                let actual = eval::eval_number(&derivative, &env).unwrap();
+                assert!((expected - actual).abs() < 1e-9, "{}", input);
+            }
+        }
+
+        assert_eq!(
+            ast::to_infix(&integrate("int(x^2 dx)")),
+            "((x ^ 3) / 3) + C"
+        );
+        assert_eq!(
+            ast::to_infix(&integrate("integrate(1/(x^2 + 1), x)")),
+            "arctan(x,) + C"
+        );
+        // no elementary antiderivative
+        assert_eq!(
+            builtin::apply(parse(&mut Scanner::new("integrate(e^(x^2), x)")).unwrap()),
+            Err("couldn't integrate that, it may have no elementary antiderivative")
+        );
+        assert_eq!(
+            latex::to_latex(&parse(&mut Scanner::new("integrate(e^(x^2), x)")).unwrap()),
+            "\\int e^{x^{2}} \\, dx"
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let limit = |input: &'static str| {
+            builtin::apply(parse(&mut Scanner::new(input)).unwrap())
+                .map(|expr| ast::to_infix(&expr))
+        };
+
+        assert_eq!(limit("limit(sin(x)/x, x, 0)"), Ok("1".into()));
+        // more l'hôpital steps than the depth limit, but the degree drops each time
+        assert_eq!(limit("limit(e^x/x^10, x, inf)"), Ok("∞".into()));
+        assert_eq!(limit("limit(x^20 e^(-x), x, inf)"), Ok("0".into()));
+        assert_eq!(limit("limit((x^2 - 1)/(x - 1), x, 1)"), Ok("2".into()));
+        assert_eq!(limit("limit((1 - cos(x))/x^2, x, 0)"), Ok("1 / 2".into()));
+        assert_eq!(
+            limit("limit((3x^2 + 1)/(2x^2 - x), x, inf)"),
+            Ok("3 / 2".into())
+        );
+        assert_eq!(limit("limit((1 + 1/x)^x, x, ∞)"), Ok("e".into()));
+        assert_eq!(limit("limit(x ln(x), x, 0, right)"), Ok("0".into()));
+        assert_eq!(limit("limit(x^x, x, 0, right)"), Ok("1".into()));
+        assert_eq!(limit("limit(e^x / x^2, x, inf)"), Ok("∞".into()));
+        assert_eq!(limit("limit(x^3 - x, x, -∞)"), Ok(" -∞".into()));
+        assert_eq!(limit("limit(1/x, x, 0, left)"), Ok(" -∞".into()));
+        assert_eq!(
+            limit("limit(1/x, x, 0)"),
+            Err("limit does not exist: the left and right limits differ")
+        );
+        assert_eq!(
+            limit("limit(sin(x), x, inf)"),
+            Err("limit does not exist: the function oscillates forever")
+        );
+
+        assert_eq!(
+            latex::to_latex(&parse(&mut Scanner::new("limit(1/x, x, 0, right)")).unwrap()),
+            "\\lim_{x \\to 0^{+}} \\frac{1}{x}"
+        );
+    }
+
+    #[test]
+    fn taylor_series() {
+        let series = |input: &'static str| {
+            builtin::apply(parse(&mut Scanner::new(input)).unwrap())
+                .map(|expr| ast::to_infix(&expr))
+        };
+
+        assert_eq!(
+            series("series(sin(x), x, 0, 5)"),
+            Ok("(x - ((x ^ 3) / 6)) + ((x ^ 5) / 120)".into())
+        );
+        assert_eq!(
+            series("series(ln(x), x, 1, 3)"),
+            Ok("((x - 1) - (((x - 1) ^ 2) / 2)) + (((x - 1) ^ 3) / 3)".into())
+        );
+        assert_eq!(
+            series("series(e^x, x, 4)"),
+            Ok("(((1 + x) + ((x ^ 2) / 2)) + ((x ^ 3) / 6)) + ((x ^ 4) / 24)".into())
+        );
+        assert_eq!(
+            series("series(cos(x), x, pi, 2)"),
+            Ok(" -1 + (((x - pi) ^ 2) / 2)".into())
+        );
+        assert_eq!(
+            series("series(a sin(x), x, 3)"),
+            Ok("(a * x) + (( -a / 6) * (x ^ 3))".into())
+        );
+        assert_eq!(
+            series("series(x^2 + 1, x, 1, 5)"),
+            Ok("(2 + (2 * (x - 1))) + ((x - 1) ^ 2)".into())
+        );
+        assert_eq!(
+            series("series(ln(x), x, 0, 3)"),
+            Err("the expression isn't smooth at the point, so it has no taylor series there")
+        );
+        // pi/2 is rounded, so tan there is finite but nothing like tan nearby
+        assert_eq!(
+            series("series(tan(x), x, pi/2, 3)"),
+            Err("the expression isn't smooth at the point, so it has no taylor series there")
+        );
+        assert!(series("series(1/x, x, 0.000001, 2)").is_ok());
+
+        let expr = parse(&mut Scanner::new("series(1/(1 - x), x, -1, 2)")).unwrap();
+        let ast::Expr::Call { args, .. } = expr else {
+            panic!("expected a call")
+        };
+        let series = series::call(&args).unwrap();
+        assert_eq!(
+            ast::to_infix(&series.with_remainder()),
+            "(((1 / 2) + ((x + 1) / 4)) + (((x + 1) ^ 2) / 8)) + O((x + 1) ^ 3,)"
+        );
+    }
+
+    #[test]
+    fn differential_equations() {
+        let ode = |input: &'static str| match parse_statement(&mut Scanner::new(input)) {
+            Ok(Statement::Ode(ode)) => *ode,
+            other => panic!("expected an ode, got {:?}", other),
+        };
+        let general = |input: &'static str| {
+            let solution = ode::solve(&ode(input)).unwrap();
+            ast::to_infix(&solution.explicit.unwrap())
+        };
+        let particular = |input: &'static str| {
+            let ode = ode(input);
+            let (x0, y0) = ode.initial.clone().unwrap();
+            let solution = ode::particular(&ode, &ode::solve(&ode).unwrap(), &x0, &y0).unwrap();
+            ast::to_infix(&solution.explicit.unwrap())
+        };
+
+        let parsed = ode("dy/dx = x*y; y(0) = 1; x = 2; h = 0.5");
+        assert_eq!(parsed.y.lexeme, "y");
+        assert_eq!(parsed.x.lexeme, "x");
+        assert_eq!(ast::to_infix(&parsed.rhs), "x * y");
+        assert!(parsed.initial.is_some() && parsed.end.is_some() && parsed.step.is_some());
+        assert!(matches!(
+            parse_statement(&mut Scanner::new("dy/dx")),
+            Ok(Statement::Expr(_))
+        ));
+
+        assert_eq!(general("dy/dx = x*y"), "C * (e ^ ((x ^ 2) / 2))");
+        assert_eq!(general("dy/dx = y - 1"), "(C * (e ^ x)) + 1");
+        assert_eq!(general("dy/dx = y^2"), "1 / ( -C - x)");
+        assert_eq!(general("dy/dx = y/x + x"), "(C * x) + (x ^ 2)");
+        assert_eq!(
+            general("dy/dx = -2y + e^x"),
+            "(C * (e ^  -(2 * x))) + ((e ^ x) / 3)"
+        );
+        assert_eq!(particular("dy/dx = x*y; y(0) = 1"), "e ^ ((x ^ 2) / 2)");
+        assert_eq!(
+            particular("dy/dx = x + y; y(0) = 1"),
+            "( -x + (2 * (e ^ x))) - 1"
+        );
+        assert_eq!(
+            ode::solve(&ode("dy/dx = sin(x y)")),
+            Err("can only solve separable and first order linear equations")
+        );
+
+        // y' = y from y(0) = 1 is e^x
+        let f = |_: f64, y: f64| Ok(y);
+        let rk4 = ode::rk4(f, 0.0, 1.0, 1.0, 10).unwrap();
+        assert_eq!(rk4.len(), 11);
+        assert!((rk4[10].1 - std::f64::consts::E).abs() < 1e-5);
+        let rk45 = ode::rk45(f, 0.0, 1.0, 1.0, &ode::Options::default()).unwrap();
+        assert!((rk45.last().unwrap().1 - std::f64::consts::E).abs() < 1e-9);
+        let table =
+            ode::table(&ode("dy/dx = x*y"), 0.0, 1.0, 2.0, None, &eval::Env::new()).unwrap();
+        assert!((table[10].1 - 2f64.exp()).abs() < 1e-8);
+        assert_eq!(
+            ode::table(
+                &ode("dy/dx = y"),
+                0.0,
+                1.0,
+                1000.0,
+                Some(1e-7),
+                &eval::Env::new()
+            ),
+            Err("too many steps, the step size has to be bigger")
+        );
+        assert_eq!(
+            ode::rk45(|_, y| Ok(y * y), 0.0, 1.0, 2.0, &ode::Options::default()),
+            Err("the solution isn't finite, it may blow up before the end")
+        );
+    }
+
+    #[test]
+    fn gradients_and_partials() {
+        let apply = |input: &'static str| {
+            builtin::apply(parse(&mut Scanner::new(input)).unwrap())
+                .map(|expr| ast::to_infix(&expr))
+        };
+
+        assert_eq!(
+            apply("grad(x^2 y + sin(z), [x, y, z])"),
+            Ok("[2 * (x * y), x ^ 2, cos(z,)]".into())
+        );
+        assert_eq!(
+            apply("jacobian([x^2 y, 5x + sin(y)], [x, y])"),
+            Ok("[2 * (x * y), x ^ 2; 5, cos(y,)]".into())
+        );
+        assert_eq!(
+            apply("hessian(x^3 + x y^2, [x, y])"),
+            Ok("[6 * x, 2 * y; 2 * y, 2 * x]".into())
+        );
+        assert_eq!(
+            apply("grad(x y, x)"),
+            Err("expected a vector of variables like [x, y]")
+        );
+
+        let partial = parse(&mut Scanner::new("∂(x^2 y^3)/∂x∂y")).unwrap();
+        assert_eq!(
+            ast::to_infix(&partial),
+            "diff(diff((x ^ 2) * (y ^ 3),x,),y,)"
+        );
+        assert_eq!(
+            latex::to_latex(&partial),
+            "\\frac{\\partial}{\\partial y} \\frac{\\partial}{\\partial x} \\left(x^{2} \\cdot y^{3}\\right)"
+        );
+        assert_eq!(apply("∂(x^2 y)/∂y"), Ok("x ^ 2".into()));
+    }
+
+    #[test]
+    fn repl_commands() {
+        use command::{Command, Settings, View};
+
+        assert_eq!(
+            command::parse(":mode infix latex"),
+            Ok(Command::Mode(vec![View::Infix, View::Latex]))
+        );
+        assert_eq!(command::parse(":mode"), Ok(Command::Mode(vec![])));
+        assert_eq!(command::parse(" :quit "), Ok(Command::Quit));
+        assert_eq!(
+            command::parse(":load my file.nums"),
+            Ok(Command::Load("my file.nums".into()))
+        );
+        assert_eq!(command::parse(":save"), Ok(Command::Save(None)));
+        assert_eq!(
+            command::parse(":save a.nums"),
+            Ok(Command::Save(Some("a.nums".into())))
+        );
+        assert_eq!(command::parse(":load"), Err("expected a file name"));
+        assert_eq!(
+            command::parse(":vars x"),
+            Err("that command doesn't take arguments")
+        );
+        assert_eq!(
+            command::parse(":frobnicate"),
+            Err("unknown command, try :help")
+        );
+
+        let mut settings = Settings::default();
+        assert!(settings.shows(View::Value) && !settings.shows(View::Tree));
+        assert_eq!(
+            settings.to_string(),
+            ":mode infix prefix postfix latex value"
+        );
+        match command::parse(":mode all") {
+            Ok(Command::Mode(views)) => settings.views = views.into_iter().collect(),
+            other => panic!("expected a mode, got {:?}", other),
+        }
+        assert_eq!(
+            settings.to_string(),
+            ":mode tree infix prefix postfix sexpr latex dot value"
+        );
+
+        match parse_statement(&mut Scanner::new("a := 2 + 3")) {
+            Ok(Statement::Assign { name, value }) => {
+                assert_eq!(name, "a");
+                assert_eq!(ast::to_infix(&value), "2 + 3");
+            }
+            other => panic!("expected an assignment, got {:?}", other),
+        }
+        let value = eval::Value::Number(-2.5).to_expr().unwrap();
+        assert_eq!(ast::to_infix(&value).trim(), "-2.5");
+        assert_eq!(eval::eval_number(&value, &eval::Env::new()), Ok(-2.5));
+        assert_eq!(eval::Value::Bool(true).to_expr(), None);
+    }
+
+    #[test]
+    fn bound_variables() {
+        let mut session = super::Session::new(RuleSet::parse(DEFAULT_RULES).unwrap());
+        for name in ["x", "i", "left"].iter() {
+            session
+                .variables
+                .insert(name.to_string(), parse(&mut Scanner::new("2")).unwrap());
+        }
+        let session = &session;
+        let value = |input: &'static str| {
+            let expr = session.bind(&parse(&mut Scanner::new(input)).unwrap());
+            let expr = builtin::apply(expr)?;
+            eval::eval(&expr, &eval::Env::new()).and_then(|value| value.number())
+        };
+
+        assert_eq!(value("integrate(x^2, x, 0, 3)"), Ok(9.0));
+        assert_eq!(value("int(x dx, 0, x)"), Ok(2.0));
+        assert_eq!(value("sigma(i=0, 3, i)"), Ok(6.0));
+        assert_eq!(value("sigma(i=i, 3, i)"), Ok(5.0));
+        assert_eq!(value("limit(x^2 / x, x, 0, left)"), Ok(0.0));
+        assert_eq!(value("root(x^2 - 4, x, 0, 5)"), Ok(2.0));
+        assert_eq!(value("x * diff(x^3, x) / x"), Err("unbound variable"));
+        assert_eq!(
+            ast::to_infix(
+                &session.bind(&parse(&mut Scanner::new("series(e^x, x, x, 1) + x")).unwrap())
+            ),
+            "series(e ^ x,x,2,1,) + 2"
+        );
+    }
+
+    #[test]
+    fn exit_status() {
+        use std::sync::atomic::Ordering;
+
+        let mut session = super::Session::new(RuleSet::parse(DEFAULT_RULES).unwrap());
+        let mut run = |line: &str| {
+            super::FAILED.store(false, Ordering::Relaxed);
+            super::run(std::iter::once(line.to_string()), &mut session)
+        };
+        assert_eq!(run("1 + 2"), 0);
+        assert_eq!(run("x + 1"), 0);
+        assert_eq!(run("{ 1 if 2 < 1 }"), 1);
+        assert_eq!(run("det([1, 2, 3])"), 1);
+
+        // json output reports them too, rather than a null value
+        super::FAILED.store(false, Ordering::Relaxed);
+        super::print_json("{ 1 if 2 < 1 }", &mut session);
+        assert!(super::FAILED.load(Ordering::Relaxed));
+        super::FAILED.store(false, Ordering::Relaxed);
+        super::print_json("x + 1", &mut session);
+        assert!(!super::FAILED.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn command_line() {
+        use cli::{Input, Options};
+        use command::View;
+
+        let parse = |args: &[&str]| cli::parse(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(
+            parse(&[]),
+            Ok(Options {
+                input: Input::Repl,
+                views: None,
+                json: false,
+                data_dir: None,
+                help: false
+            })
+        );
+        let options = parse(&["-e", "1 + 2", "--eval=x := 3", "--format", "latex,value"]).unwrap();
+        assert_eq!(
+            options.input,
+            Input::Eval(vec!["1 + 2".into(), "x := 3".into()])
+        );
+        assert_eq!(options.views, Some(vec![View::Latex, View::Value]));
+        assert_eq!(parse(&["-"]).unwrap().input, Input::Lines(None));
+        assert_eq!(
+            parse(&["--data-dir", "/tmp/nums", "a.nums"]).unwrap(),
+            Options {
+                input: Input::Lines(Some("a.nums".into())),
+                views: None,
+                json: false,
+                data_dir: Some("/tmp/nums".into()),
+                help: false
+            }
+        );
+        assert!(parse(&["-h"]).unwrap().help);
+
+        assert_eq!(parse(&["-e"]), Err("--eval needs an expression"));
+        assert_eq!(parse(&["-x"]), Err("unknown option, try --help"));
+        assert_eq!(
+            parse(&["a", "b"]),
+            Err("only one file can be run at a time")
+        );
+        assert_eq!(
+            parse(&["-e", "1", "a.nums"]),
+            Err("expressions and a file can't be run together")
+        );
+        assert_eq!(
+            parse(&["-o", "infix,bogus"]),
+            Err("unknown mode, try tree infix prefix postfix sexpr latex dot value or all")
+        );
+    }
+
+    #[test]
+    fn script_files() {
+        use script::Line;
+
+        let source = "# a script\nf(x) = x^2 # square\n\n  [1, 2; # rows\n   3, 4]\nx + y = 3;\nx - y = 1\n1 +";
+        let lines = script::lines(source);
+        let line = |line, column, text: &str| Line {
+            line,
+            column,
+            text: text.into(),
+        };
+        assert_eq!(
+            lines,
+            vec![
+                line(2, 1, "f(x) = x^2 "),
+                line(4, 3, "[1, 2; \n   3, 4]"),
+                line(6, 1, "x + y = 3;\nx - y = 1"),
+                line(8, 1, "1 +"),
+            ]
+        );
+        assert_eq!(lines[1].position(3), (4, 6));
+        assert_eq!(lines[1].position(12), (5, 5));
+
+        let tokens = Scanner::new("1 + x # 2 +\n# y\n3").scan_all().unwrap();
+        let kinds: Vec<TokenKind> = tokens.iter().map(|token| token.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                TokenKind::Integer(1),
+                TokenKind::Plus,
+                TokenKind::Variable,
+                TokenKind::Integer(3),
+                TokenKind::End
+            ]
+        );
+
+        let mut scanner = Scanner::new("(1 + ) * 2");
+        assert!(parse(&mut scanner).is_err());
+        assert_eq!(scanner.offset(), 5);
+    }
+
+    #[test]
+    fn json_round_trip() {
+        use json::Json;
+
+        for input in [
+            "2x^2 - 3.5 + sin(theta)",
+            "-sqrt(x, y) / 4!",
+            "{x if x > 0 and x != 2; 0 otherwise}",
+            "[1, 2; a, ln(b)]",
+        ]
+        .iter()
+        {
+            let expr = parse(&mut Scanner::new(input)).unwrap();
+            let text = json::from_expr(&expr).to_string();
+            let read = json::parse(&text).unwrap();
+            assert_eq!(json::to_expr(&read), Ok(expr), "{}", text);
+        }
+
+        let expr = parse(&mut Scanner::new("x + 1")).unwrap();
+        assert_eq!(
+            json::from_expr(&expr).to_string(),
+            r#"{"type": "binary", "op": "+", "lhs": {"type": "variable", "name": "x"}, "rhs": {"type": "number", "value": 1}}"#
+        );
+        let tokens = Scanner::new("2.5x").scan_all().unwrap();
+        assert_eq!(
+            json::tokens(&tokens).to_string(),
+            r#"[{"kind": "Float", "lexeme": "2.5"}, {"kind": "Variable", "lexeme": "x"}, {"kind": "End", "lexeme": ""}]"#
+        );
+        let m = eval::Value::Matrix(matrix::Matrix::new(1, 2, vec![1.0, f64::NAN]));
+        assert_eq!(
+            json::from_value(&m).to_string(),
+            r#"{"rows": 1, "cols": 2, "data": [1.0, null]}"#
+        );
+
+        let text = r#" {"a": [true, null, -2e3, "q\"\u00e9\ud83d\ude00\n"], "b": {}} "#;
+        let read = json::parse(text).unwrap();
+        assert_eq!(
+            read.get("a"),
+            Some(&Json::Array(vec![
+                Json::Bool(true),
+                Json::Null,
+                Json::Float(-2000.0),
+                Json::string("q\"é😀\n")
+            ]))
+        );
+        assert_eq!(json::parse(&read.to_string()), Ok(read));
+        assert!(json::parse("[1, 2").is_err());
+        assert!(json::parse("{\"a\": 1} x").is_err());
+
+        let bad = json::parse(r#"{"type": "binary", "op": "+-", "lhs": {}, "rhs": {}}"#).unwrap();
+        assert!(json::to_expr(&bad).is_err());
+        assert!(
+            cli::parse(vec!["--format".to_string(), "json".into()])
+                .unwrap()
+                .json
+        );
+    }
+
+    #[test]
+    fn sexpr_and_dot() {
+        let sexpr = |input| sexpr::to_sexpr(&parse(&mut Scanner::new(input)).unwrap());
+        assert_eq!(sexpr("x^2 + 1"), "(+ (^ x 2) 1)");
+        assert_eq!(sexpr("2x y"), "(* (* 2 x) y)");
+        assert_eq!(sexpr("-sin(theta)!"), "(- (! (sin theta)))");
+        assert_eq!(
+            sexpr("{x if x > 0; 0 otherwise}"),
+            "(piecewise (if (> x 0) x) (otherwise 0))"
+        );
+        assert_eq!(sexpr("[1, 2.5; 3, a]"), "(matrix (row 1 2.5) (row 3 a))");
+
+        for input in [
+            "x^2 + 1",
+            "2x - 3.5 / e",
+            "-sqrt(x, y) ^ 2!",
+            "not (a < b and c >= 2)",
+            "{x if x > 0; 0 otherwise}",
+            "[1, 2; theta, ln(b)]",
+        ]
+        .iter()
+        {
+            let expr = parse(&mut Scanner::new(input)).unwrap();
+            let text = sexpr::to_sexpr(&expr);
+            assert_eq!(sexpr::parse(&text), Ok(expr), "{}", text);
+        }
+        let float = Expr::Literal {
+            literal: Token::new(TokenKind::Float(2.0), ""),
+        };
+        assert_eq!(sexpr::parse(&sexpr::to_sexpr(&float)), Ok(float));
+        assert_eq!(
+            sexpr::parse("(f inf)").map(|expr| ast::to_infix(&expr)),
+            Ok("f(inf,)".into())
+        );
+
+        assert_eq!(sexpr::parse("(+ 1"), Err("unclosed ( in s-expression"));
+        assert_eq!(
+            sexpr::parse("(+ 1 2 3)"),
+            Err("operators take one or two arguments")
+        );
+        assert!(sexpr::parse("(+ 1 2) x").is_err());
+        assert!(sexpr::parse("((+) 1)").is_err());
+
+        // read back in when sexpr output is on
+        let settings = command::Settings {
+            views: std::iter::once(command::View::Sexpr).collect(),
+        };
+        let read = |line, settings| super::sexpr_line(line, settings).map(|e| ast::to_infix(&e));
+        assert_eq!(read("(+ (^ x 2) 1)", &settings), Some("(x ^ 2) + 1".into()));
+        assert_eq!(read("(x + 1)^2", &settings), None);
+        assert_eq!(read("(+ x 1)", &command::Settings::default()), None);
+
+        let expr = parse(&mut Scanner::new("2x + 1")).unwrap();
+        assert_eq!(
+            dot::to_dot(&expr),
+            "digraph expr {
+    ordering=out;
+    n0 [label=\"+\"];
+    n1 [label=\"*\"];
+    n0 -> n1;
+    n2 [label=\"2\"];
+    n1 -> n2;
+    n3 [label=\"x\"];
+    n1 -> n3;
+    n4 [label=\"1\"];
+    n0 -> n4;
+}"
+        );
+        let quoted = Expr::Variable {
+            name: Token::new(TokenKind::Variable, "a\"b"),
+        };
+        assert!(dot::to_dot(&quoted).contains(r#"n0 [label="a\"b"];"#));
+    }
+
+    #[test]
+    fn session_persistence() {
+        let dir = storage::data_dir(Some("/tmp/nums"));
+        assert_eq!(
+            storage::session_file(&dir),
+            std::path::PathBuf::from("/tmp/nums/session.nums")
+        );
+        assert_eq!(
+            storage::history_file(&dir),
+            std::path::PathBuf::from("/tmp/nums/history.txt")
+        );
+        assert_eq!(
+            storage::rules_file(&dir),
+            std::path::PathBuf::from("/tmp/nums/rules.txt")
+        );
+
+        let rules = || RuleSet::parse(DEFAULT_RULES).unwrap();
+        let mut session = super::Session::new(rules());
+        let failed = super::restore(
+            ":mode infix value\nf(x) = x^2 + 1\na := f(2) * 2\nbig := 2^70\nsmall := -1/3\nnonsense (\n",
+            &mut session,
+        );
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, 6);
+        assert_eq!(session.settings.to_string(), ":mode infix value");
+        assert_eq!(ast::to_infix(&session.variables["a"]).trim(), "10");
+
+        let mut restored = super::Session::new(rules());
+        assert_eq!(super::restore(&session.script(), &mut restored), vec![]);
+        assert_eq!(restored.variables, session.variables);
+        assert_eq!(restored.script(), session.script());
+        assert_eq!(restored.functions, session.functions);
+    }
+
+    #[test]
+    fn repl_helper() {
+        use rustyline::highlight::Highlighter;
+
+        let mut helper = helper::Helper::default();
+        helper
+            .variables
+            .insert("a".into(), parse(&mut Scanner::new("3")).unwrap());
+        assert_eq!(
+            helper.value("2a + 1").map(|v| v.to_string()),
+            Some("7".into())
+        );
+        assert_eq!(helper.value("2b + 1"), None);
+        assert_eq!(
+            helper.value("sqrt(a + 6)").map(|v| v.to_string()),
+            Some("3".into())
+        );
+        // too slow to run on every keystroke
+        assert_eq!(helper.value("expand((a+b+c+d+e+f)^64)"), None);
+        assert_eq!(helper.value("sigma(i=0, 10000000, i)"), None);
+        assert_eq!(helper.value("integrate(x, x, 0, a)"), None);
+        assert_eq!(helper.value("f(x) = x"), None);
+        assert_eq!(helper.value("(1 +"), None);
+
+        assert!(helper::unclosed("sin(x + [1, 2"));
+        assert!(!helper::unclosed("sin(x) + [1, 2]"));
+        assert_eq!(helper::matching_bracket("f(g(x))", 1), Some(6));
+        assert_eq!(helper::matching_bracket("f(g(x))", 7), Some(1));
+        assert_eq!(helper::matching_bracket("f(g(x)", 1), None);
+        assert_eq!(helper::matching_bracket("x + 1", 2), None);
+
+        assert_eq!(
+            helper.highlight("sin(2)", 0),
+            "\x1b[36msin\x1b[0m(\x1b[33m2\x1b[0m)"
+        );
+        assert_eq!(helper.highlight("(x)", 0), "(x\x1b[1;34m)\x1b[0m");
+    }
+
+    #[test]
+    fn repl_completion() {
+        use rustyline::completion::Completer;
+        use rustyline::history::History;
+
+        let mut helper = helper::Helper::default();
+        let (name, f) = define("sq(x) = x^2");
+        helper.functions.insert(name.into(), f.clone());
+        helper
+            .variables
+            .insert("sa".into(), parse(&mut Scanner::new("3")).unwrap());
+
+        let history = History::new();
+        let complete = |line: &str| {
+            let (start, pairs) = helper
+                .complete(line, line.len(), &rustyline::Context::new(&history))
+                .unwrap();
+            let replacements: Vec<String> = pairs.into_iter().map(|p| p.replacement).collect();
+            (start, replacements)
+        };
+        assert_eq!(
+            complete("1 + s"),
+            (
+                4,
+                vec![
+                    "sa".to_string(),
+                    "sec(".into(),
+                    "series(".into(),
+                    "sigma(".into(),
+                    "sin(".into(),
+                    "solve(".into(),
+                    "sq(".into(),
+                    "sqrt(".into()
+                ]
+            )
+        );
+        assert_eq!(complete("2p"), (1, vec!["pi".to_string()]));
+        assert_eq!(complete(":sa"), (0, vec![":save".to_string()]));
+        assert_eq!(complete("x + "), (4, vec![]));
+
+        let pairs = helper.candidates("sq", false);
+        assert!(pairs[0].display.starts_with("sq(x)") && pairs[0].display.ends_with("= x ^ 2"));
+        assert!(pairs[1].display.contains("square root"));
+        // the example in the description parses
+        let pairs = helper.candidates("otherwise", false);
+        let example = &pairs[0].display[pairs[0].display.find('{').unwrap()..];
+        assert!(parse(&mut Scanner::new(example)).is_ok(), "{}", example);
+        assert_eq!(helper.candidates(":", true).len(), command::COMMANDS.len());
+    }
+
+    #[test]
+    fn matrix_literals_and_broadcasting() {
+        let expr = parse(&mut Scanner::new("[1, 2; 3, x^2]")).unwrap();
+        assert_eq!(ast::to_infix(&expr), "[1, 2; 3, x ^ 2]");
+        assert_eq!(
+            latex::to_latex(&expr),
+            "\\begin{bmatrix} 1 & 2 \\\\ 3 & x^{2} \\end{bmatrix}"
+        );
+        assert!(parse(&mut Scanner::new("[1, 2; 3]")).is_err());
+
+        let m = |rows, cols, data| Ok(eval::Value::Matrix(matrix::Matrix::new(rows, cols, data)));
+        assert_eq!(
+            matrix_value("[1, 2; 3, 4] + [1, 1; 1, 1]"),
+            m(2, 2, vec![2.0, 3.0, 4.0, 5.0])
+        );
+        assert_eq!(matrix_value("2 * [1, 2] - 1"), m(1, 2, vec![1.0, 3.0]));
+        assert_eq!(
+            matrix_value("[1, 2; 3, 4] * [5; 6]"),
+            m(2, 1, vec![17.0, 39.0])
+        );
+        assert_eq!(
+            matrix_value("[1, 2, 3] * [4, 5, 6]"),
+            Ok(eval::Value::Number(32.0))
+        );
+        assert!(matrix_value("[1, 2] + [1, 2, 3]").is_err());
+    }
+
+    #[test]
+    fn matrix_functions() {
+        let number = |input| matrix_value(input).unwrap().number().unwrap();
+        assert!((number("det([1, 2; 3, 4])") + 2.0).abs() < 1e-12);
+        assert_eq!(number("rank([1, 2; 2, 4])"), 1.0);
+        assert_eq!(number("det([1, 2; 2, 4])"), 0.0);
+
+        match matrix_value("inverse([4, 7; 2, 6]) * [4, 7; 2, 6]") {
+            Ok(eval::Value::Matrix(m)) => {
+                assert_eq!(m.to_string(), "[ 1 0 ]\n[ 0 1 ]");
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matrix_value("inverse([1, 2; 2, 4])").is_err());
+
+        match matrix_value("eigenvalues([2, 0, 0; 0, 3, 4; 0, 4, 9])") {
+            Ok(eval::Value::Matrix(m)) => assert_eq!(m.to_string(), "[ 1 2 11 ]"),
+            other => panic!("{:?}", other),
+        }
+        assert!(matrix_value("eigenvalues([0, -1; 1, 0])").is_err());
+        match matrix_value("[1, 1; 1, 0]^30") {
+            Ok(eval::Value::Matrix(m)) => assert_eq!(m.get(0, 1), 832040.0),
+            other => panic!("{:?}", other),
+        }
+        match matrix_value("[2, 0; 0, 4]^-3") {
+            Ok(eval::Value::Matrix(m)) => {
+                assert_eq!((m.get(0, 0), m.get(1, 1)), (0.125, 0.015625))
+            }
+            other => panic!("{:?}", other),
+        }
+
+        // errors that aren't just a symbolic expression get reported
+        let expr = |input| parse(&mut Scanner::new(input)).unwrap();
+        let err = matrix_value("det([1, 2, 3])").unwrap_err();
+        assert!(!super::symbolic(&expr("det([1, 2, 3])"), err));
+        assert!(super::symbolic(&expr("x + 1"), "unbound variable"));
+        assert!(super::symbolic(&expr("solve(x^2 = 2, x)"), err));
+        assert_eq!(
+            matrix_value("transpose([1, 2])"),
+            Ok(eval::Value::Matrix(matrix::Matrix::new(
+                2,
+                1,
+                vec![1.0, 2.0]
+            )))
+        );
+    }
+
+    #[test]
+    fn derivative_rules() {
+        let derivative = |input: &str| {
+            let expr = parse(&mut Scanner::new(input)).unwrap();
+            ast::to_infix(&expr.derivative("x").unwrap())
+        };
+        assert_eq!(derivative("3x^2 + y"), "3 * (2 * x)");
+        assert_eq!(derivative("sin(x^2)"), "cos(x ^ 2,) * (2 * x)");
+        assert_eq!(
+            derivative("ln(x) / x"),
+            "(((1 / x) * x) - ln(x,)) / (x ^ 2)"
+        );
+        assert_eq!(derivative("2^x"), "(2 ^ x) * ln(2,)");
+    }
+
     #[test]
     fn scan() {
         let ts = Scanner::new("f(x)=3x^2-2x+1").scan_all().unwrap();
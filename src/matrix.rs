@@ -0,0 +1,306 @@
+use super::solve;
+
+use std::fmt;
+
+// pivots smaller than this, relative to the largest entry, count as zero
+const SINGULAR_TOLERANCE: f64 = 1e-10;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Matrix {
+    pub rows: usize,
+    pub cols: usize,
+    // row by row
+    pub data: Vec<f64>,
+}
+
+impl Matrix {
+    pub fn new(rows: usize, cols: usize, data: Vec<f64>) -> Matrix {
+        assert_eq!(rows * cols, data.len());
+        Matrix { rows, cols, data }
+    }
+
+    pub fn identity(n: usize) -> Matrix {
+        let mut identity = Matrix::new(n, n, vec![0.0; n * n]);
+        for i in 0..n {
+            identity.set(i, i, 1.0);
+        }
+        identity
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> f64 {
+        self.data[row * self.cols + col]
+    }
+
+    fn set(&mut self, row: usize, col: usize, value: f64) {
+        self.data[row * self.cols + col] = value;
+    }
+
+    pub fn is_vector(&self) -> bool {
+        self.rows == 1 || self.cols == 1
+    }
+
+    pub fn is_square(&self) -> bool {
+        self.rows == self.cols
+    }
+
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Matrix {
+        Matrix::new(
+            self.rows,
+            self.cols,
+            self.data.iter().map(|x| f(*x)).collect(),
+        )
+    }
+
+    // entry by entry, the shapes have to match
+    pub fn zip(&self, other: &Matrix, f: impl Fn(f64, f64) -> f64) -> Result<Matrix, &'static str> {
+        if self.rows != other.rows || self.cols != other.cols {
+            return Err("matrices have different shapes");
+        }
+        Ok(Matrix::new(
+            self.rows,
+            self.cols,
+            self.data
+                .iter()
+                .zip(other.data.iter())
+                .map(|(a, b)| f(*a, *b))
+                .collect(),
+        ))
+    }
+
+    pub fn mul(&self, other: &Matrix) -> Result<Matrix, &'static str> {
+        if self.cols != other.rows {
+            return Err("matrix dimensions don't match for multiplication");
+        }
+        let mut product = Matrix::new(self.rows, other.cols, vec![0.0; self.rows * other.cols]);
+        for i in 0..self.rows {
+            for j in 0..other.cols {
+                let sum = (0..self.cols)
+                    .map(|k| self.get(i, k) * other.get(k, j))
+                    .sum();
+                product.set(i, j, sum);
+            }
+        }
+        Ok(product)
+    }
+
+    pub fn dot(&self, other: &Matrix) -> Result<f64, &'static str> {
+        if !self.is_vector() || !other.is_vector() || self.data.len() != other.data.len() {
+            return Err("dot product needs two vectors of the same length");
+        }
+        Ok(self
+            .data
+            .iter()
+            .zip(other.data.iter())
+            .map(|(a, b)| a * b)
+            .sum())
+    }
+
+    pub fn pow(&self, exp: i64) -> Result<Matrix, &'static str> {
+        if !self.is_square() {
+            return Err("only square matrices have powers");
+        }
+        let mut base = if exp < 0 {
+            self.inverse()?
+        } else {
+            self.clone()
+        };
+        // by squaring, so big powers take log(exp) products
+        let mut exp = exp.unsigned_abs();
+        let mut result = Matrix::identity(self.rows);
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result.mul(&base)?;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base)?;
+            }
+        }
+        Ok(result)
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let mut transpose = Matrix::new(self.cols, self.rows, vec![0.0; self.data.len()]);
+        for i in 0..self.rows {
+            for j in 0..self.cols {
+                transpose.set(j, i, self.get(i, j));
+            }
+        }
+        transpose
+    }
+
+    fn tolerance(&self) -> f64 {
+        SINGULAR_TOLERANCE * self.data.iter().fold(1.0_f64, |max, x| max.max(x.abs()))
+    }
+
+    // row echelon form by partial pivoting, returning the pivot columns and
+    // whether an odd number of rows were swapped
+    fn eliminate(&mut self, mut augmented: Option<&mut Matrix>) -> (Vec<usize>, bool) {
+        let tolerance = self.tolerance();
+        let mut pivots = Vec::new();
+        let mut odd = false;
+
+        for col in 0..self.cols {
+            let row = pivots.len();
+            if row == self.rows {
+                break;
+            }
+            let pivot = (row..self.rows)
+                .max_by(|a, b| {
+                    self.get(*a, col)
+                        .abs()
+                        .partial_cmp(&self.get(*b, col).abs())
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                })
+                .unwrap();
+            if self.get(pivot, col).abs() <= tolerance {
+                continue;
+            }
+
+            if pivot != row {
+                self.swap_rows(row, pivot);
+                if let Some(other) = augmented.as_deref_mut() {
+                    other.swap_rows(row, pivot);
+                }
+                odd = !odd;
+            }
+
+            for below in row + 1..self.rows {
+                let factor = self.get(below, col) / self.get(row, col);
+                self.subtract_row(below, row, factor);
+                if let Some(other) = augmented.as_deref_mut() {
+                    other.subtract_row(below, row, factor);
+                }
+            }
+            pivots.push(col);
+        }
+
+        (pivots, odd)
+    }
+
+    fn swap_rows(&mut self, a: usize, b: usize) {
+        for col in 0..self.cols {
+            self.data.swap(a * self.cols + col, b * self.cols + col);
+        }
+    }
+
+    // row a -= factor * row b
+    fn subtract_row(&mut self, a: usize, b: usize, factor: f64) {
+        for col in 0..self.cols {
+            let value = self.get(a, col) - factor * self.get(b, col);
+            self.set(a, col, value);
+        }
+    }
+
+    pub fn rank(&self) -> usize {
+        self.clone().eliminate(None).0.len()
+    }
+
+    pub fn det(&self) -> Result<f64, &'static str> {
+        if !self.is_square() {
+            return Err("only square matrices have a determinant");
+        }
+        let mut reduced = self.clone();
+        let (pivots, odd) = reduced.eliminate(None);
+        if pivots.len() < self.rows {
+            return Ok(0.0);
+        }
+        let product: f64 = (0..self.rows).map(|i| reduced.get(i, i)).product();
+        Ok(if odd { -product } else { product })
+    }
+
+    // gauss-jordan on [A | I]
+    pub fn inverse(&self) -> Result<Matrix, &'static str> {
+        if !self.is_square() {
+            return Err("only square matrices have an inverse");
+        }
+        let mut reduced = self.clone();
+        let mut inverse = Matrix::identity(self.rows);
+        let (pivots, _) = reduced.eliminate(Some(&mut inverse));
+        if pivots.len() < self.rows {
+            return Err("matrix is singular");
+        }
+
+        for row in (0..self.rows).rev() {
+            let scale = reduced.get(row, row);
+            for col in 0..self.cols {
+                reduced.set(row, col, reduced.get(row, col) / scale);
+                inverse.set(row, col, inverse.get(row, col) / scale);
+            }
+            for above in 0..row {
+                let factor = reduced.get(above, row);
+                reduced.subtract_row(above, row, factor);
+                inverse.subtract_row(above, row, factor);
+            }
+        }
+
+        Ok(inverse)
+    }
+
+    // roots of the characteristic polynomial, which comes from the
+    // faddeev-leverrier recurrence
+    pub fn eigenvalues(&self) -> Result<Vec<(f64, f64)>, &'static str> {
+        if !self.is_square() {
+            return Err("only square matrices have eigenvalues");
+        }
+        let n = self.rows;
+        let mut coefficients = vec![0.0; n + 1];
+        coefficients[n] = 1.0;
+
+        let mut m = Matrix::new(n, n, vec![0.0; n * n]);
+        for k in 1..=n {
+            m = self.mul(&m)?;
+            for i in 0..n {
+                m.set(i, i, m.get(i, i) + coefficients[n - k + 1]);
+            }
+            let am = self.mul(&m)?;
+            let trace: f64 = (0..n).map(|i| am.get(i, i)).sum();
+            coefficients[n - k] = -trace / k as f64;
+        }
+
+        let mut roots = solve::complex_roots(&coefficients);
+        roots.sort_by(|a, b| {
+            a.0.partial_cmp(&b.0)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+        });
+        Ok(roots)
+    }
+}
+
+// one row per line with the columns lined up
+impl fmt::Display for Matrix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // hide rounding error from elimination, and -0
+        let entries: Vec<String> = self
+            .data
+            .iter()
+            .map(|x| ((x * 1e12).round() / 1e12 + 0.0).to_string())
+            .collect();
+        let widths: Vec<usize> = (0..self.cols)
+            .map(|col| {
+                (0..self.rows)
+                    .map(|row| entries[row * self.cols + col].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        for row in 0..self.rows {
+            if row > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "[")?;
+            for col in 0..self.cols {
+                write!(
+                    f,
+                    " {:>width$}",
+                    entries[row * self.cols + col],
+                    width = widths[col]
+                )?;
+            }
+            write!(f, " ]")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,523 @@
+use super::ast::*;
+use super::diff::{call, depends_on, difference, negate, power, product, quotient, sum};
+use super::eval::{self, Env};
+use super::integral;
+use super::limit::cancelled;
+use super::poly::*;
+use super::rational::Rational;
+use super::rewrite::{self, Bindings};
+use super::scan::*;
+use super::series::tidy;
+
+// dy/dx = rhs, with whatever came after it on the line
+#[derive(Debug, PartialEq, Clone)]
+pub struct Ode<'a> {
+    // y and x in dy/dx
+    pub y: Token<'a>,
+    pub x: Token<'a>,
+    pub rhs: Expr<'a>,
+    // y(x0) = y0
+    pub initial: Option<(Expr<'a>, Expr<'a>)>,
+    // x = end, where a table of values stops
+    pub end: Option<Expr<'a>>,
+    // h = step, for fixed steps with rk4 instead of rk45
+    pub step: Option<Expr<'a>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Separable,
+    Linear,
+}
+
+// lhs = rhs, where rhs has the constant C in it, and y = explicit when that
+// could be solved for y
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution<'a> {
+    pub method: Method,
+    pub lhs: Expr<'a>,
+    pub rhs: Expr<'a>,
+    pub explicit: Option<Expr<'a>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    // largest error allowed in one step, relative to y
+    pub tolerance: f64,
+    pub max_steps: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            tolerance: 1e-10,
+            max_steps: 100_000,
+        }
+    }
+}
+
+// rows in a table when there's no step size
+const TABLE_ROWS: usize = 10;
+
+const BLEW_UP: &str = "the solution isn't finite, it may blow up before the end";
+
+fn finite(y: f64) -> Result<f64, &'static str> {
+    if y.is_finite() {
+        Ok(y)
+    } else {
+        Err(BLEW_UP)
+    }
+}
+
+// classic fourth order runge-kutta with a fixed number of steps, every step
+// is included
+pub fn rk4(
+    f: impl Fn(f64, f64) -> Result<f64, &'static str>,
+    x0: f64,
+    y0: f64,
+    x1: f64,
+    steps: usize,
+) -> Result<Vec<(f64, f64)>, &'static str> {
+    let h = (x1 - x0) / steps as f64;
+    let mut points = vec![(x0, y0)];
+    let mut y = y0;
+
+    for i in 0..steps {
+        let x = x0 + i as f64 * h;
+        let k1 = f(x, y)?;
+        let k2 = f(x + h / 2.0, y + h * k1 / 2.0)?;
+        let k3 = f(x + h / 2.0, y + h * k2 / 2.0)?;
+        let k4 = f(x + h, y + h * k3)?;
+        y = finite(y + h * (k1 + 2.0 * k2 + 2.0 * k3 + k4) / 6.0)?;
+        points.push((x0 + (i + 1) as f64 * h, y));
+    }
+
+    Ok(points)
+}
+
+// runge-kutta-fehlberg: a fourth and fifth order step from the same six
+// evaluations, their difference picks the next step size. every accepted
+// step is included.
+pub fn rk45(
+    f: impl Fn(f64, f64) -> Result<f64, &'static str>,
+    x0: f64,
+    y0: f64,
+    x1: f64,
+    options: &Options,
+) -> Result<Vec<(f64, f64)>, &'static str> {
+    let mut points = vec![(x0, y0)];
+    let (mut x, mut y) = (x0, y0);
+    let mut h = (x1 - x0) / 100.0;
+    if h == 0.0 {
+        return Ok(points);
+    }
+
+    for _ in 0..options.max_steps {
+        if (x1 - x) * h.signum() <= 0.0 {
+            return Ok(points);
+        }
+        if (x + h - x1) * h.signum() > 0.0 {
+            h = x1 - x;
+        }
+
+        let k1 = h * f(x, y)?;
+        let k2 = h * f(x + h / 4.0, y + k1 / 4.0)?;
+        let k3 = h * f(x + 3.0 * h / 8.0, y + 3.0 * k1 / 32.0 + 9.0 * k2 / 32.0)?;
+        let k4 = h * f(
+            x + 12.0 * h / 13.0,
+            y + 1932.0 * k1 / 2197.0 - 7200.0 * k2 / 2197.0 + 7296.0 * k3 / 2197.0,
+        )?;
+        let k5 = h * f(
+            x + h,
+            y + 439.0 * k1 / 216.0 - 8.0 * k2 + 3680.0 * k3 / 513.0 - 845.0 * k4 / 4104.0,
+        )?;
+        let k6 = h * f(
+            x + h / 2.0,
+            y - 8.0 * k1 / 27.0 + 2.0 * k2 - 3544.0 * k3 / 2565.0 + 1859.0 * k4 / 4104.0
+                - 11.0 * k5 / 40.0,
+        )?;
+        let fourth = y + 25.0 * k1 / 216.0 + 1408.0 * k3 / 2565.0 + 2197.0 * k4 / 4104.0 - k5 / 5.0;
+        let fifth = y + 16.0 * k1 / 135.0 + 6656.0 * k3 / 12825.0 + 28561.0 * k4 / 56430.0
+            - 9.0 * k5 / 50.0
+            + 2.0 * k6 / 55.0;
+
+        let error = (fifth - fourth).abs();
+        let tolerance = options.tolerance * y.abs().max(1.0);
+        if !error.is_finite() {
+            return Err(BLEW_UP);
+        }
+        if error <= tolerance {
+            x += h;
+            y = finite(fifth)?;
+            points.push((x, y));
+        }
+
+        let scale = if error == 0.0 {
+            4.0
+        } else {
+            (0.84 * (tolerance / error).powf(0.25)).clamp(0.1, 4.0)
+        };
+        h *= scale;
+        if h.abs() <= 1e3 * f64::EPSILON * x.abs().max(1.0) {
+            return Err(BLEW_UP);
+        }
+    }
+
+    Err("too many steps, the equation may be stiff")
+}
+
+// y at evenly spaced x from x0 to x1, with rk4 when there's a step size and
+// rk45 between the rows otherwise
+pub fn table(
+    ode: &Ode<'_>,
+    x0: f64,
+    y0: f64,
+    x1: f64,
+    step: Option<f64>,
+    env: &Env,
+) -> Result<Vec<(f64, f64)>, &'static str> {
+    let f = |x: f64, y: f64| {
+        eval::eval_number(&ode.rhs, &env.with(ode.x.lexeme, x).with(ode.y.lexeme, y))
+    };
+
+    let options = Options::default();
+    match step {
+        Some(h) if h <= 0.0 || !h.is_finite() => Err("the step size has to be positive"),
+        Some(h) => {
+            // every step is a row, so the same limit as rk45
+            let steps = ((x1 - x0).abs() / h).round().max(1.0);
+            if steps.is_nan() || steps > options.max_steps as f64 {
+                return Err("too many steps, the step size has to be bigger");
+            }
+            rk4(f, x0, y0, x1, steps as usize)
+        }
+        None => {
+            let mut rows = vec![(x0, y0)];
+            for i in 1..=TABLE_ROWS {
+                let (x, y) = rows[i - 1];
+                let next = x0 + (x1 - x0) * i as f64 / TABLE_ROWS as f64;
+                let points = rk45(f, x, y, next, &options)?;
+                rows.push((next, points.last().unwrap().1));
+            }
+            Ok(rows)
+        }
+    }
+}
+
+// the general solution with an arbitrary constant C, for separable and first
+// order linear equations
+pub fn solve<'a>(ode: &Ode<'a>) -> Result<Solution<'a>, &'static str> {
+    separable(ode)
+        .or_else(|| linear(ode))
+        .ok_or("can only solve separable and first order linear equations")
+}
+
+fn constant<'a>() -> Expr<'a> {
+    Expr::Variable {
+        name: Token::new(TokenKind::Variable, "C"),
+    }
+}
+
+// the factors of a product, and whether each one is in the denominator
+fn factors<'a>(expr: &Expr<'a>, denominator: bool, out: &mut Vec<(Expr<'a>, bool)>) {
+    match expr {
+        Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Multiply => {
+            factors(lhs, denominator, out);
+            factors(rhs, denominator, out);
+        }
+        Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Divide => {
+            factors(lhs, denominator, out);
+            factors(rhs, !denominator, out);
+        }
+        Expr::Unary { op, rhs } if op.kind == TokenKind::Minus => {
+            out.push((negate(integer(1)), false));
+            factors(rhs, denominator, out);
+        }
+        _ => out.push((expr.clone(), denominator)),
+    }
+}
+
+fn product_of<'a>(factors: impl Iterator<Item = Expr<'a>>) -> Expr<'a> {
+    factors.fold(integer(1), product)
+}
+
+// dy/dx = g(x) h(y) gives ∫ 1/h(y) dy = ∫ g(x) dx + C
+fn separable<'a>(ode: &Ode<'a>) -> Option<Solution<'a>> {
+    let (x, y) = (ode.x.lexeme, ode.y.lexeme);
+    let mut all = Vec::new();
+    factors(&ode.rhs, false, &mut all);
+    if all
+        .iter()
+        .any(|(factor, _)| depends_on(factor, x) && depends_on(factor, y))
+    {
+        return None;
+    }
+
+    let (h, g): (Vec<_>, Vec<_>) = all
+        .into_iter()
+        .partition(|(factor, _)| depends_on(factor, y));
+    let part = |factors: &[(Expr<'a>, bool)], denominator: bool| {
+        product_of(
+            factors
+                .iter()
+                .filter(|(_, d)| *d == denominator)
+                .map(|(factor, _)| factor.clone()),
+        )
+    };
+    let g = quotient(part(&g, false), part(&g, true));
+    let reciprocal_h = quotient(part(&h, true), part(&h, false));
+
+    let var = |token: Token<'a>| match token.kind {
+        TokenKind::SpecialVariable => Expr::SpecialVariable { name: token },
+        _ => Expr::Variable { name: token },
+    };
+    let lhs = tidy(integral::antiderivative(&tidy(reciprocal_h), &var(ode.y))?);
+    let rhs = tidy(integral::antiderivative(&tidy(g), &var(ode.x))?);
+    let rhs = sum(rhs, constant());
+    let explicit = isolate(&lhs, rhs.clone(), y).map(|y| absorb(tidy(y)));
+
+    Some(Solution {
+        method: Method::Separable,
+        lhs,
+        rhs,
+        explicit,
+    })
+}
+
+// dy/dx = a(x) y + q(x). with the integrating factor μ = e^(-∫a dx),
+// μ y = ∫ μ q dx + C
+fn linear<'a>(ode: &Ode<'a>) -> Option<Solution<'a>> {
+    let (x, y) = (ode.x.lexeme, ode.y.lexeme);
+    let a = cancelled(&tidy(ode.rhs.derivative(y).ok()?)).ok()?;
+    if depends_on(&a, y) {
+        return None;
+    }
+    let mut zero = Bindings::new();
+    zero.insert(y, integer(0));
+    let q = cancelled(&tidy(rewrite::substitute(&ode.rhs, &zero))).ok()?;
+
+    let var = integral::variable(&ode.rhs, x);
+    let integral_a = tidy(integral::antiderivative(&a, &var)?);
+    let factor = exp(tidy(negate(integral_a.clone())));
+    let inverse = exp(integral_a);
+    let integrand = merge(cancelled(&product(factor.clone(), q)).ok()?);
+    let rhs = match integral::antiderivative(&integrand, &var) {
+        Some(rhs) => tidy(rhs),
+        None => Expr::Call {
+            name: Token::new(TokenKind::BuiltinFunction, "integrate"),
+            args: vec![integrand, var],
+        },
+    };
+
+    Some(Solution {
+        method: Method::Linear,
+        lhs: product(factor, integral::variable(&ode.rhs, y)),
+        explicit: Some(merge(
+            cancelled(&product(inverse, sum(rhs.clone(), constant()))).ok()?,
+        )),
+        rhs: sum(rhs, constant()),
+    })
+}
+
+// e^expr, with e^(k ln(u)) written as u^k
+fn exp(expr: Expr<'_>) -> Expr<'_> {
+    match log_of(&expr) {
+        Some((k, u)) => tidy(power(u, k)),
+        None => power(e(), expr),
+    }
+}
+
+fn e<'a>() -> Expr<'a> {
+    Expr::Variable {
+        name: Token::new(TokenKind::Variable, "e"),
+    }
+}
+
+// k and u when expr is k ln(u) for a constant k
+fn log_of<'a>(expr: &Expr<'a>) -> Option<(Expr<'a>, Expr<'a>)> {
+    let is_constant = |expr: &Expr<'_>| {
+        Poly::from_expr(expr)
+            .ok()
+            .and_then(|p| p.as_constant())
+            .is_some()
+    };
+    match expr {
+        Expr::Call { name, args } if name.lexeme == "ln" && args.len() == 1 => {
+            Some((integer(1), args[0].clone()))
+        }
+        Expr::Unary { op, rhs } if op.kind == TokenKind::Minus => {
+            log_of(rhs).map(|(k, u)| (negate(k), u))
+        }
+        Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Multiply => {
+            if is_constant(lhs) {
+                log_of(rhs).map(|(k, u)| (tidy(product(*lhs.clone(), k)), u))
+            } else if is_constant(rhs) {
+                log_of(lhs).map(|(k, u)| (tidy(product(*rhs.clone(), k)), u))
+            } else {
+                None
+            }
+        }
+        Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Divide && is_constant(rhs) => {
+            log_of(lhs).map(|(k, u)| (tidy(quotient(k, *rhs.clone())), u))
+        }
+        _ => None,
+    }
+}
+
+// solve lhs = rhs for y by undoing whatever is done to it, one operation at
+// a time. None when y shows up more than once or in something that can't be
+// undone.
+fn isolate<'a>(lhs: &Expr<'a>, rhs: Expr<'a>, y: &str) -> Option<Expr<'a>> {
+    match lhs {
+        Expr::Variable { name } | Expr::SpecialVariable { name } if name.lexeme == y => Some(rhs),
+        Expr::Unary { op, rhs: u } if op.kind == TokenKind::Minus => isolate(u, negate(rhs), y),
+        Expr::Binary { lhs: l, op, rhs: r } => {
+            let (l, r) = (l.as_ref().clone(), r.as_ref().clone());
+            match (depends_on(&l, y), depends_on(&r, y), op.kind) {
+                (true, true, _) => None,
+                (true, false, TokenKind::Plus) => isolate(&l, difference(rhs, r), y),
+                (false, true, TokenKind::Plus) => isolate(&r, difference(rhs, l), y),
+                (true, false, TokenKind::Minus) => isolate(&l, sum(rhs, r), y),
+                (false, true, TokenKind::Minus) => isolate(&r, difference(l, rhs), y),
+                (true, false, TokenKind::Multiply) => isolate(&l, quotient(rhs, r), y),
+                (false, true, TokenKind::Multiply) => isolate(&r, quotient(rhs, l), y),
+                (true, false, TokenKind::Divide) => isolate(&l, product(rhs, r), y),
+                (false, true, TokenKind::Divide) => isolate(&r, quotient(l, rhs), y),
+                (true, false, TokenKind::Power) => {
+                    isolate(&l, power(rhs, quotient(integer(1), r)), y)
+                }
+                (false, true, TokenKind::Power) if l == e() => isolate(&r, call("ln", rhs), y),
+                (false, true, TokenKind::Power) => {
+                    isolate(&r, quotient(call("ln", rhs), call("ln", l)), y)
+                }
+                _ => None,
+            }
+        }
+        Expr::Call { name, args } if args.len() == 1 => match name.lexeme {
+            "ln" => isolate(&args[0], power(e(), rhs), y),
+            "sqrt" => isolate(&args[0], power(rhs, integer(2)), y),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// e^u e^v is e^(u + v), with e^u / e^v as e^(u - v)
+fn merge(expr: Expr<'_>) -> Expr<'_> {
+    tidy(Merge.fold_expr(expr))
+}
+
+struct Merge;
+
+impl<'a> Fold<'a> for Merge {
+    fn fold_binary(&mut self, lhs: Expr<'a>, op: Token<'a>, rhs: Expr<'a>) -> Expr<'a> {
+        let expr = Expr::Binary {
+            lhs: Box::new(self.fold_expr(lhs)),
+            op,
+            rhs: Box::new(self.fold_expr(rhs)),
+        };
+        if !matches!(op.kind, TokenKind::Multiply | TokenKind::Divide) {
+            return expr;
+        }
+
+        let mut all = Vec::new();
+        factors(&expr, false, &mut all);
+        let (exponentials, rest): (Vec<_>, Vec<_>) = all
+            .into_iter()
+            .partition(|(factor, _)| matches!(factor, Expr::Binary { lhs, op, .. } if op.kind == TokenKind::Power && **lhs == e()));
+        if exponentials.len() < 2 {
+            return expr;
+        }
+
+        let exponent = exponentials
+            .into_iter()
+            .fold(integer(0), |total, (factor, denominator)| {
+                let Expr::Binary { rhs: u, .. } = factor else {
+                    unreachable!()
+                };
+                if denominator {
+                    difference(total, *u)
+                } else {
+                    sum(total, *u)
+                }
+            });
+        let part = |denominator: bool| {
+            product_of(
+                rest.iter()
+                    .filter(|(_, d)| *d == denominator)
+                    .map(|(factor, _)| factor.clone()),
+            )
+        };
+        quotient(product(part(false), power(e(), tidy(exponent))), part(true))
+    }
+}
+
+// e^(f + C) is just C e^f for another constant C
+fn absorb(expr: Expr<'_>) -> Expr<'_> {
+    Absorb.fold_expr(expr)
+}
+
+struct Absorb;
+
+impl<'a> Fold<'a> for Absorb {
+    fn fold_binary(&mut self, lhs: Expr<'a>, op: Token<'a>, rhs: Expr<'a>) -> Expr<'a> {
+        let (lhs, rhs) = (self.fold_expr(lhs), self.fold_expr(rhs));
+        if op.kind == TokenKind::Power && lhs == e() {
+            let coefficients = Poly::from_expr(&rhs)
+                .ok()
+                .and_then(|p| p.coefficients_in("C"));
+            if let Some([f, c]) = coefficients.as_deref() {
+                if c.as_constant() == Some(Rational::ONE) {
+                    return product(constant(), power(e(), f.to_expr()));
+                }
+            }
+        }
+        Expr::Binary {
+            lhs: Box::new(lhs),
+            op,
+            rhs: Box::new(rhs),
+        }
+    }
+}
+
+// the solution through (x0, y0). C comes from the explicit solution when it's
+// linear in C, otherwise from lhs = rhs.
+pub fn particular<'a>(
+    ode: &Ode<'a>,
+    solution: &Solution<'a>,
+    x0: &Expr<'a>,
+    y0: &Expr<'a>,
+) -> Result<Solution<'a>, &'static str> {
+    let mut at = Bindings::new();
+    at.insert(ode.x.lexeme, x0.clone());
+    at.insert(ode.y.lexeme, y0.clone());
+    at.insert("C", integer(0));
+    let at = |expr: &Expr<'a>| tidy(rewrite::substitute(expr, &at));
+
+    let linear_in_c = solution
+        .explicit
+        .as_ref()
+        .and_then(|explicit| Poly::from_expr(explicit).ok()?.coefficients_in("C"))
+        .filter(|coefficients| coefficients.len() == 2);
+    let c = match linear_in_c {
+        Some(coefficients) => {
+            let (a, b) = (coefficients[0].to_expr(), coefficients[1].to_expr());
+            quotient(difference(y0.clone(), at(&a)), at(&b))
+        }
+        None => difference(at(&solution.lhs), at(&solution.rhs)),
+    };
+    let c = tidy(c);
+    if eval::eval_number(&c, &Env::new()).is_ok_and(|c| !c.is_finite()) {
+        return Err("no solution goes through the initial condition");
+    }
+
+    let mut bindings = Bindings::new();
+    bindings.insert("C", c.clone());
+    let with_c = |expr: &Expr<'a>| tidy(rewrite::substitute(expr, &bindings));
+    Ok(Solution {
+        method: solution.method,
+        lhs: solution.lhs.clone(),
+        rhs: with_c(&solution.rhs),
+        explicit: solution.explicit.as_ref().map(with_c),
+    })
+}
@@ -1,10 +1,184 @@
 use super::ast::*;
+use super::function::Function;
+use super::ode::Ode;
 use super::scan::*;
 
 pub fn parse<'a>(scanner: &mut Scanner<'a>) -> Result<Expr<'a>, &'static str> {
     parse_expr(scanner, 0)
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub enum Statement<'a> {
+    Define {
+        name: &'a str,
+        function: Function<'a>,
+    },
+    Expr(Expr<'a>),
+    // expressions separated by ;
+    System(Vec<Expr<'a>>),
+    Ode(Box<Ode<'a>>),
+    // x := expr
+    Assign {
+        name: &'a str,
+        value: Expr<'a>,
+    },
+}
+
+// a line is a function definition if it starts with `name(a, b, ...) =`,
+// several expressions if they're separated by `;`, a differential equation if
+// it starts with `dy/dx =`, an assignment if it starts with `x :=`, and
+// otherwise just one
+pub fn parse_statement<'a>(scanner: &mut Scanner<'a>) -> Result<Statement<'a>, &'static str> {
+    if let Some((y, x)) = derivative(scanner)? {
+        return Ok(Statement::Ode(Box::new(ode(scanner, y, x)?)));
+    }
+    if scanner.peek_token(0)?.kind == TokenKind::Variable
+        && scanner.peek_token(1)?.kind == TokenKind::Assign
+    {
+        let name = scanner.next_token()?.lexeme;
+        scanner.next_token()?;
+        return Ok(Statement::Assign {
+            name,
+            value: parse(scanner)?,
+        });
+    }
+
+    let params = match definition_params(scanner)? {
+        Some(params) => params,
+        None => {
+            let expr = parse(scanner)?;
+            if scanner.peek_token(0)?.kind != TokenKind::Semicolon {
+                return Ok(Statement::Expr(expr));
+            }
+
+            let mut exprs = vec![expr];
+            while scanner.peek_token(0)?.kind == TokenKind::Semicolon {
+                scanner.next_token()?;
+                if scanner.peek_token(0)?.kind != TokenKind::End {
+                    exprs.push(parse(scanner)?);
+                }
+            }
+            return Ok(Statement::System(exprs));
+        }
+    };
+
+    let name = scanner.next_token()?.lexeme;
+    for _ in 0..params.len() * 2 + 2 {
+        scanner.next_token()?;
+    }
+
+    // so the body can mention the function
+    scanner.add_function(name);
+    let body = parse(scanner)?;
+    Ok(Statement::Define {
+        name,
+        function: Function { params, body },
+    })
+}
+
+// dy/dx, dtheta/dx and so on, and the variables without the d
+fn derivative<'a>(
+    scanner: &mut Scanner<'a>,
+) -> Result<Option<(Token<'a>, Token<'a>)>, &'static str> {
+    let differential = |token: &Token<'a>| {
+        if token.kind != TokenKind::SpecialVariable || !token.lexeme.starts_with('d') {
+            return None;
+        }
+        let name = &token.lexeme[1..];
+        Some(match name {
+            "theta" => Token::new(TokenKind::SpecialVariable, name),
+            _ => Token::new(TokenKind::Variable, name),
+        })
+    };
+
+    let (dy, divide, dx) = (
+        *scanner.peek_token(0)?,
+        scanner.peek_token(1)?.kind,
+        *scanner.peek_token(2)?,
+    );
+    if divide != TokenKind::Divide || scanner.peek_token(3)?.kind != TokenKind::Equal {
+        return Ok(None);
+    }
+    Ok(differential(&dy).zip(differential(&dx)))
+}
+
+// the rest of `dy/dx = rhs; y(x0) = y0; x = end; h = step`, everything after
+// the equation is optional
+fn ode<'a>(scanner: &mut Scanner<'a>, y: Token<'a>, x: Token<'a>) -> Result<Ode<'a>, &'static str> {
+    for _ in 0..4 {
+        scanner.next_token()?;
+    }
+    let mut ode = Ode {
+        y,
+        x,
+        rhs: parse(scanner)?,
+        initial: None,
+        end: None,
+        step: None,
+    };
+
+    while scanner.peek_token(0)?.kind == TokenKind::Semicolon {
+        scanner.next_token()?;
+        if scanner.peek_token(0)?.lexeme == y.lexeme
+            && scanner.peek_token(1)?.kind == TokenKind::LeftParen
+        {
+            scanner.next_token()?;
+            scanner.next_token()?;
+            let x0 = parse(scanner)?;
+            consume(scanner, TokenKind::RightParen)?;
+            consume(scanner, TokenKind::Equal)?;
+            ode.initial = Some((x0, parse(scanner)?));
+            continue;
+        }
+
+        match parse(scanner)? {
+            Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Equal => match *lhs {
+                Expr::Variable { name } | Expr::SpecialVariable { name }
+                    if name.lexeme == x.lexeme =>
+                {
+                    ode.end = Some(*rhs)
+                }
+                Expr::Variable { name } if name.lexeme == "h" => ode.step = Some(*rhs),
+                _ => return Err("expected y(x0) = y0, x = end or h = step after the equation"),
+            },
+            _ => return Err("expected y(x0) = y0, x = end or h = step after the equation"),
+        }
+    }
+
+    Ok(ode)
+}
+
+fn definition_params<'a>(scanner: &mut Scanner<'a>) -> Result<Option<Vec<&'a str>>, &'static str> {
+    let name = scanner.peek_token(0)?.kind;
+    if !matches!(name, TokenKind::Variable | TokenKind::Function)
+        || scanner.peek_token(1)?.kind != TokenKind::LeftParen
+    {
+        return Ok(None);
+    }
+
+    let mut params = Vec::new();
+    let mut idx = 2;
+    loop {
+        let param = *scanner.peek_token(idx)?;
+        if param.kind != TokenKind::Variable {
+            return Ok(None);
+        }
+        params.push(param.lexeme);
+
+        match scanner.peek_token(idx + 1)?.kind {
+            TokenKind::Comma => idx += 2,
+            TokenKind::RightParen => break,
+            _ => return Ok(None),
+        }
+    }
+
+    if scanner.peek_token(idx + 2)?.kind == TokenKind::Equal {
+        Ok(Some(params))
+    } else {
+        Ok(None)
+    }
+}
+
 pub fn parse_expr<'a>(scanner: &mut Scanner<'a>, min_bp: u8) -> Result<Expr<'a>, &'static str> {
     let lhs_token = scanner.next_token()?;
 
@@ -12,7 +186,7 @@ pub fn parse_expr<'a>(scanner: &mut Scanner<'a>, min_bp: u8) -> Result<Expr<'a>,
         TokenKind::Variable => Expr::Variable { name: lhs_token },
         TokenKind::SpecialVariable => Expr::SpecialVariable { name: lhs_token },
 
-        TokenKind::BuiltinFunction => {
+        TokenKind::BuiltinFunction | TokenKind::Function => {
             consume(scanner, TokenKind::LeftParen)?;
             let mut args = Vec::new();
 
@@ -39,16 +213,93 @@ pub fn parse_expr<'a>(scanner: &mut Scanner<'a>, min_bp: u8) -> Result<Expr<'a>,
             lhs
         }
 
-        TokenKind::Minus => {
+        // { value if guard; value otherwise }
+        TokenKind::LeftBrace => {
+            let mut branches = Vec::new();
+
+            loop {
+                let value = parse(scanner)?;
+                let guard = match scanner.next_token()?.kind {
+                    TokenKind::If => Some(parse(scanner)?),
+                    TokenKind::Otherwise => None,
+                    _ => return Err("piecewise branches need `if` or `otherwise`"),
+                };
+                branches.push(Branch { value, guard });
+
+                match scanner.next_token()?.kind {
+                    TokenKind::Semicolon
+                        if scanner.peek_token(0)?.kind == TokenKind::RightBrace =>
+                    {
+                        scanner.next_token()?;
+                        break;
+                    }
+                    TokenKind::Semicolon => {}
+                    TokenKind::RightBrace => break,
+                    _ => return Err("expected ; or } after a piecewise branch"),
+                }
+            }
+
+            Expr::Piecewise { branches }
+        }
+
+        // [a, b; c, d]
+        TokenKind::LeftBracket => {
+            let mut rows = vec![Vec::new()];
+
+            loop {
+                rows.last_mut().unwrap().push(parse(scanner)?);
+                match scanner.next_token()?.kind {
+                    TokenKind::Comma => {}
+                    TokenKind::Semicolon => rows.push(Vec::new()),
+                    TokenKind::RightBracket => break,
+                    _ => return Err("expected , ; or ] in a matrix"),
+                }
+            }
+
+            if rows.iter().any(|row| row.len() != rows[0].len()) {
+                return Err("every row of a matrix needs the same length");
+            }
+            Expr::Matrix { rows }
+        }
+
+        // ∂f/∂x, or ∂f/∂x∂y for a mixed partial
+        TokenKind::Partial => {
+            let mut expr = parse_expr(scanner, MULTIPLY_DIVIDE_MOD + 1)?;
+            consume(scanner, TokenKind::Divide)?;
+            loop {
+                consume(scanner, TokenKind::Partial)?;
+                let name = scanner.next_token()?;
+                let var = match name.kind {
+                    TokenKind::Variable => Expr::Variable { name },
+                    TokenKind::SpecialVariable => Expr::SpecialVariable { name },
+                    _ => return Err("expected a variable after ∂"),
+                };
+                expr = Expr::Call {
+                    name: Token::new(TokenKind::BuiltinFunction, "diff"),
+                    args: vec![expr, var],
+                };
+                if scanner.peek_token(0)?.kind != TokenKind::Partial {
+                    break;
+                }
+            }
+            expr
+        }
+
+        TokenKind::Minus | TokenKind::Not => {
             let bp = prefix_bp(lhs_token.kind)?;
             let rhs = Box::new(parse_expr(scanner, bp)?);
             Expr::Unary { op: lhs_token, rhs }
         }
 
-        _ => panic!("syntax err: {:?}", lhs_token),
+        _ => return Err("syntax error: expected an expression"),
     };
 
+    // right hand side of the last comparison at this level, so a < b < c can
+    // be read as a < b and b < c
+    let mut chain: Option<Expr<'a>> = None;
+
     loop {
+        let previous = chain.take();
         let op_token = scanner.peek_token(0)?;
         if op_token.kind == TokenKind::End {
             break;
@@ -67,7 +318,13 @@ pub fn parse_expr<'a>(scanner: &mut Scanner<'a>, min_bp: u8) -> Result<Expr<'a>,
             continue;
         }
 
-        if op_token.kind == TokenKind::Variable {
+        if matches!(
+            op_token.kind,
+            TokenKind::Variable
+                | TokenKind::SpecialVariable
+                | TokenKind::Function
+                | TokenKind::BuiltinFunction
+        ) {
             if MULTIPLY_DIVIDE_MOD < min_bp {
                 break;
             }
@@ -88,11 +345,27 @@ pub fn parse_expr<'a>(scanner: &mut Scanner<'a>, min_bp: u8) -> Result<Expr<'a>,
 
             if scanner.peek_token(0)?.kind != TokenKind::LeftParen {
                 let op = scanner.next_token()?;
+                let rhs = parse_expr(scanner, lbp + 1)?;
 
-                lhs = Expr::Binary {
-                    lhs: Box::new(lhs),
-                    op,
-                    rhs: Box::new(parse_expr(scanner, lbp + 1)?),
+                if op.kind.is_comparison() {
+                    chain = Some(rhs.clone());
+                }
+
+                lhs = match previous {
+                    Some(previous) if op.kind.is_comparison() => Expr::Binary {
+                        lhs: Box::new(lhs),
+                        op: Token::new(TokenKind::And, "and"),
+                        rhs: Box::new(Expr::Binary {
+                            lhs: Box::new(previous),
+                            op,
+                            rhs: Box::new(rhs),
+                        }),
+                    },
+                    _ => Expr::Binary {
+                        lhs: Box::new(lhs),
+                        op,
+                        rhs: Box::new(rhs),
+                    },
                 };
             } else {
                 consume(scanner, TokenKind::LeftParen)?;
@@ -115,16 +388,20 @@ pub fn parse_expr<'a>(scanner: &mut Scanner<'a>, min_bp: u8) -> Result<Expr<'a>,
     Ok(lhs)
 }
 
-const COMPARE: u8 = 1;
-const PLUS_MINUS: u8 = 2;
-const MULTIPLY_DIVIDE_MOD: u8 = 3;
-const POWER: u8 = 4;
-const MINUS_PREFIX: u8 = 5;
-const FACTORIAL: u8 = 6;
+const OR: u8 = 1;
+const AND: u8 = 2;
+const NOT_PREFIX: u8 = 3;
+const COMPARE: u8 = 4;
+const PLUS_MINUS: u8 = 5;
+const MULTIPLY_DIVIDE_MOD: u8 = 6;
+const POWER: u8 = 7;
+const MINUS_PREFIX: u8 = 8;
+const FACTORIAL: u8 = 9;
 
 fn prefix_bp(kind: TokenKind) -> Result<u8, &'static str> {
     match kind {
         TokenKind::Minus => Ok(MINUS_PREFIX),
+        TokenKind::Not => Ok(NOT_PREFIX),
         _ => Err("syntax err: prefix op"),
     }
 }
@@ -138,6 +415,8 @@ fn postfix_bp(kind: TokenKind) -> Option<u8> {
 
 fn infix_bp(kind: TokenKind) -> Option<u8> {
     match kind {
+        TokenKind::Or => Some(OR),
+        TokenKind::And => Some(AND),
         TokenKind::Equal
         | TokenKind::Less
         | TokenKind::LessEqual
@@ -158,7 +437,6 @@ fn consume(scanner: &mut Scanner<'_>, kind: TokenKind) -> Result<(), &'static st
     if t.kind == kind {
         Ok(())
     } else {
-        println!("wanted {:?} got {:?}", kind, t.kind);
         Err("syntax error")
     }
 }
@@ -0,0 +1,566 @@
+use super::ast::*;
+use super::diff::depends_on;
+use super::rational::{gcd, Rational, OVERFLOW};
+use super::scan::*;
+
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+// anything the polynomial code can't look inside, usually just a variable but
+// also calls, fractional powers and so on. plain variables sort first, then
+// everything else by its infix text so the canonical form doesn't depend on
+// where an atom came from.
+#[derive(Debug, Clone)]
+pub struct Atom<'a> {
+    key: (bool, String),
+    pub expr: Expr<'a>,
+}
+
+impl<'a> Atom<'a> {
+    pub fn new(expr: Expr<'a>) -> Atom<'a> {
+        let compound = !matches!(expr, Expr::Variable { .. } | Expr::SpecialVariable { .. });
+        Atom {
+            key: (compound, to_infix(&expr)),
+            expr,
+        }
+    }
+}
+
+impl PartialEq for Atom<'_> {
+    fn eq(&self, other: &Atom<'_>) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for Atom<'_> {}
+
+impl PartialOrd for Atom<'_> {
+    fn partial_cmp(&self, other: &Atom<'_>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Atom<'_> {
+    fn cmp(&self, other: &Atom<'_>) -> Ordering {
+        self.key.cmp(&other.key)
+    }
+}
+
+// product of atoms raised to positive powers, kept sorted by atom
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Monomial<'a>(pub Vec<(Atom<'a>, u32)>);
+
+impl<'a> Monomial<'a> {
+    pub fn one() -> Monomial<'a> {
+        Monomial(Vec::new())
+    }
+
+    pub fn degree(&self) -> u32 {
+        self.0.iter().map(|(_, exp)| exp).sum()
+    }
+
+    fn mul(&self, other: &Monomial<'a>) -> Monomial<'a> {
+        let mut factors = self.0.clone();
+        for (atom, exp) in other.0.iter() {
+            match factors.binary_search_by(|(a, _)| a.cmp(atom)) {
+                Ok(i) => factors[i].1 += exp,
+                Err(i) => factors.insert(i, (atom.clone(), *exp)),
+            }
+        }
+        Monomial(factors)
+    }
+}
+
+// canonical order: highest degree first, then lexicographic by variable with
+// higher powers of earlier variables first, so x^2 comes before x*y
+impl PartialOrd for Monomial<'_> {
+    fn partial_cmp(&self, other: &Monomial<'_>) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Monomial<'_> {
+    fn cmp(&self, other: &Monomial<'_>) -> Ordering {
+        other.degree().cmp(&self.degree()).then_with(|| {
+            for ((a, a_exp), (b, b_exp)) in self.0.iter().zip(other.0.iter()) {
+                let ord = a.cmp(b).then(b_exp.cmp(a_exp));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            self.0.len().cmp(&other.0.len())
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Poly<'a> {
+    pub terms: BTreeMap<Monomial<'a>, Rational>,
+}
+
+// powers bigger than this are left alone rather than multiplied out
+const MAX_EXPAND_POWER: u64 = 64;
+
+impl<'a> Poly<'a> {
+    pub fn zero() -> Poly<'a> {
+        Poly {
+            terms: BTreeMap::new(),
+        }
+    }
+
+    pub fn constant(value: Rational) -> Poly<'a> {
+        let mut poly = Poly::zero();
+        poly.insert_term(Monomial::one(), value);
+        poly
+    }
+
+    pub fn atom(expr: Expr<'a>) -> Poly<'a> {
+        let mut poly = Poly::zero();
+        poly.insert_term(Monomial(vec![(Atom::new(expr), 1)]), Rational::ONE);
+        poly
+    }
+
+    // for a monomial that isn't there yet
+    fn insert_term(&mut self, monomial: Monomial<'a>, coefficient: Rational) {
+        if !coefficient.is_zero() {
+            self.terms.insert(monomial, coefficient);
+        }
+    }
+
+    fn add_term(
+        &mut self,
+        monomial: Monomial<'a>,
+        coefficient: Rational,
+    ) -> Result<(), &'static str> {
+        let sum = match self.terms.get(&monomial) {
+            Some(existing) => existing.checked_add(coefficient)?,
+            None => coefficient,
+        };
+        if sum.is_zero() {
+            self.terms.remove(&monomial);
+        } else {
+            self.terms.insert(monomial, sum);
+        }
+        Ok(())
+    }
+
+    pub fn as_constant(&self) -> Option<Rational> {
+        match self.terms.len() {
+            0 => Some(Rational::ZERO),
+            1 => self.terms.get(&Monomial::one()).copied(),
+            _ => None,
+        }
+    }
+
+    pub fn add(&self, other: &Poly<'a>) -> Result<Poly<'a>, &'static str> {
+        let mut sum = self.clone();
+        for (monomial, coefficient) in other.terms.iter() {
+            sum.add_term(monomial.clone(), *coefficient)?;
+        }
+        Ok(sum)
+    }
+
+    pub fn neg(&self) -> Poly<'a> {
+        Poly {
+            terms: self.terms.iter().map(|(m, c)| (m.clone(), -*c)).collect(),
+        }
+    }
+
+    pub fn sub(&self, other: &Poly<'a>) -> Result<Poly<'a>, &'static str> {
+        self.add(&other.neg())
+    }
+
+    pub fn scale(&self, by: Rational) -> Result<Poly<'a>, &'static str> {
+        let mut scaled = Poly::zero();
+        for (monomial, coefficient) in self.terms.iter() {
+            scaled.insert_term(monomial.clone(), coefficient.checked_mul(by)?);
+        }
+        Ok(scaled)
+    }
+
+    pub fn mul(&self, other: &Poly<'a>) -> Result<Poly<'a>, &'static str> {
+        let mut product = Poly::zero();
+        for (a, a_coefficient) in self.terms.iter() {
+            for (b, b_coefficient) in other.terms.iter() {
+                product.add_term(a.mul(b), a_coefficient.checked_mul(*b_coefficient)?)?;
+            }
+        }
+        Ok(product)
+    }
+
+    pub fn pow(&self, exp: u32) -> Result<Poly<'a>, &'static str> {
+        if self.terms.len() == 2 {
+            return self.binomial(exp);
+        }
+
+        let mut result = Poly::constant(Rational::ONE);
+        let mut base = self.clone();
+        let mut exp = exp;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                result = result.mul(&base)?;
+            }
+            exp >>= 1;
+            if exp > 0 {
+                base = base.mul(&base)?;
+            }
+        }
+        Ok(result)
+    }
+
+    // (a + b)^n = sum of C(n, k) a^(n-k) b^k
+    fn binomial(&self, n: u32) -> Result<Poly<'a>, &'static str> {
+        let mut terms = self.terms.iter();
+        let (a_monomial, a_coefficient) = terms.next().unwrap();
+        let (b_monomial, b_coefficient) = terms.next().unwrap();
+
+        let mut a_powers = vec![(Monomial::one(), Rational::ONE)];
+        let mut b_powers = vec![(Monomial::one(), Rational::ONE)];
+        for k in 0..n as usize {
+            let (m, c) = &a_powers[k];
+            a_powers.push((m.mul(a_monomial), c.checked_mul(*a_coefficient)?));
+            let (m, c) = &b_powers[k];
+            b_powers.push((m.mul(b_monomial), c.checked_mul(*b_coefficient)?));
+        }
+
+        let mut result = Poly::zero();
+        let mut choose = Rational::ONE;
+        for k in 0..=n as usize {
+            let (a_m, a_c) = &a_powers[n as usize - k];
+            let (b_m, b_c) = &b_powers[k];
+            result.add_term(a_m.mul(b_m), choose.checked_mul(*a_c)?.checked_mul(*b_c)?)?;
+            choose = choose
+                .checked_mul(Rational::integer((n as usize - k) as i128))?
+                .checked_div(Rational::integer(k as i128 + 1))?;
+        }
+        Ok(result)
+    }
+
+    pub fn from_expr(expr: &Expr<'a>) -> Result<Poly<'a>, &'static str> {
+        Ok(match expr {
+            Expr::Literal { literal } => match literal.kind {
+                TokenKind::Integer(i) => Poly::constant(Rational::integer(i as i128)),
+                TokenKind::Float(f) => match Rational::from_f64(f) {
+                    Some(r) => Poly::constant(r),
+                    None => Poly::atom(expr.clone()),
+                },
+                _ => Poly::atom(expr.clone()),
+            },
+
+            Expr::Variable { .. } | Expr::SpecialVariable { .. } => Poly::atom(expr.clone()),
+
+            Expr::Unary { op, rhs } => {
+                if op.kind == TokenKind::Minus {
+                    Poly::from_expr(rhs)?.neg()
+                } else {
+                    Poly::atom(Expr::Unary {
+                        op: *op,
+                        rhs: Box::new(rhs.expand()?),
+                    })
+                }
+            }
+
+            Expr::Binary { lhs, op, rhs } => {
+                let l = Poly::from_expr(lhs)?;
+                let r = Poly::from_expr(rhs)?;
+                let opaque = || {
+                    Poly::atom(Expr::Binary {
+                        lhs: Box::new(l.to_expr()),
+                        op: *op,
+                        rhs: Box::new(r.to_expr()),
+                    })
+                };
+
+                match op.kind {
+                    TokenKind::Plus => l.add(&r)?,
+                    TokenKind::Minus => l.sub(&r)?,
+                    TokenKind::Multiply => l.mul(&r)?,
+                    TokenKind::Divide => match r.as_constant() {
+                        Some(c) if !c.is_zero() => l.scale(c.recip())?,
+                        _ => opaque(),
+                    },
+                    TokenKind::Power => match r.as_constant() {
+                        Some(c)
+                            if c.is_integer()
+                                && !c.is_negative()
+                                && c.num() as u64 <= MAX_EXPAND_POWER =>
+                        {
+                            l.pow(c.num() as u32)?
+                        }
+                        _ => opaque(),
+                    },
+                    _ => opaque(),
+                }
+            }
+
+            Expr::Call { name, args } => Poly::atom(Expr::Call {
+                name: *name,
+                args: args
+                    .iter()
+                    .map(|arg| arg.expand())
+                    .collect::<Result<_, _>>()?,
+            }),
+
+            Expr::Piecewise { .. } | Expr::Matrix { .. } => Poly::atom(expr.clone()),
+        })
+    }
+
+    // split into content * monomial * rest where rest has integer coefficients
+    // with no common divisor, a positive leading term, and no atom that
+    // divides every term
+    pub fn common_factor(&self) -> Result<(Rational, Monomial<'a>, Poly<'a>), &'static str> {
+        let mut terms = self.terms.iter();
+        let (first, first_coefficient) = match terms.next() {
+            Some(term) => term,
+            None => return Ok((Rational::ZERO, Monomial::one(), Poly::zero())),
+        };
+
+        let mut common = first.0.clone();
+        let mut num = first_coefficient.num();
+        let mut den = first_coefficient.den();
+        for (monomial, coefficient) in terms {
+            common.retain_mut(|(atom, exp)| {
+                match monomial.0.iter().find(|(other, _)| other == atom) {
+                    Some((_, other_exp)) => {
+                        *exp = (*exp).min(*other_exp);
+                        true
+                    }
+                    None => false,
+                }
+            });
+            num = gcd(num, coefficient.num());
+            den = (den / gcd(den, coefficient.den()))
+                .checked_mul(coefficient.den())
+                .ok_or(OVERFLOW)?;
+        }
+
+        let mut content = Rational::new(num, den);
+        if first_coefficient.is_negative() {
+            content = -content;
+        }
+
+        let mut rest = Poly::zero();
+        for (monomial, coefficient) in self.terms.iter() {
+            let factors = monomial
+                .0
+                .iter()
+                .filter_map(|(atom, exp)| {
+                    let common_exp = common
+                        .iter()
+                        .find(|(other, _)| other == atom)
+                        .map_or(0, |(_, e)| *e);
+                    if *exp > common_exp {
+                        Some((atom.clone(), exp - common_exp))
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+            rest.insert_term(Monomial(factors), coefficient.checked_div(content)?);
+        }
+
+        Ok((content, Monomial(common), rest))
+    }
+
+    // the single atom this is a polynomial in and its coefficients, lowest
+    // degree first. constants have no atom.
+    pub fn univariate(&self) -> Option<(Option<Atom<'a>>, Vec<Rational>)> {
+        let mut atom: Option<Atom<'a>> = None;
+        let mut coefficients = Vec::new();
+
+        for (monomial, coefficient) in self.terms.iter() {
+            let degree = match monomial.0.as_slice() {
+                [] => 0,
+                [(a, exp)] => {
+                    match &atom {
+                        Some(existing) if existing != a => return None,
+                        _ => atom = Some(a.clone()),
+                    }
+                    *exp as usize
+                }
+                _ => return None,
+            };
+
+            if coefficients.len() <= degree {
+                coefficients.resize(degree + 1, Rational::ZERO);
+            }
+            coefficients[degree] = *coefficient;
+        }
+
+        Some((atom, coefficients))
+    }
+
+    // coefficients of each power of the variable `name`, lowest first, each of
+    // which may still involve other atoms. None if the variable shows up
+    // somewhere other than a plain power, like inside a call.
+    pub fn coefficients_in(&self, name: &str) -> Option<Vec<Poly<'a>>> {
+        let mut coefficients = Vec::new();
+
+        for (monomial, coefficient) in self.terms.iter() {
+            let mut degree = 0;
+            let mut rest = Vec::new();
+            for (atom, exp) in monomial.0.iter() {
+                match &atom.expr {
+                    Expr::Variable { name: var } | Expr::SpecialVariable { name: var }
+                        if var.lexeme == name =>
+                    {
+                        degree = *exp as usize
+                    }
+                    expr if depends_on(expr, name) => return None,
+                    _ => rest.push((atom.clone(), *exp)),
+                }
+            }
+
+            if coefficients.len() <= degree {
+                coefficients.resize(degree + 1, Poly::zero());
+            }
+            coefficients[degree].insert_term(Monomial(rest), *coefficient);
+        }
+
+        Some(coefficients)
+    }
+
+    // whether `name` is stuck inside a power too big to multiply out, which
+    // is why coefficients_in gave up
+    pub fn too_high_power(&self, name: &str) -> bool {
+        self.terms
+            .keys()
+            .flat_map(|monomial| monomial.0.iter())
+            .any(|(atom, _)| match &atom.expr {
+                Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Power => {
+                    depends_on(lhs, name)
+                        && Poly::from_expr(rhs).is_ok_and(|exp| {
+                            exp.as_constant().is_some_and(|c| {
+                                c.is_integer() && c.num() > MAX_EXPAND_POWER as i128
+                            })
+                        })
+                }
+                _ => false,
+            })
+    }
+
+    pub fn from_dense(atom: &Atom<'a>, coefficients: &[Rational]) -> Poly<'a> {
+        let mut poly = Poly::zero();
+        for (degree, coefficient) in coefficients.iter().enumerate() {
+            let monomial = if degree == 0 {
+                Monomial::one()
+            } else {
+                Monomial(vec![(atom.clone(), degree as u32)])
+            };
+            poly.insert_term(monomial, *coefficient);
+        }
+        poly
+    }
+
+    pub fn to_expr(&self) -> Expr<'a> {
+        let mut result: Option<Expr<'a>> = None;
+
+        for (monomial, coefficient) in self.terms.iter() {
+            let term = term_expr(monomial, coefficient.abs());
+            result = Some(match result {
+                None if coefficient.is_negative() => Expr::Unary {
+                    op: Token::new(TokenKind::Minus, "-"),
+                    rhs: Box::new(term),
+                },
+                None => term,
+                Some(sum) => Expr::Binary {
+                    lhs: Box::new(sum),
+                    op: if coefficient.is_negative() {
+                        Token::new(TokenKind::Minus, "-")
+                    } else {
+                        Token::new(TokenKind::Plus, "+")
+                    },
+                    rhs: Box::new(term),
+                },
+            });
+        }
+
+        result.unwrap_or_else(|| integer(0))
+    }
+}
+
+pub fn integer<'a>(value: i128) -> Expr<'a> {
+    let literal = match u64::try_from(value.unsigned_abs()) {
+        Ok(n) => Token::integer(n),
+        // too big for an integer token, so as close as a float gets
+        Err(_) => Token::new(TokenKind::Float(value.unsigned_abs() as f64), ""),
+    };
+    let literal = Expr::Literal { literal };
+    if value < 0 {
+        Expr::Unary {
+            op: Token::new(TokenKind::Minus, "-"),
+            rhs: Box::new(literal),
+        }
+    } else {
+        literal
+    }
+}
+
+pub fn binary<'a>(lhs: Expr<'a>, kind: TokenKind, lexeme: &'static str, rhs: Expr<'a>) -> Expr<'a> {
+    Expr::Binary {
+        lhs: Box::new(lhs),
+        op: Token::new(kind, lexeme),
+        rhs: Box::new(rhs),
+    }
+}
+
+// one non-negative term, written as 3*x^2*y or x/2
+fn term_expr<'a>(monomial: &Monomial<'a>, coefficient: Rational) -> Expr<'a> {
+    let mut factors = monomial.0.iter().map(|(atom, exp)| {
+        if *exp == 1 {
+            atom.expr.clone()
+        } else {
+            binary(
+                atom.expr.clone(),
+                TokenKind::Power,
+                "^",
+                integer(*exp as i128),
+            )
+        }
+    });
+
+    let numerator = match factors.next() {
+        None => integer(coefficient.num()),
+        Some(first) => {
+            let product =
+                factors.fold(first, |lhs, rhs| binary(lhs, TokenKind::Multiply, "*", rhs));
+            if coefficient.num() == 1 {
+                product
+            } else {
+                binary(
+                    integer(coefficient.num()),
+                    TokenKind::Multiply,
+                    "*",
+                    product,
+                )
+            }
+        }
+    };
+
+    if coefficient.is_integer() {
+        numerator
+    } else {
+        binary(
+            numerator,
+            TokenKind::Divide,
+            "/",
+            integer(coefficient.den()),
+        )
+    }
+}
+
+impl<'a> Expr<'a> {
+    // multiply everything out into a canonical sum of monomials. the two sides
+    // of a comparison are expanded separately.
+    pub fn expand(&self) -> Result<Expr<'a>, &'static str> {
+        Ok(match self {
+            Expr::Binary { lhs, op, rhs } if op.kind.is_comparison() => Expr::Binary {
+                lhs: Box::new(lhs.expand()?),
+                op: *op,
+                rhs: Box::new(rhs.expand()?),
+            },
+            _ => Poly::from_expr(self)?.to_expr(),
+        })
+    }
+}
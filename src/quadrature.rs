@@ -0,0 +1,324 @@
+use super::ast::*;
+use super::eval::{self, Env};
+use super::poly::integer;
+use super::scan::*;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    // stop once the estimated error is this small, relative to the value
+    pub tolerance: f64,
+    // how many times the interval may be split
+    pub max_subdivisions: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            tolerance: 1e-10,
+            max_subdivisions: 500,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub value: f64,
+    pub error: f64,
+}
+
+const NOT_CONVERGED: &str = "integral didn't converge, it may diverge";
+
+fn finite(y: f64) -> Result<f64, &'static str> {
+    if y.is_finite() {
+        Ok(y)
+    } else {
+        Err("the integrand isn't finite on the interval")
+    }
+}
+
+// adaptive simpson's rule. each step compares one parabola over the interval
+// with two over its halves, the difference is the error estimate. the ends
+// are evaluated so this only works on proper integrals.
+pub fn simpson(
+    f: impl Fn(f64) -> Result<f64, &'static str>,
+    a: f64,
+    b: f64,
+    options: &Options,
+) -> Result<Estimate, &'static str> {
+    let fa = finite(f(a)?)?;
+    let fb = finite(f(b)?)?;
+    let m = (a + b) / 2.0;
+    let fm = finite(f(m)?)?;
+    let whole = (b - a) / 6.0 * (fa + 4.0 * fm + fb);
+    let mut budget = options.max_subdivisions;
+    let tolerance = options.tolerance * whole.abs().max(1.0);
+    simpson_step(&f, (a, fa), (m, fm), (b, fb), whole, tolerance, &mut budget)
+}
+
+fn simpson_step(
+    f: &impl Fn(f64) -> Result<f64, &'static str>,
+    (a, fa): (f64, f64),
+    (m, fm): (f64, f64),
+    (b, fb): (f64, f64),
+    whole: f64,
+    tolerance: f64,
+    budget: &mut usize,
+) -> Result<Estimate, &'static str> {
+    let (lm, rm) = ((a + m) / 2.0, (m + b) / 2.0);
+    let (flm, frm) = (finite(f(lm)?)?, finite(f(rm)?)?);
+    let left = (m - a) / 6.0 * (fa + 4.0 * flm + fm);
+    let right = (b - m) / 6.0 * (fm + 4.0 * frm + fb);
+    let delta = left + right - whole;
+
+    // richardson extrapolation, the error of the halves is about delta / 15
+    if delta.abs() <= 15.0 * tolerance {
+        return Ok(Estimate {
+            value: left + right + delta / 15.0,
+            error: delta.abs() / 15.0,
+        });
+    }
+
+    if *budget == 0 {
+        return Err(NOT_CONVERGED);
+    }
+    *budget -= 1;
+
+    let l = simpson_step(
+        f,
+        (a, fa),
+        (lm, flm),
+        (m, fm),
+        left,
+        tolerance / 2.0,
+        budget,
+    )?;
+    let r = simpson_step(
+        f,
+        (m, fm),
+        (rm, frm),
+        (b, fb),
+        right,
+        tolerance / 2.0,
+        budget,
+    )?;
+    Ok(Estimate {
+        value: l.value + r.value,
+        error: l.error + r.error,
+    })
+}
+
+// 15 point kronrod nodes on [0, 1), the odd ones are also the 7 point gauss
+// nodes, and they're symmetric about 0
+const KRONROD_NODES: [f64; 8] = [
+    0.9914553711208126,
+    0.9491079123427585,
+    0.8648644233597691,
+    0.7415311855993945,
+    0.5860872354676911,
+    0.4058451513773972,
+    0.20778495500789848,
+    0.0,
+];
+const KRONROD_WEIGHTS: [f64; 8] = [
+    0.022935322010529224,
+    0.06309209262997856,
+    0.10479001032225019,
+    0.14065325971552592,
+    0.1690047266392679,
+    0.19035057806478542,
+    0.20443294007529889,
+    0.20948214108472782,
+];
+const GAUSS_WEIGHTS: [f64; 4] = [
+    0.1294849661688697,
+    0.27970539148927664,
+    0.3818300505051189,
+    0.4179591836734694,
+];
+
+// one 15 point kronrod rule over [a, b], with the difference from the
+// embedded 7 point gauss rule as the error
+fn kronrod(
+    f: &impl Fn(f64) -> Result<f64, &'static str>,
+    a: f64,
+    b: f64,
+) -> Result<Estimate, &'static str> {
+    let center = (a + b) / 2.0;
+    let half = (b - a) / 2.0;
+    let mut kronrod = 0.0;
+    let mut gauss = 0.0;
+
+    for (i, (node, weight)) in KRONROD_NODES.iter().zip(KRONROD_WEIGHTS.iter()).enumerate() {
+        let y = if *node == 0.0 {
+            finite(f(center)?)?
+        } else {
+            finite(f(center - half * node)?)? + finite(f(center + half * node)?)?
+        };
+        kronrod += weight * y;
+        if i % 2 == 1 {
+            gauss += GAUSS_WEIGHTS[i / 2] * y;
+        }
+    }
+
+    Ok(Estimate {
+        value: kronrod * half,
+        error: ((kronrod - gauss) * half).abs(),
+    })
+}
+
+// adaptive gauss-kronrod: keep splitting whichever piece has the largest
+// error. the nodes are all inside the interval, so infinite ends can be
+// mapped onto a finite one first.
+pub fn gauss_kronrod(
+    f: impl Fn(f64) -> Result<f64, &'static str>,
+    a: f64,
+    b: f64,
+    options: &Options,
+) -> Result<Estimate, &'static str> {
+    if a == b {
+        return Ok(Estimate {
+            value: 0.0,
+            error: 0.0,
+        });
+    }
+    if a > b {
+        return gauss_kronrod(f, b, a, options).map(|estimate| Estimate {
+            value: -estimate.value,
+            ..estimate
+        });
+    }
+
+    match (a.is_infinite(), b.is_infinite()) {
+        // simpson's rule gets its own budget when gauss-kronrod runs out
+        (false, false) => match adaptive(&f, a, b, options) {
+            Err(NOT_CONVERGED) => simpson(&f, a, b, options),
+            result => result,
+        },
+        // x = a + t / (1 - t)
+        (false, true) => adaptive(
+            &|t: f64| Ok(scaled(f(a + t / (1.0 - t))?, (1.0 - t) * (1.0 - t))),
+            0.0,
+            1.0,
+            options,
+        ),
+        // x = b - (1 - t) / t
+        (true, false) => adaptive(
+            &|t: f64| Ok(scaled(f(b - (1.0 - t) / t)?, t * t)),
+            0.0,
+            1.0,
+            options,
+        ),
+        // x = t / (1 - t^2)
+        (true, true) => adaptive(
+            &|t: f64| {
+                let s = 1.0 - t * t;
+                Ok(scaled(f(t / s)?, s * s / (1.0 + t * t)))
+            },
+            -1.0,
+            1.0,
+            options,
+        ),
+    }
+}
+
+// y / dt for a substituted integrand. near the ends of [0, 1] the integrand
+// usually underflows while dt goes to 0, and 0 / 0 would be NaN.
+fn scaled(y: f64, dt: f64) -> f64 {
+    if y == 0.0 {
+        0.0
+    } else {
+        y / dt
+    }
+}
+
+fn adaptive(
+    f: &impl Fn(f64) -> Result<f64, &'static str>,
+    a: f64,
+    b: f64,
+    options: &Options,
+) -> Result<Estimate, &'static str> {
+    let mut pieces = vec![(a, b, kronrod(f, a, b)?)];
+
+    for _ in 0..options.max_subdivisions {
+        let value: f64 = pieces.iter().map(|(_, _, e)| e.value).sum();
+        let error: f64 = pieces.iter().map(|(_, _, e)| e.error).sum();
+        if error <= options.tolerance * value.abs().max(1.0) {
+            return Ok(Estimate { value, error });
+        }
+
+        let worst = (0..pieces.len())
+            .max_by(|i, j| {
+                pieces[*i]
+                    .2
+                    .error
+                    .partial_cmp(&pieces[*j].2.error)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap();
+        let (lo, hi, _) = pieces.swap_remove(worst);
+        // out of floating point precision, usually near a singularity
+        if hi - lo <= 1e3 * f64::EPSILON * lo.abs().max(hi.abs()) {
+            return Err(NOT_CONVERGED);
+        }
+        let mid = (lo + hi) / 2.0;
+        pieces.push((lo, mid, kronrod(f, lo, mid)?));
+        pieces.push((mid, hi, kronrod(f, mid, hi)?));
+    }
+
+    Err(NOT_CONVERGED)
+}
+
+// the integral of `expr` over [a, b] with respect to `var`, either end may be
+// infinite
+pub fn integrate(
+    expr: &Expr<'_>,
+    var: &str,
+    a: f64,
+    b: f64,
+    env: &Env,
+    options: &Options,
+) -> Result<Estimate, &'static str> {
+    gauss_kronrod(
+        |x| eval::eval_number(expr, &env.with(var, x)),
+        a,
+        b,
+        options,
+    )
+}
+
+// split `f dx` into f and x
+pub fn differential<'a, 'e>(expr: &'e Expr<'a>) -> Option<(Expr<'a>, &'a str)> {
+    let var = |expr: &Expr<'a>| match expr {
+        Expr::SpecialVariable { name } if name.lexeme.starts_with('d') => Some(&name.lexeme[1..]),
+        _ => None,
+    };
+
+    match expr {
+        Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Multiply => {
+            match (var(rhs), var(lhs)) {
+                (Some(x), _) => Some((*lhs.clone(), x)),
+                (_, Some(x)) => Some((*rhs.clone(), x)),
+                _ => None,
+            }
+        }
+        _ => var(expr).map(|x| (integer(1), x)),
+    }
+}
+
+// integrate(expr, x, a, b), or int(expr dx, a, b)
+pub fn call(args: &[Expr<'_>], env: &Env) -> Result<Estimate, &'static str> {
+    let (expr, var, a, b) = match args {
+        [expr, Expr::Variable { name } | Expr::SpecialVariable { name }, a, b] => {
+            (expr.clone(), name.lexeme, a, b)
+        }
+        [expr, a, b] => match differential(expr) {
+            Some((expr, var)) => (expr, var, a, b),
+            None => return Err("expected the integrand to end in dx"),
+        },
+        _ => return Err("integrate takes an expression, a variable and two limits"),
+    };
+
+    let a = eval::eval_number(a, env)?;
+    let b = eval::eval_number(b, env)?;
+    integrate(&expr, var, a, b, env, &Options::default())
+}
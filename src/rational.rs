@@ -0,0 +1,191 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::Neg;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+impl Rational {
+    pub const ZERO: Rational = Rational { num: 0, den: 1 };
+    pub const ONE: Rational = Rational { num: 1, den: 1 };
+
+    pub fn new(num: i128, den: i128) -> Rational {
+        assert!(den != 0, "rational with zero denominator");
+        let g = gcd(num, den);
+        let sign = if den < 0 { -1 } else { 1 };
+        Rational {
+            num: sign * num / g,
+            den: sign * den / g,
+        }
+    }
+
+    pub fn integer(n: i128) -> Rational {
+        Rational { num: n, den: 1 }
+    }
+
+    // exact value of the shortest decimal that round-trips to `f`
+    pub fn from_f64(f: f64) -> Option<Rational> {
+        if !f.is_finite() {
+            return None;
+        }
+
+        let text = format!("{}", f);
+        let (int, frac) = match text.find('.') {
+            Some(dot) => (&text[..dot], &text[dot + 1..]),
+            None => (&text[..], ""),
+        };
+
+        let mut den: i128 = 1;
+        for _ in 0..frac.len() {
+            den = den.checked_mul(10)?;
+        }
+        let digits = format!("{}{}", int, frac);
+        Some(Rational::new(digits.parse().ok()?, den))
+    }
+
+    pub fn num(self) -> i128 {
+        self.num
+    }
+
+    pub fn den(self) -> i128 {
+        self.den
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.num == 0
+    }
+
+    pub fn is_integer(self) -> bool {
+        self.den == 1
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.num < 0
+    }
+
+    pub fn abs(self) -> Rational {
+        Rational {
+            num: self.num.abs(),
+            den: self.den,
+        }
+    }
+
+    pub fn recip(self) -> Rational {
+        Rational::new(self.den, self.num)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.num as f64 / self.den as f64
+    }
+}
+
+pub fn gcd(a: i128, b: i128) -> i128 {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while b != 0 {
+        let t = a % b;
+        a = b;
+        b = t;
+    }
+    if a == 0 {
+        1
+    } else {
+        a
+    }
+}
+
+// arithmetic that would overflow an i128 fails instead of wrapping
+pub const OVERFLOW: &str = "number too large for exact arithmetic";
+
+fn checked(num: Option<i128>, den: Option<i128>) -> Result<Rational, &'static str> {
+    match (num, den) {
+        // MIN has no negation, so keep clear of it
+        (Some(num), Some(den)) if num != i128::MIN && den != i128::MIN => {
+            Ok(Rational::new(num, den))
+        }
+        _ => Err(OVERFLOW),
+    }
+}
+
+impl Rational {
+    pub fn checked_add(self, rhs: Rational) -> Result<Rational, &'static str> {
+        let g = gcd(self.den, rhs.den);
+        let (a, b) = (self.den / g, rhs.den / g);
+        let num = self
+            .num
+            .checked_mul(b)
+            .and_then(|lhs| rhs.num.checked_mul(a).and_then(|rhs| lhs.checked_add(rhs)));
+        checked(num, self.den.checked_mul(b))
+    }
+
+    pub fn checked_sub(self, rhs: Rational) -> Result<Rational, &'static str> {
+        self.checked_add(-rhs)
+    }
+
+    pub fn checked_mul(self, rhs: Rational) -> Result<Rational, &'static str> {
+        // cancel across first so the products stay small
+        let g = gcd(self.num, rhs.den);
+        let h = gcd(rhs.num, self.den);
+        let num = (self.num / g).checked_mul(rhs.num / h);
+        let den = (self.den / h).checked_mul(rhs.den / g);
+        checked(num, den)
+    }
+
+    pub fn checked_div(self, rhs: Rational) -> Result<Rational, &'static str> {
+        if rhs.is_zero() {
+            return Err("division by zero");
+        }
+        self.checked_mul(rhs.recip())
+    }
+}
+
+impl Neg for Rational {
+    type Output = Rational;
+    fn neg(self) -> Rational {
+        Rational {
+            num: -self.num,
+            den: self.den,
+        }
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Rational) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// compares whole parts, then the reciprocals of what's left over, like
+// continued fractions, so nothing is multiplied and nothing overflows
+impl Ord for Rational {
+    fn cmp(&self, other: &Rational) -> Ordering {
+        let (mut a, mut b) = (self.num, self.den);
+        let (mut c, mut d) = (other.num, other.den);
+        loop {
+            let ord = a.div_euclid(b).cmp(&c.div_euclid(d));
+            if ord != Ordering::Equal {
+                return ord;
+            }
+            let (r, s) = (a.rem_euclid(b), c.rem_euclid(d));
+            match (r == 0, s == 0) {
+                (true, true) => return Ordering::Equal,
+                (true, false) => return Ordering::Less,
+                (false, true) => return Ordering::Greater,
+                // r/b against s/d is d/s against b/r
+                _ => (a, b, c, d) = (d, s, b, r),
+            }
+        }
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
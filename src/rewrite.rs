@@ -0,0 +1,387 @@
+use super::ast::*;
+use super::parse;
+use super::scan::*;
+
+use std::collections::HashMap;
+
+pub const DEFAULT_RULES: &str = "
+# identities applied to every input. they have to hold for any value, a
+# matrix or a negative number included, so x * 0 -> 0 and ln(a*b) -> ln(a) +
+# ln(b) aren't here.
+x + 0 -> x
+x * 1 -> x
+x ^ 1 -> x
+sin(u)^2 + cos(u)^2 -> 1
+";
+
+pub const DEFAULT_STEP_LIMIT: usize = 100;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Rule<'a> {
+    pub lhs: Expr<'a>,
+    pub rhs: Expr<'a>,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct RuleSet<'a> {
+    pub rules: Vec<Rule<'a>>,
+}
+
+impl<'a> RuleSet<'a> {
+    // one rule per line in the form `pattern -> replacement`, blank lines and
+    // lines starting with `#` are skipped. every variable in a pattern matches
+    // an arbitrary subtree.
+    pub fn parse(source: &'a str) -> Result<RuleSet<'a>, &'static str> {
+        let mut rules = Vec::new();
+
+        for line in source.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let arrow = line.find("->").ok_or("rule is missing ->")?;
+            let lhs = parse::parse(&mut Scanner::new(&line[..arrow]))?;
+            let rhs = parse::parse(&mut Scanner::new(&line[arrow + 2..]))?;
+            rules.push(Rule { lhs, rhs });
+        }
+
+        Ok(RuleSet { rules })
+    }
+
+    pub fn extend(&mut self, other: RuleSet<'a>) {
+        self.rules.extend(other.rules);
+    }
+}
+
+pub type Bindings<'a> = HashMap<&'a str, Expr<'a>>;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Rewrite<'a> {
+    pub expr: Expr<'a>,
+    pub steps: usize,
+    pub converged: bool,
+}
+
+// apply rules bottom-up until nothing changes or `limit` rewrites have been made
+pub fn simplify<'a>(rules: &RuleSet<'a>, expr: Expr<'a>, limit: usize) -> Rewrite<'a> {
+    let mut pass = Pass {
+        rules,
+        steps: 0,
+        limit,
+    };
+    let mut expr = expr;
+
+    loop {
+        let before = pass.steps;
+        expr = pass.fold_expr(expr);
+
+        if pass.steps == before {
+            return Rewrite {
+                expr,
+                steps: pass.steps,
+                converged: true,
+            };
+        }
+
+        if pass.steps >= limit {
+            return Rewrite {
+                expr,
+                steps: pass.steps,
+                converged: false,
+            };
+        }
+    }
+}
+
+struct Pass<'r, 'a> {
+    rules: &'r RuleSet<'a>,
+    steps: usize,
+    limit: usize,
+}
+
+impl<'a> Fold<'a> for Pass<'_, 'a> {
+    fn fold_expr(&mut self, expr: Expr<'a>) -> Expr<'a> {
+        let expr = fold_expr(self, expr);
+        if self.steps >= self.limit {
+            return expr;
+        }
+
+        for rule in self.rules.rules.iter() {
+            if let Some(rewritten) = apply(rule, &expr) {
+                self.steps += 1;
+                return rewritten;
+            }
+        }
+
+        expr
+    }
+}
+
+// try a single rule at the root of `expr`
+pub fn apply<'a>(rule: &Rule<'a>, expr: &Expr<'a>) -> Option<Expr<'a>> {
+    if let (Some(op), Some(pattern_op)) = (ac_op(expr), ac_op(&rule.lhs)) {
+        if op.kind != pattern_op.kind {
+            return None;
+        }
+
+        // a sum or product pattern may match some of the operands of a longer
+        // chain, in which case the rest are kept next to the replacement
+        let patterns = flatten(&rule.lhs, op.kind);
+        let subjects = flatten(expr, op.kind);
+        let (bindings, rest) = match_operands(op, &patterns, &subjects, Bindings::new(), true)?;
+
+        let mut operands: Vec<Expr<'a>> = rest.into_iter().cloned().collect();
+        operands.push(substitute(&rule.rhs, &bindings));
+        return Some(rebuild(op, operands));
+    }
+
+    let bindings = match_expr(&rule.lhs, expr, Bindings::new())?;
+    Some(substitute(&rule.rhs, &bindings))
+}
+
+pub fn match_expr<'a>(
+    pattern: &Expr<'a>,
+    subject: &Expr<'a>,
+    mut bindings: Bindings<'a>,
+) -> Option<Bindings<'a>> {
+    match (pattern, subject) {
+        (Expr::Variable { name }, _) => {
+            if let Some(bound) = bindings.get(name.lexeme) {
+                if bound == subject {
+                    Some(bindings)
+                } else {
+                    None
+                }
+            } else {
+                bindings.insert(name.lexeme, subject.clone());
+                Some(bindings)
+            }
+        }
+
+        (Expr::Literal { literal: p }, Expr::Literal { literal: s }) => {
+            if p.kind == s.kind {
+                Some(bindings)
+            } else {
+                None
+            }
+        }
+
+        (Expr::SpecialVariable { name: p }, Expr::SpecialVariable { name: s }) => {
+            if p.lexeme == s.lexeme {
+                Some(bindings)
+            } else {
+                None
+            }
+        }
+
+        (
+            Expr::Unary {
+                op: p_op,
+                rhs: p_rhs,
+            },
+            Expr::Unary {
+                op: s_op,
+                rhs: s_rhs,
+            },
+        ) => {
+            if p_op.kind != s_op.kind {
+                return None;
+            }
+            match_expr(p_rhs, s_rhs, bindings)
+        }
+
+        (
+            Expr::Binary {
+                lhs: p_lhs,
+                op: p_op,
+                rhs: p_rhs,
+            },
+            Expr::Binary {
+                lhs: s_lhs,
+                op: s_op,
+                rhs: s_rhs,
+            },
+        ) => {
+            if p_op.kind != s_op.kind {
+                return None;
+            }
+
+            if is_ac(p_op.kind) {
+                let patterns = flatten(pattern, p_op.kind);
+                let subjects = flatten(subject, s_op.kind);
+                let (bindings, _) = match_operands(*s_op, &patterns, &subjects, bindings, false)?;
+                return Some(bindings);
+            }
+
+            let bindings = match_expr(p_lhs, s_lhs, bindings)?;
+            match_expr(p_rhs, s_rhs, bindings)
+        }
+
+        (
+            Expr::Call {
+                name: p_name,
+                args: p_args,
+            },
+            Expr::Call {
+                name: s_name,
+                args: s_args,
+            },
+        ) => {
+            if p_name.lexeme != s_name.lexeme || p_args.len() != s_args.len() {
+                return None;
+            }
+
+            let mut bindings = bindings;
+            for (p, s) in p_args.iter().zip(s_args.iter()) {
+                bindings = match_expr(p, s, bindings)?;
+            }
+            Some(bindings)
+        }
+
+        (Expr::Piecewise { branches: p }, Expr::Piecewise { branches: s }) => {
+            if p.len() != s.len() {
+                return None;
+            }
+
+            let mut bindings = bindings;
+            for (p, s) in p.iter().zip(s.iter()) {
+                bindings = match (&p.guard, &s.guard) {
+                    (Some(p), Some(s)) => match_expr(p, s, bindings)?,
+                    (None, None) => bindings,
+                    _ => return None,
+                };
+                bindings = match_expr(&p.value, &s.value, bindings)?;
+            }
+            Some(bindings)
+        }
+
+        (Expr::Matrix { rows: p }, Expr::Matrix { rows: s }) => {
+            let same_shape =
+                p.len() == s.len() && p.iter().zip(s.iter()).all(|(p, s)| p.len() == s.len());
+            if !same_shape {
+                return None;
+            }
+
+            let mut bindings = bindings;
+            for (p, s) in p.iter().flatten().zip(s.iter().flatten()) {
+                bindings = match_expr(p, s, bindings)?;
+            }
+            Some(bindings)
+        }
+
+        _ => None,
+    }
+}
+
+// match every pattern operand against a distinct subject operand in any order.
+// leftover subjects are returned if `allow_rest`, otherwise they have to be
+// absorbed by a trailing pattern variable.
+fn match_operands<'s, 'a>(
+    op: Token<'a>,
+    patterns: &[&Expr<'a>],
+    subjects: &[&'s Expr<'a>],
+    bindings: Bindings<'a>,
+    allow_rest: bool,
+) -> Option<(Bindings<'a>, Vec<&'s Expr<'a>>)> {
+    // try the most constrained patterns first so variables don't eat operands
+    // that a more specific pattern needed
+    let mut patterns = patterns.to_vec();
+    patterns.sort_by_key(|pattern| matches!(pattern, Expr::Variable { .. }));
+    match_operands_(op, &patterns, subjects.to_vec(), bindings, allow_rest)
+}
+
+fn match_operands_<'s, 'a>(
+    op: Token<'a>,
+    patterns: &[&Expr<'a>],
+    subjects: Vec<&'s Expr<'a>>,
+    bindings: Bindings<'a>,
+    allow_rest: bool,
+) -> Option<(Bindings<'a>, Vec<&'s Expr<'a>>)> {
+    if patterns.is_empty() {
+        if subjects.is_empty() || allow_rest {
+            return Some((bindings, subjects));
+        }
+        return None;
+    }
+
+    if patterns.len() == 1 && subjects.len() > 1 && !allow_rest {
+        if let Expr::Variable { name } = patterns[0] {
+            if !bindings.contains_key(name.lexeme) {
+                let mut bindings = bindings;
+                let rest = subjects.into_iter().cloned().collect();
+                bindings.insert(name.lexeme, rebuild(op, rest));
+                return Some((bindings, Vec::new()));
+            }
+        }
+    }
+
+    for i in 0..subjects.len() {
+        if let Some(bound) = match_expr(patterns[0], subjects[i], bindings.clone()) {
+            let mut remaining = subjects.clone();
+            remaining.remove(i);
+            if let Some(result) = match_operands_(op, &patterns[1..], remaining, bound, allow_rest)
+            {
+                return Some(result);
+            }
+        }
+    }
+
+    None
+}
+
+fn is_ac(kind: TokenKind) -> bool {
+    kind == TokenKind::Plus || kind == TokenKind::Multiply
+}
+
+fn ac_op<'a>(expr: &Expr<'a>) -> Option<Token<'a>> {
+    match expr {
+        Expr::Binary { op, .. } if is_ac(op.kind) => Some(*op),
+        _ => None,
+    }
+}
+
+fn flatten<'s, 'a>(expr: &'s Expr<'a>, kind: TokenKind) -> Vec<&'s Expr<'a>> {
+    match expr {
+        Expr::Binary { lhs, op, rhs } if op.kind == kind => {
+            let mut operands = flatten(lhs, kind);
+            operands.extend(flatten(rhs, kind));
+            operands
+        }
+        _ => vec![expr],
+    }
+}
+
+fn rebuild<'a>(op: Token<'a>, operands: Vec<Expr<'a>>) -> Expr<'a> {
+    let mut operands = operands.into_iter();
+    let first = operands.next().expect("rebuild with no operands");
+    operands.fold(first, |lhs, rhs| Expr::Binary {
+        lhs: Box::new(lhs),
+        op,
+        rhs: Box::new(rhs),
+    })
+}
+
+struct Substitute<'b, 'a> {
+    bindings: &'b Bindings<'a>,
+}
+
+impl<'a> Fold<'a> for Substitute<'_, 'a> {
+    fn fold_variable(&mut self, name: Token<'a>) -> Expr<'a> {
+        match self.bindings.get(name.lexeme) {
+            Some(expr) => expr.clone(),
+            None => Expr::Variable { name },
+        }
+    }
+
+    fn fold_special_variable(&mut self, name: Token<'a>) -> Expr<'a> {
+        match self.bindings.get(name.lexeme) {
+            Some(expr) => expr.clone(),
+            None => Expr::SpecialVariable { name },
+        }
+    }
+}
+
+pub fn substitute<'a>(expr: &Expr<'a>, bindings: &Bindings<'a>) -> Expr<'a> {
+    Substitute { bindings }.fold_expr(expr.clone())
+}
@@ -0,0 +1,246 @@
+use super::ast::*;
+use super::eval::{self, Env};
+use super::scan::*;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    // stop once the root is known to within this, relative to its size
+    pub tolerance: f64,
+    pub max_iterations: usize,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            tolerance: 1e-12,
+            max_iterations: 200,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumericRoot {
+    pub value: f64,
+    pub iterations: usize,
+}
+
+const NOT_CONVERGED: &str = "root finding didn't converge within the iteration limit";
+const NO_SIGN_CHANGE: &str = "the function has the same sign at both ends of the bracket";
+
+fn close_enough(width: f64, x: f64, options: &Options) -> bool {
+    width.abs() <= options.tolerance * x.abs().max(1.0)
+}
+
+// how a bracket is narrowed down to the root
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Method {
+    Brent,
+    Bisection,
+}
+
+// halve the bracket until it's small enough. slow but can't fail once the
+// ends have different signs.
+pub fn bisection(
+    f: impl Fn(f64) -> Result<f64, &'static str>,
+    mut a: f64,
+    mut b: f64,
+    options: &Options,
+) -> Result<NumericRoot, &'static str> {
+    let mut fa = f(a)?;
+    if fa == 0.0 {
+        return Ok(NumericRoot {
+            value: a,
+            iterations: 0,
+        });
+    }
+    if fa * f(b)? > 0.0 {
+        return Err(NO_SIGN_CHANGE);
+    }
+
+    for iterations in 1..=options.max_iterations {
+        let mid = (a + b) / 2.0;
+        let fm = f(mid)?;
+        if fm == 0.0 || close_enough(b - a, mid, options) {
+            return Ok(NumericRoot {
+                value: mid,
+                iterations,
+            });
+        }
+        if fa * fm < 0.0 {
+            b = mid;
+        } else {
+            a = mid;
+            fa = fm;
+        }
+    }
+
+    Err(NOT_CONVERGED)
+}
+
+// x - f(x)/f'(x) from a starting guess. fast near a simple root, but can
+// wander off or stall when the derivative is small.
+pub fn newton(
+    f: impl Fn(f64) -> Result<f64, &'static str>,
+    df: impl Fn(f64) -> Result<f64, &'static str>,
+    mut x: f64,
+    options: &Options,
+) -> Result<NumericRoot, &'static str> {
+    for iterations in 1..=options.max_iterations {
+        let fx = f(x)?;
+        if fx == 0.0 {
+            return Ok(NumericRoot {
+                value: x,
+                iterations,
+            });
+        }
+        let dfx = df(x)?;
+        if dfx == 0.0 {
+            return Err("newton's method hit a point where the derivative is zero");
+        }
+
+        let step = fx / dfx;
+        x -= step;
+        if !x.is_finite() {
+            return Err("newton's method diverged");
+        }
+        if close_enough(step, x, options) {
+            return Ok(NumericRoot {
+                value: x,
+                iterations,
+            });
+        }
+    }
+
+    Err(NOT_CONVERGED)
+}
+
+// brent's method: inverse quadratic interpolation or the secant method when
+// they behave, bisection when they don't, so it keeps bisection's guarantee
+pub fn brent(
+    f: impl Fn(f64) -> Result<f64, &'static str>,
+    mut a: f64,
+    mut b: f64,
+    options: &Options,
+) -> Result<NumericRoot, &'static str> {
+    let mut fa = f(a)?;
+    let mut fb = f(b)?;
+    if fa * fb > 0.0 {
+        return Err(NO_SIGN_CHANGE);
+    }
+    if fa.abs() < fb.abs() {
+        std::mem::swap(&mut a, &mut b);
+        std::mem::swap(&mut fa, &mut fb);
+    }
+
+    let (mut c, mut fc) = (a, fa);
+    let mut d = c;
+    let mut bisected = true;
+
+    for iterations in 1..=options.max_iterations {
+        if fb == 0.0 || close_enough(b - a, b, options) {
+            return Ok(NumericRoot {
+                value: b,
+                iterations,
+            });
+        }
+
+        let mut s = if fa != fc && fb != fc {
+            a * fb * fc / ((fa - fb) * (fa - fc))
+                + b * fa * fc / ((fb - fa) * (fb - fc))
+                + c * fa * fb / ((fc - fa) * (fc - fb))
+        } else {
+            b - fb * (b - a) / (fb - fa)
+        };
+
+        let quarter = (3.0 * a + b) / 4.0;
+        let outside = !((s > quarter.min(b)) && (s < quarter.max(b)));
+        let tolerance = options.tolerance * b.abs().max(1.0);
+        let slow = if bisected {
+            (s - b).abs() >= (b - c).abs() / 2.0 || (b - c).abs() < tolerance
+        } else {
+            (s - b).abs() >= (c - d).abs() / 2.0 || (c - d).abs() < tolerance
+        };
+        bisected = outside || slow;
+        if bisected {
+            s = (a + b) / 2.0;
+        }
+
+        let fs = f(s)?;
+        d = c;
+        c = b;
+        fc = fb;
+        if fa * fs < 0.0 {
+            b = s;
+            fb = fs;
+        } else {
+            a = s;
+            fa = fs;
+        }
+        if fa.abs() < fb.abs() {
+            std::mem::swap(&mut a, &mut b);
+            std::mem::swap(&mut fa, &mut fb);
+        }
+    }
+
+    Err(NOT_CONVERGED)
+}
+
+// a root of `expr` (or of lhs - rhs for an equation) in `var`. with a bracket
+// [a, b] it's narrowed down with `method`, with only a starting guess newton's
+// method with the symbolic derivative is used.
+pub fn find_root(
+    expr: &Expr<'_>,
+    var: &str,
+    a: f64,
+    b: Option<f64>,
+    method: Method,
+    env: &Env,
+    options: &Options,
+) -> Result<NumericRoot, &'static str> {
+    let function = match expr {
+        Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Equal => Expr::Binary {
+            lhs: lhs.clone(),
+            op: Token::new(TokenKind::Minus, "-"),
+            rhs: rhs.clone(),
+        },
+        _ => expr.clone(),
+    };
+    let f = |x: f64| eval::eval_number(&function, &env.with(var, x));
+
+    match b {
+        Some(b) => match method {
+            Method::Brent => brent(f, a, b, options),
+            Method::Bisection => bisection(f, a, b, options),
+        },
+        None => {
+            let derivative = function.derivative(var)?;
+            let df = |x: f64| eval::eval_number(&derivative, &env.with(var, x));
+            newton(f, df, a, options)
+        }
+    }
+}
+
+// root(expr, x, a, b) or root(expr, x, guess). a bracket can be followed by
+// `bisection` to use that instead of brent's method.
+pub fn call(args: &[Expr<'_>], env: &Env) -> Result<f64, &'static str> {
+    let (expr, var, a, b, method) = match args {
+        [expr, Expr::Variable { name }, a] => (expr, name.lexeme, a, None, Method::Brent),
+        [expr, Expr::Variable { name }, a, b] => (expr, name.lexeme, a, Some(b), Method::Brent),
+        [expr, Expr::Variable { name }, a, b, Expr::Variable { name: method }] => {
+            let method = match method.lexeme {
+                "brent" => Method::Brent,
+                "bisection" => Method::Bisection,
+                _ => return Err("a bracket is narrowed down by brent or bisection"),
+            };
+            (expr, name.lexeme, a, Some(b), method)
+        }
+        _ => return Err("root takes an expression, a variable and a bracket or a guess"),
+    };
+
+    let a = eval::eval_number(a, env)?;
+    let b = match b {
+        Some(b) => Some(eval::eval_number(b, env)?),
+        None => None,
+    };
+    find_root(expr, var, a, b, method, env, &Options::default()).map(|root| root.value)
+}
@@ -1,4 +1,5 @@
 use std::collections::VecDeque;
+use std::fmt;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum TokenKind {
@@ -20,13 +21,40 @@ pub enum TokenKind {
     Variable,
     SpecialVariable,
     BuiltinFunction,
+    Function,
     Integer(u64),
     Float(f64),
     Comma,
+    LeftBrace,
+    RightBrace,
+    LeftBracket,
+    RightBracket,
+    Semicolon,
+    If,
+    Otherwise,
+    And,
+    Or,
+    Not,
+    Partial,
+    Assign,
     End,
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
+impl TokenKind {
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            TokenKind::Equal
+                | TokenKind::Less
+                | TokenKind::LessEqual
+                | TokenKind::Greater
+                | TokenKind::GreaterEqual
+                | TokenKind::NotEqual
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct Token<'a> {
     pub kind: TokenKind,
     pub lexeme: &'a str,
@@ -36,13 +64,51 @@ impl Token<'_> {
     pub fn new(kind: TokenKind, lexeme: &str) -> Token<'_> {
         Token { kind, lexeme }
     }
+
+    // numbers made up after scanning have no source text, they're printed
+    // from their value instead
+    pub fn integer(value: u64) -> Token<'static> {
+        Token::new(TokenKind::Integer(value), "")
+    }
+}
+
+// literals compare by value so computed numbers equal scanned ones
+impl PartialEq for Token<'_> {
+    fn eq(&self, other: &Token<'_>) -> bool {
+        match self.kind {
+            TokenKind::Integer(_) | TokenKind::Float(_) => self.kind == other.kind,
+            _ => self.kind == other.kind && self.lexeme == other.lexeme,
+        }
+    }
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            TokenKind::Integer(i) if self.lexeme.is_empty() => write!(f, "{}", i),
+            // past u64 the digits alone won't scan back in as an integer
+            TokenKind::Float(x)
+                if self.lexeme.is_empty() && x.fract() == 0.0 && x.abs() >= 1e15 =>
+            {
+                write!(f, "{}.0", x)
+            }
+            TokenKind::Float(x) if self.lexeme.is_empty() => write!(f, "{}", x),
+            _ => write!(f, "{}", self.lexeme),
+        }
+    }
 }
 
 pub struct Scanner<'a> {
     source: &'a [u8],
     tokens: VecDeque<Token<'a>>,
+    // where each token in `tokens` starts
+    starts: VecDeque<usize>,
+    // where the last token taken started, or where scanning failed
+    last: usize,
     start: usize,
     current: usize,
+    // names the user has defined as functions
+    functions: Vec<String>,
 }
 
 impl<'a> Scanner<'a> {
@@ -50,17 +116,35 @@ impl<'a> Scanner<'a> {
         Scanner {
             source: source.as_bytes(),
             tokens: VecDeque::new(),
+            starts: VecDeque::new(),
+            last: 0,
             start: 0,
             current: 0,
+            functions: Vec::new(),
+        }
+    }
+
+    pub fn with_functions(source: &'a str, functions: Vec<String>) -> Self {
+        Scanner {
+            functions,
+            ..Scanner::new(source)
         }
     }
 
-    #[allow(dead_code)]
+    pub fn add_function(&mut self, name: &str) {
+        self.functions.push(name.to_string());
+    }
+
     pub fn scan_all(mut self) -> Result<Vec<Token<'a>>, &'static str> {
         while self.next()?.kind != TokenKind::End {}
         Ok(self.tokens.drain(0..).collect())
     }
 
+    // the byte offset of the last token taken, for pointing at errors
+    pub fn offset(&self) -> usize {
+        self.last
+    }
+
     pub fn peek_token<'b>(&'b mut self, idx: usize) -> Result<&'b Token<'a>, &'static str> {
         if self.tokens.is_empty() {
             self.next()?;
@@ -78,17 +162,35 @@ impl<'a> Scanner<'a> {
             self.next()?;
         }
 
+        self.last = self.starts.pop_front().unwrap();
         Ok(self.tokens.pop_front().unwrap())
     }
 
     fn next<'b>(&'b mut self) -> Result<&'b Token<'a>, &'static str> {
+        match self.scan_token() {
+            Ok(()) => Ok(&self.tokens[self.tokens.len() - 1]),
+            Err(err) => {
+                self.last = self.start;
+                Err(err)
+            }
+        }
+    }
+
+    fn scan_token(&mut self) -> Result<(), &'static str> {
         self.slurp_whitespace();
         if self.is_at_end() {
+            self.start = self.current;
             self.add_token(TokenKind::End)?;
-            return Ok(&self.tokens[self.tokens.len() - 1]);
+            return Ok(());
         }
 
         self.start = self.current;
+        if let Some((tk, len)) = self.symbol_operator() {
+            self.current += len;
+            self.add_token(tk)?;
+            return Ok(());
+        }
+
         let tk = match self.advance_char() {
             b'(' => TokenKind::LeftParen,
             b')' => TokenKind::RightParen,
@@ -100,6 +202,11 @@ impl<'a> Scanner<'a> {
             b'%' => TokenKind::Modulo,
             b'=' => TokenKind::Equal,
             b',' => TokenKind::Comma,
+            b'{' => TokenKind::LeftBrace,
+            b'}' => TokenKind::RightBrace,
+            b'[' => TokenKind::LeftBracket,
+            b']' => TokenKind::RightBracket,
+            b';' => TokenKind::Semicolon,
             b'<' => {
                 if self.peek_char() == b'=' {
                     self.advance_char();
@@ -135,17 +242,28 @@ impl<'a> Scanner<'a> {
             }
         };
 
-        self.add_token(tk)?;
-        Ok(&self.tokens[self.tokens.len() - 1])
+        self.add_token(tk)
+    }
+
+    // operators spelled with non-ascii symbols
+    fn symbol_operator(&self) -> Option<(TokenKind, usize)> {
+        let rest = &self.source[self.current..];
+        SYMBOL_OPERATORS
+            .iter()
+            .find(|(symbol, _)| rest.starts_with(symbol.as_bytes()))
+            .map(|(symbol, tk)| (*tk, symbol.len()))
     }
 
     fn scan_name(&mut self) -> Result<TokenKind, &'static str> {
-        while !is_non_identifier(self.peek_char()) {
+        while !is_non_identifier(self.peek_char()) && self.symbol_operator().is_none() {
             self.advance_char();
         }
 
-        if let Some(tk) = to_keyword(self.lexeme()?) {
+        let lexeme = self.lexeme()?;
+        if let Some(tk) = to_keyword(lexeme) {
             Ok(tk)
+        } else if self.functions.iter().any(|name| name == lexeme) {
+            Ok(TokenKind::Function)
         } else {
             Ok(TokenKind::Variable)
         }
@@ -194,14 +312,26 @@ impl<'a> Scanner<'a> {
         }
     }
 
+    // comments count as whitespace
     fn slurp_whitespace(&mut self) {
-        while self.peek_char().is_ascii_whitespace() {
-            self.advance_char();
+        loop {
+            match self.peek_char() {
+                c if c.is_ascii_whitespace() => {
+                    self.advance_char();
+                }
+                b'#' => {
+                    while !matches!(self.peek_char(), b'\n' | b'\0') {
+                        self.advance_char();
+                    }
+                }
+                _ => break,
+            }
         }
     }
 
     fn add_token(&mut self, kind: TokenKind) -> Result<(), &'static str> {
         self.tokens.push_back(Token::new(kind, self.lexeme()?));
+        self.starts.push_back(self.start);
         Ok(())
     }
 
@@ -232,6 +362,22 @@ impl<'a> Scanner<'a> {
     }
 }
 
+const SYMBOL_OPERATORS: [(&str, TokenKind); 5] = [
+    ("∧", TokenKind::And),
+    ("∨", TokenKind::Or),
+    ("¬", TokenKind::Not),
+    ("∂", TokenKind::Partial),
+    (":=", TokenKind::Assign),
+];
+
+// the line without its comment, which runs from # to the end
+pub fn strip_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(i) => &line[..i],
+        None => line,
+    }
+}
+
 fn is_non_identifier(c: u8) -> bool {
     c.is_ascii_whitespace()
         || c == 0x00
@@ -249,14 +395,60 @@ fn is_non_identifier(c: u8) -> bool {
         || c == b'<'
         || c == b'='
         || c == b','
+        || c == b'{'
+        || c == b'}'
+        || c == b';'
+        || c == b'['
+        || c == b']'
+        || c == b'#'
 }
 
+// names the scanner treats specially, also used for completion
+pub const KEYWORDS: [(&str, TokenKind); 39] = [
+    ("sin", TokenKind::BuiltinFunction),
+    ("cos", TokenKind::BuiltinFunction),
+    ("tan", TokenKind::BuiltinFunction),
+    ("csc", TokenKind::BuiltinFunction),
+    ("sec", TokenKind::BuiltinFunction),
+    ("cot", TokenKind::BuiltinFunction),
+    ("arctan", TokenKind::BuiltinFunction),
+    ("sigma", TokenKind::BuiltinFunction),
+    ("ln", TokenKind::BuiltinFunction),
+    ("log", TokenKind::BuiltinFunction),
+    ("sqrt", TokenKind::BuiltinFunction),
+    ("expand", TokenKind::BuiltinFunction),
+    ("factor", TokenKind::BuiltinFunction),
+    ("solve", TokenKind::BuiltinFunction),
+    ("csolve", TokenKind::BuiltinFunction),
+    ("diff", TokenKind::BuiltinFunction),
+    ("transpose", TokenKind::BuiltinFunction),
+    ("det", TokenKind::BuiltinFunction),
+    ("inverse", TokenKind::BuiltinFunction),
+    ("rank", TokenKind::BuiltinFunction),
+    ("eigenvalues", TokenKind::BuiltinFunction),
+    ("root", TokenKind::BuiltinFunction),
+    ("integrate", TokenKind::BuiltinFunction),
+    ("int", TokenKind::BuiltinFunction),
+    ("∫", TokenKind::BuiltinFunction),
+    ("limit", TokenKind::BuiltinFunction),
+    ("series", TokenKind::BuiltinFunction),
+    ("grad", TokenKind::BuiltinFunction),
+    ("jacobian", TokenKind::BuiltinFunction),
+    ("hessian", TokenKind::BuiltinFunction),
+    ("theta", TokenKind::SpecialVariable),
+    ("dx", TokenKind::SpecialVariable),
+    ("dy", TokenKind::SpecialVariable),
+    ("dtheta", TokenKind::SpecialVariable),
+    ("if", TokenKind::If),
+    ("otherwise", TokenKind::Otherwise),
+    ("and", TokenKind::And),
+    ("or", TokenKind::Or),
+    ("not", TokenKind::Not),
+];
+
 fn to_keyword(token: &str) -> Option<TokenKind> {
-    match token {
-        "sin" | "cos" | "tan" | "csc" | "sec" | "cot" | "sigma" | "ln" | "log" => {
-            Some(TokenKind::BuiltinFunction)
-        }
-        "theta" | "dx" | "dy" | "dtheta" => Some(TokenKind::SpecialVariable),
-        _ => None,
-    }
+    KEYWORDS
+        .iter()
+        .find(|(name, _)| *name == token)
+        .map(|(_, kind)| *kind)
 }
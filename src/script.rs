@@ -0,0 +1,63 @@
+use super::helper;
+use super::scan::strip_comment;
+
+// a statement from a script. it can run over several lines when they end in ;
+// or have brackets left open.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Line {
+    // where it starts, counting from 1
+    pub line: usize,
+    pub column: usize,
+    // without comments
+    pub text: String,
+}
+
+impl Line {
+    // the line and column of a byte offset into the text
+    pub fn position(&self, offset: usize) -> (usize, usize) {
+        let before = &self.text[..offset.min(self.text.len())];
+        match before.rfind('\n') {
+            Some(newline) => (
+                self.line + before.matches('\n').count(),
+                before[newline + 1..].chars().count() + 1,
+            ),
+            None => (self.line, self.column + before.chars().count()),
+        }
+    }
+}
+
+// the statements of a script, skipping blank lines and comments
+pub fn lines(source: &str) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut block: Option<Line> = None;
+
+    for (number, line) in source.lines().enumerate() {
+        let code = strip_comment(line);
+        if block.is_none() && code.trim().is_empty() {
+            continue;
+        }
+
+        let mut current = block.take().unwrap_or_else(|| {
+            let indent = code.len() - code.trim_start().len();
+            Line {
+                line: number + 1,
+                column: code[..indent].chars().count() + 1,
+                text: String::new(),
+            }
+        });
+        if current.text.is_empty() {
+            current.text.push_str(code.trim_start());
+        } else {
+            current.text.push('\n');
+            current.text.push_str(code);
+        }
+
+        if code.trim_end().ends_with(';') || helper::unclosed(&current.text) {
+            block = Some(current);
+        } else {
+            lines.push(current);
+        }
+    }
+    lines.extend(block);
+    lines
+}
@@ -0,0 +1,154 @@
+use super::ast::*;
+use super::diff::{difference, power, product, quotient, sum};
+use super::eval::{self, Env};
+use super::limit;
+use super::poly::*;
+use super::rational::Rational;
+use super::rewrite::{self, Bindings};
+use super::scan::*;
+
+// derivatives past this get too big to be worth it
+const MAX_ORDER: u64 = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series<'a> {
+    // the taylor polynomial
+    pub polynomial: Expr<'a>,
+    // O((x - a)^(n + 1))
+    pub remainder: Expr<'a>,
+}
+
+impl<'a> Series<'a> {
+    // the polynomial with the big-O term on the end
+    pub fn with_remainder(&self) -> Expr<'a> {
+        sum(self.polynomial.clone(), self.remainder.clone())
+    }
+}
+
+// the taylor polynomial of `expr` in `var` about `a` up to (x - a)^n, from
+// f(a) + f'(a)(x - a) + f''(a)/2! (x - a)^2 + ...
+pub fn taylor<'a>(
+    expr: &Expr<'a>,
+    var: &Expr<'a>,
+    a: &Expr<'a>,
+    n: u64,
+) -> Result<Series<'a>, &'static str> {
+    let x = match var {
+        Expr::Variable { name } | Expr::SpecialVariable { name } => name.lexeme,
+        _ => return Err("series needs a variable to expand in"),
+    };
+    if n > MAX_ORDER {
+        return Err("series order is too high, it can be up to 20");
+    }
+
+    let shift = match Poly::from_expr(a)?.as_constant() {
+        Some(c) if c.is_zero() => var.clone(),
+        Some(c) if c.is_negative() => sum(var.clone(), Poly::constant(-c).to_expr()),
+        _ => difference(var.clone(), a.clone()),
+    };
+    let mut bindings = Bindings::new();
+    bindings.insert(x, a.clone());
+    let point = eval::eval_number(a, &Env::new()).ok();
+
+    let mut derivative = expr.clone();
+    let mut factorial: i128 = 1;
+    let mut polynomial: Option<Expr<'a>> = None;
+
+    for k in 0..=n {
+        if k > 0 {
+            derivative = Poly::from_expr(&derivative.derivative(x)?)?.to_expr();
+            factorial *= k as i128;
+        }
+
+        let at = tidy(rewrite::substitute(&derivative, &bindings));
+        if let Ok(v) = eval::eval_number(&at, &Env::new()) {
+            let pole = match point {
+                Some(a) => !close_by(&derivative, x, a, v),
+                None => false,
+            };
+            if !v.is_finite() || pole {
+                return Err(
+                    "the expression isn't smooth at the point, so it has no taylor series there",
+                );
+            }
+        }
+
+        let term = match Poly::from_expr(&limit::exact(at.clone()))?.as_constant() {
+            Some(c) if c.is_zero() => continue,
+            Some(c) => Poly::atom(shift.clone())
+                .pow(k as u32)?
+                .scale(c.checked_div(Rational::integer(factorial))?)?
+                .to_expr(),
+            None => product(
+                quotient(at, integer(factorial)),
+                power(shift.clone(), integer(k as i128)),
+            ),
+        };
+
+        polynomial = Some(match (polynomial, term) {
+            (None, term) => term,
+            (Some(acc), Expr::Unary { op, rhs }) if op.kind == TokenKind::Minus => {
+                difference(acc, *rhs)
+            }
+            (Some(acc), term) => sum(acc, term),
+        });
+    }
+
+    Ok(Series {
+        polynomial: polynomial.unwrap_or_else(|| integer(0)),
+        remainder: Expr::Call {
+            name: Token::new(TokenKind::BuiltinFunction, "O"),
+            args: vec![power(shift, integer(n as i128 + 1))],
+        },
+    })
+}
+
+// whether `expr` is about `v` just either side of `a`. a pole like tan at
+// pi/2 evaluates to something huge but finite there, since pi/2 is rounded,
+// while the values either side are nowhere near it.
+fn close_by(expr: &Expr<'_>, x: &str, a: f64, v: f64) -> bool {
+    let h = if a == 0.0 { 1e-9 } else { 1e-9 * a.abs() };
+    [a - h, a + h].iter().all(
+        |near| match eval::eval_number(expr, &Env::new().with(x, *near)) {
+            Ok(w) => (w - v).abs() <= 1e-3 * v.abs().max(1.0),
+            Err(_) => true,
+        },
+    )
+}
+
+// replaces calls with constant arguments like sin(0) or ln(e) with their
+// value when it's rational, so coefficients with other variables in them
+// still come out tidy
+struct Constants;
+
+impl<'a> Fold<'a> for Constants {
+    fn fold_call(&mut self, name: Token<'a>, args: Vec<Expr<'a>>) -> Expr<'a> {
+        let args = args.into_iter().map(|arg| self.fold_expr(arg)).collect();
+        limit::exact(Expr::Call { name, args })
+    }
+}
+
+// collects like terms and evaluates constant calls
+pub fn tidy(expr: Expr<'_>) -> Expr<'_> {
+    let expr = Constants.fold_expr(expr);
+    match Poly::from_expr(&expr) {
+        Ok(poly) => poly.to_expr(),
+        // too big to collect exactly, so it stays as it is
+        Err(_) => expr,
+    }
+}
+
+// series(expr, x, a, n), or series(expr, x, n) about 0
+pub fn call<'a>(args: &[Expr<'a>]) -> Result<Series<'a>, &'static str> {
+    let (expr, var, a, n) = match args {
+        [expr, var, n] => (expr, var, integer(0), n),
+        [expr, var, a, n] => (expr, var, a.clone(), n),
+        _ => return Err("series takes an expression, a variable, a point and an order"),
+    };
+
+    let n = eval::eval_number(n, &Env::new())?;
+    if n < 0.0 || n.fract() != 0.0 {
+        return Err("series order has to be a whole number");
+    }
+    taylor(expr, var, &a, n as u64)
+}
@@ -0,0 +1,280 @@
+use super::ast::*;
+use super::scan::*;
+
+// s-expressions like (+ (^ x 2) 1), with every operator written prefix and
+// fully parenthesized. calls are (sin x), piecewise functions are
+// (piecewise (if guard value) (otherwise value)) and matrices are
+// (matrix (row 1 2) (row 3 4)). a one sided (- x) is negation.
+struct Sexpr(String);
+
+impl Sexpr {
+    fn list(&mut self, head: &str, exprs: &[&Expr<'_>]) {
+        self.0.push('(');
+        self.0.push_str(head);
+        for expr in exprs.iter() {
+            self.0.push(' ');
+            self.visit_expr(expr);
+        }
+        self.0.push(')');
+    }
+}
+
+impl<'a> Visitor<'a> for Sexpr {
+    fn visit_literal(&mut self, literal: &Token<'a>) {
+        match literal.kind {
+            // so it reads back as a float
+            TokenKind::Float(x) if x.fract() == 0.0 => self.0.push_str(&format!("{:?}", x)),
+            _ => self.0.push_str(&literal.to_string()),
+        }
+    }
+
+    fn visit_variable(&mut self, name: &Token<'a>) {
+        self.0.push_str(name.lexeme);
+    }
+
+    fn visit_special_variable(&mut self, name: &Token<'a>) {
+        self.0.push_str(name.lexeme);
+    }
+
+    fn visit_unary(&mut self, op: &Token<'a>, rhs: &Expr<'a>) {
+        self.list(op.lexeme, &[rhs]);
+    }
+
+    fn visit_binary(&mut self, lhs: &Expr<'a>, op: &Token<'a>, rhs: &Expr<'a>) {
+        self.list(op.lexeme, &[lhs, rhs]);
+    }
+
+    fn visit_call(&mut self, name: &Token<'a>, args: &[Expr<'a>]) {
+        self.list(name.lexeme, &args.iter().collect::<Vec<_>>());
+    }
+
+    fn visit_piecewise(&mut self, branches: &[Branch<'a>]) {
+        self.0.push_str("(piecewise");
+        for branch in branches.iter() {
+            self.0.push(' ');
+            match &branch.guard {
+                Some(guard) => self.list("if", &[guard, &branch.value]),
+                None => self.list("otherwise", &[&branch.value]),
+            }
+        }
+        self.0.push(')');
+    }
+
+    fn visit_matrix(&mut self, rows: &[Vec<Expr<'a>>]) {
+        self.0.push_str("(matrix");
+        for row in rows.iter() {
+            self.0.push(' ');
+            self.list("row", &row.iter().collect::<Vec<_>>());
+        }
+        self.0.push(')');
+    }
+}
+
+pub fn to_sexpr(expr: &Expr<'_>) -> String {
+    let mut sexpr = Sexpr(String::new());
+    sexpr.visit_expr(expr);
+    sexpr.0
+}
+
+// an atom or a parenthesized list, before it's turned into an expr
+enum Node<'a> {
+    Atom(&'a str),
+    List(Vec<Node<'a>>),
+}
+
+fn atoms(source: &str) -> Vec<&str> {
+    let mut atoms = Vec::new();
+    let mut start = None;
+    for (i, c) in source.char_indices() {
+        if c == '(' || c == ')' || c.is_whitespace() {
+            if let Some(start) = start.take() {
+                atoms.push(&source[start..i]);
+            }
+            if !c.is_whitespace() {
+                atoms.push(&source[i..i + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(start) = start {
+        atoms.push(&source[start..]);
+    }
+    atoms
+}
+
+fn node<'a>(atoms: &mut std::slice::Iter<'_, &'a str>) -> Result<Node<'a>, &'static str> {
+    match atoms.next() {
+        None => Err("unexpected end of s-expression"),
+        Some(&")") => Err("unexpected ) in s-expression"),
+        Some(&"(") => {
+            let mut items = Vec::new();
+            loop {
+                match atoms.as_slice().first() {
+                    None => return Err("unclosed ( in s-expression"),
+                    Some(&")") => {
+                        atoms.next();
+                        return Ok(Node::List(items));
+                    }
+                    Some(_) => items.push(node(atoms)?),
+                }
+            }
+        }
+        Some(atom) => Ok(Node::Atom(atom)),
+    }
+}
+
+// the operator an atom spells, if it's one
+fn operator(atom: &str) -> Option<Token<'_>> {
+    use TokenKind::*;
+    let token = Scanner::new(atom).next_token().ok()?;
+    let is_operator = matches!(
+        token.kind,
+        Minus
+            | Plus
+            | Multiply
+            | Divide
+            | Power
+            | Modulo
+            | Equal
+            | Factorial
+            | Less
+            | Greater
+            | LessEqual
+            | GreaterEqual
+            | NotEqual
+            | And
+            | Or
+            | Not
+    );
+    if is_operator && token.lexeme == atom {
+        Some(token)
+    } else {
+        None
+    }
+}
+
+fn to_expr<'a>(node: &Node<'a>) -> Result<Expr<'a>, &'static str> {
+    let items = match node {
+        Node::Atom(atom) => {
+            if let Ok(i) = atom.parse::<u64>() {
+                return Ok(Expr::Literal {
+                    literal: Token::new(TokenKind::Integer(i), atom),
+                });
+            }
+            // not inf or nan, which are variables
+            let numeric = atom.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '.');
+            match atom.parse::<f64>() {
+                Ok(x) if numeric => {
+                    return Ok(Expr::Literal {
+                        literal: Token::new(TokenKind::Float(x), atom),
+                    })
+                }
+                _ => {}
+            }
+            return match KEYWORDS.iter().find(|(name, _)| name == atom) {
+                Some((_, TokenKind::SpecialVariable)) => Ok(Expr::SpecialVariable {
+                    name: Token::new(TokenKind::SpecialVariable, atom),
+                }),
+                None if operator(atom).is_none() => Ok(Expr::Variable {
+                    name: Token::new(TokenKind::Variable, atom),
+                }),
+                _ => Err("expected a number or a variable in s-expression"),
+            };
+        }
+        Node::List(items) => items,
+    };
+
+    let head = match items.first() {
+        Some(Node::Atom(head)) => *head,
+        _ => return Err("expected an operator or a name at the start of a list"),
+    };
+    let args = || {
+        items[1..]
+            .iter()
+            .map(to_expr)
+            .collect::<Result<Vec<_>, _>>()
+    };
+
+    match (head, operator(head)) {
+        ("piecewise", _) => {
+            let mut branches = Vec::new();
+            for branch in items[1..].iter() {
+                let branch = match branch {
+                    Node::List(branch) => branch,
+                    _ => return Err("expected (if guard value) or (otherwise value)"),
+                };
+                branches.push(match branch.as_slice() {
+                    [Node::Atom("if"), guard, value] => Branch {
+                        value: to_expr(value)?,
+                        guard: Some(to_expr(guard)?),
+                    },
+                    [Node::Atom("otherwise"), value] => Branch {
+                        value: to_expr(value)?,
+                        guard: None,
+                    },
+                    _ => return Err("expected (if guard value) or (otherwise value)"),
+                });
+            }
+            Ok(Expr::Piecewise { branches })
+        }
+        ("matrix", _) => {
+            let mut rows = Vec::new();
+            for row in items[1..].iter() {
+                match row {
+                    Node::List(row) if matches!(row.first(), Some(Node::Atom("row"))) => rows.push(
+                        row[1..]
+                            .iter()
+                            .map(to_expr)
+                            .collect::<Result<Vec<_>, _>>()?,
+                    ),
+                    _ => return Err("expected (row ...) in a matrix"),
+                }
+            }
+            if rows.is_empty() || rows.iter().any(|row| row.len() != rows[0].len()) {
+                return Err("matrix rows have to be the same length");
+            }
+            Ok(Expr::Matrix { rows })
+        }
+        (_, Some(op)) => {
+            let mut args = args()?.into_iter();
+            match (args.next(), args.next(), args.next()) {
+                (Some(rhs), None, None) => Ok(Expr::Unary {
+                    op,
+                    rhs: Box::new(rhs),
+                }),
+                (Some(lhs), Some(rhs), None) => Ok(Expr::Binary {
+                    lhs: Box::new(lhs),
+                    op,
+                    rhs: Box::new(rhs),
+                }),
+                _ => Err("operators take one or two arguments"),
+            }
+        }
+        (name, None) => {
+            let builtin = KEYWORDS
+                .iter()
+                .any(|(keyword, kind)| *keyword == name && *kind == TokenKind::BuiltinFunction);
+            let kind = if builtin {
+                TokenKind::BuiltinFunction
+            } else {
+                TokenKind::Function
+            };
+            Ok(Expr::Call {
+                name: Token::new(kind, name),
+                args: args()?,
+            })
+        }
+    }
+}
+
+// reads what to_sexpr writes
+pub fn parse(source: &str) -> Result<Expr<'_>, &'static str> {
+    let atoms = atoms(source);
+    let mut atoms = atoms.iter();
+    let node = node(&mut atoms)?;
+    if atoms.next().is_some() {
+        return Err("unexpected text after the s-expression");
+    }
+    to_expr(&node)
+}
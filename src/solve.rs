@@ -0,0 +1,378 @@
+use super::ast::*;
+use super::eval::{self, Env};
+use super::factor;
+use super::poly::*;
+use super::rational::{Rational, OVERFLOW};
+use super::scan::*;
+
+use std::fmt;
+
+#[derive(Debug, Clone)]
+pub struct Root<'a> {
+    // None when only a numeric approximation is known
+    pub exact: Option<Expr<'a>>,
+    pub re: f64,
+    pub im: f64,
+}
+
+#[derive(Debug, Clone)]
+pub enum Solutions<'a> {
+    // empty when there's no solution
+    Roots(Vec<Root<'a>>),
+    All,
+}
+
+impl fmt::Display for Root<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let approx = if self.im == 0.0 {
+            format!("{}", self.re)
+        } else if self.re == 0.0 {
+            format!("{}i", self.im)
+        } else {
+            format!(
+                "{} {} {}i",
+                self.re,
+                if self.im < 0.0 { "-" } else { "+" },
+                self.im.abs()
+            )
+        };
+
+        match &self.exact {
+            Some(exact)
+                if self.re.is_nan()
+                    || Poly::from_expr(exact)
+                        .ok()
+                        .and_then(|p| p.as_constant())
+                        .is_some() =>
+            {
+                write!(f, "{}", to_infix(exact).trim())
+            }
+            Some(exact) => write!(f, "{} ≈ {}", to_infix(exact).trim(), approx),
+            None => write!(f, "{}", approx),
+        }
+    }
+}
+
+// the variable to solve for when none is given: x if it's there, otherwise
+// whichever shows up first
+pub fn default_variable<'a>(expr: &Expr<'a>) -> Option<&'a str> {
+    let free = free_variables(expr);
+    if free.contains(&"x") {
+        Some("x")
+    } else {
+        free.first().copied()
+    }
+}
+
+// solve `lhs = rhs` (or `expr = 0`) for `var`. linear and quadratic equations
+// are solved exactly even with symbolic coefficients, higher degrees need
+// numeric coefficients and fall back to numeric roots for factors that
+// aren't linear or quadratic. complex roots are only included if asked for.
+pub fn solve<'a>(
+    equation: &Expr<'a>,
+    var: &str,
+    complex: bool,
+) -> Result<Solutions<'a>, &'static str> {
+    let difference = match equation {
+        Expr::Binary { lhs, op, rhs } if op.kind == TokenKind::Equal => {
+            Poly::from_expr(lhs)?.sub(&Poly::from_expr(rhs)?)?
+        }
+        Expr::Binary { op, .. } if op.kind.is_comparison() => return Err("not an equation"),
+        _ => Poly::from_expr(equation)?,
+    };
+
+    let coefficients =
+        difference
+            .coefficients_in(var)
+            .ok_or(if difference.too_high_power(var) {
+                "the polynomial's degree is too high, it can be up to 64"
+            } else {
+                "can only solve polynomial equations"
+            })?;
+    let constant: Option<Vec<Rational>> = coefficients.iter().map(|c| c.as_constant()).collect();
+
+    let mut roots = match (coefficients.as_slice(), constant) {
+        ([], _) => return Ok(Solutions::All),
+        ([_], Some(_)) => Vec::new(),
+        ([_], None) => return Err("equation doesn't contain the variable"),
+        ([c0, c1], _) => vec![linear(c0, c1)?],
+        (_, Some(cs)) => polynomial(&cs, complex)?,
+        ([c0, c1, c2], None) => quadratic_formula(c0, c1, c2)?,
+        _ => return Err("can only solve symbolic equations up to degree 2"),
+    };
+
+    roots.sort_by(|a, b| {
+        a.re.partial_cmp(&b.re)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.im.partial_cmp(&b.im).unwrap_or(std::cmp::Ordering::Equal))
+    });
+    Ok(Solutions::Roots(roots))
+}
+
+fn approximate<'a>(exact: Expr<'a>) -> Root<'a> {
+    Root {
+        re: eval::eval_number(&exact, &Env::new()).unwrap_or(f64::NAN),
+        im: 0.0,
+        exact: Some(exact),
+    }
+}
+
+fn rational_root<'a>(value: Rational) -> Root<'a> {
+    Root {
+        exact: Some(Poly::constant(value).to_expr()),
+        re: value.to_f64(),
+        im: 0.0,
+    }
+}
+
+// c1 x + c0 = 0
+fn linear<'a>(c0: &Poly<'a>, c1: &Poly<'a>) -> Result<Root<'a>, &'static str> {
+    Ok(match c1.as_constant() {
+        Some(c1) => approximate(c0.scale(-c1.recip())?.to_expr()),
+        None => approximate(binary(
+            c0.neg().to_expr(),
+            TokenKind::Divide,
+            "/",
+            c1.to_expr(),
+        )),
+    })
+}
+
+// (-b ± sqrt(b^2 - 4ac)) / 2a with symbolic coefficients
+fn quadratic_formula<'a>(
+    c: &Poly<'a>,
+    b: &Poly<'a>,
+    a: &Poly<'a>,
+) -> Result<Vec<Root<'a>>, &'static str> {
+    let discriminant = b.mul(b)?.sub(&a.mul(c)?.scale(Rational::integer(4))?)?;
+    let sqrt = call("sqrt", discriminant.to_expr());
+    let denominator = a.scale(Rational::integer(2))?.to_expr();
+
+    Ok([(TokenKind::Plus, "+"), (TokenKind::Minus, "-")]
+        .iter()
+        .map(|(kind, lexeme)| {
+            let numerator = match (b.as_constant(), kind) {
+                (Some(b), TokenKind::Plus) if b.is_zero() => sqrt.clone(),
+                (Some(b), _) if b.is_zero() => Expr::Unary {
+                    op: Token::new(TokenKind::Minus, "-"),
+                    rhs: Box::new(sqrt.clone()),
+                },
+                _ => binary(b.neg().to_expr(), *kind, lexeme, sqrt.clone()),
+            };
+            approximate(binary(
+                numerator,
+                TokenKind::Divide,
+                "/",
+                denominator.clone(),
+            ))
+        })
+        .collect())
+}
+
+fn polynomial<'a>(coefficients: &[Rational], complex: bool) -> Result<Vec<Root<'a>>, &'static str> {
+    let mut roots = Vec::new();
+
+    // too big to factor exactly, so every root is found numerically
+    let factors = match factor::primitive(coefficients).and_then(|p| factor::factor_dense(&p)) {
+        Ok(factors) => factors,
+        Err(OVERFLOW) => return Ok(unfactored_roots(coefficients, complex)),
+        Err(err) => return Err(err),
+    };
+    for (factor, _) in factors {
+        match factor.as_slice() {
+            [c0, c1] => roots.push(rational_root((-*c0).checked_div(*c1)?)),
+            [c, b, a] => roots.extend(quadratic(*a, *b, *c, complex)?),
+            _ => roots.extend(numeric_roots(&factor, complex)),
+        }
+    }
+
+    Ok(roots)
+}
+
+// ax^2 + bx + c = 0 with rational coefficients and no rational roots, so the
+// roots are -b/2a ± k sqrt(m) with m square-free
+fn quadratic<'a>(
+    a: Rational,
+    b: Rational,
+    c: Rational,
+    complex: bool,
+) -> Result<Vec<Root<'a>>, &'static str> {
+    let four_ac = Rational::integer(4).checked_mul(a)?.checked_mul(c)?;
+    let discriminant = b.checked_mul(b)?.checked_sub(four_ac)?;
+    if discriminant.is_negative() && !complex {
+        return Ok(Vec::new());
+    }
+
+    // sqrt(n/d) = sqrt(n*d)/d
+    let (k, m) = square_part(
+        discriminant
+            .num()
+            .abs()
+            .checked_mul(discriminant.den())
+            .ok_or(OVERFLOW)?,
+    );
+    let two_a = Rational::integer(2).checked_mul(a)?;
+    let center = (-b).checked_div(two_a)?;
+    let offset = Rational::new(k, discriminant.den())
+        .checked_div(two_a)?
+        .abs();
+
+    let mut surd = if m == 1 {
+        Poly::constant(Rational::ONE)
+    } else {
+        Poly::atom(call("sqrt", integer(m)))
+    };
+    if discriminant.is_negative() {
+        surd = surd.mul(&Poly::atom(Expr::Variable {
+            name: Token::new(TokenKind::Variable, "i"),
+        }))?;
+    }
+    let width = offset.to_f64() * (m as f64).sqrt();
+
+    [Rational::ONE, -Rational::ONE]
+        .iter()
+        .map(|sign| {
+            let exact = Poly::constant(center).add(&surd.scale(offset.checked_mul(*sign)?)?)?;
+            let (re, im) = if discriminant.is_negative() {
+                (center.to_f64(), sign.to_f64() * width)
+            } else {
+                (center.to_f64() + sign.to_f64() * width, 0.0)
+            };
+            Ok(Root {
+                exact: Some(exact.to_expr()),
+                re,
+                im,
+            })
+        })
+        .collect()
+}
+
+// n = k^2 m with m as small as we can find quickly
+pub fn square_part(n: i128) -> (i128, i128) {
+    let (mut k, mut m) = (1, n);
+    let mut p = 2;
+    while p * p <= m && p < 1_000_000 {
+        while m % (p * p) == 0 {
+            m /= p * p;
+            k *= p;
+        }
+        p += 1;
+    }
+    (k, m)
+}
+
+fn call<'a>(name: &'static str, arg: Expr<'a>) -> Expr<'a> {
+    Expr::Call {
+        name: Token::new(TokenKind::BuiltinFunction, name),
+        args: vec![arg],
+    }
+}
+
+const DURAND_KERNER_ITERATIONS: usize = 1000;
+const IMAGINARY_TOLERANCE: f64 = 1e-9;
+
+// durand-kerner: improve guesses for every root at once by dividing out the
+// other guesses
+fn numeric_roots<'a>(coefficients: &[Rational], complex: bool) -> Vec<Root<'a>> {
+    let coefficients: Vec<f64> = coefficients.iter().map(|c| c.to_f64()).collect();
+    complex_roots(&coefficients)
+        .into_iter()
+        .filter(|(_, im)| complex || *im == 0.0)
+        .map(|(re, im)| Root {
+            exact: None,
+            re,
+            im,
+        })
+        .collect()
+}
+
+// repeated roots are only found to about half the precision, so without the
+// square-free parts the tolerance is looser and roots that close are merged
+const REPEATED_TOLERANCE: f64 = 1e-6;
+
+fn unfactored_roots<'a>(coefficients: &[Rational], complex: bool) -> Vec<Root<'a>> {
+    let coefficients: Vec<f64> = coefficients.iter().map(|c| c.to_f64()).collect();
+    let mut roots: Vec<Root<'a>> = Vec::new();
+    for (re, im) in complex_roots(&coefficients) {
+        let scale = re.abs().max(1.0);
+        let im = if im.abs() < REPEATED_TOLERANCE * scale {
+            0.0
+        } else {
+            im
+        };
+        let seen = roots
+            .iter()
+            .any(|root| (root.re - re).hypot(root.im - im) < REPEATED_TOLERANCE * scale);
+        if !seen && (complex || im == 0.0) {
+            roots.push(Root {
+                exact: None,
+                re,
+                im,
+            });
+        }
+    }
+    roots
+}
+
+// every root of a polynomial with coefficients lowest degree first, with
+// imaginary parts that are just rounding error set to zero
+pub fn complex_roots(coefficients: &[f64]) -> Vec<(f64, f64)> {
+    let lead = coefficients[coefficients.len() - 1];
+    let monic: Vec<f64> = coefficients.iter().map(|c| c / lead).collect();
+    let n = monic.len() - 1;
+
+    let mut z: Vec<(f64, f64)> = Vec::with_capacity(n);
+    let mut guess = (1.0, 0.0);
+    for _ in 0..n {
+        z.push(guess);
+        guess = cmul(guess, (0.4, 0.9));
+    }
+
+    for _ in 0..DURAND_KERNER_ITERATIONS {
+        let mut change: f64 = 0.0;
+        for i in 0..n {
+            let value = monic
+                .iter()
+                .rev()
+                .fold((0.0, 0.0), |acc, c| add(cmul(acc, z[i]), (*c, 0.0)));
+            let mut denominator = (1.0, 0.0);
+            for (j, other) in z.iter().enumerate() {
+                if i != j {
+                    denominator = cmul(denominator, (z[i].0 - other.0, z[i].1 - other.1));
+                }
+            }
+            let step = cdiv(value, denominator);
+            z[i] = (z[i].0 - step.0, z[i].1 - step.1);
+            change = change.max(step.0.hypot(step.1));
+        }
+        if change < 1e-15 {
+            break;
+        }
+    }
+
+    z.into_iter()
+        .map(|(re, im)| {
+            if im.abs() < IMAGINARY_TOLERANCE * re.abs().max(1.0) {
+                (re, 0.0)
+            } else {
+                (re, im)
+            }
+        })
+        .collect()
+}
+
+fn add(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+fn cmul(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    (a.0 * b.0 - a.1 * b.1, a.0 * b.1 + a.1 * b.0)
+}
+
+fn cdiv(a: (f64, f64), b: (f64, f64)) -> (f64, f64) {
+    let norm = b.0 * b.0 + b.1 * b.1;
+    (
+        (a.0 * b.0 + a.1 * b.1) / norm,
+        (a.1 * b.0 - a.0 * b.1) / norm,
+    )
+}
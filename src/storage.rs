@@ -0,0 +1,39 @@
+use std::path::{Path, PathBuf};
+
+// overrides where history, the session and rules are kept, after --data-dir
+pub const DATA_DIR_VAR: &str = "NUMS_DATA_DIR";
+
+// the --data-dir flag, then $NUMS_DATA_DIR, then the xdg data directory,
+// usually ~/.local/share/nums. the current directory if there's no home.
+pub fn data_dir(flag: Option<&str>) -> PathBuf {
+    if let Some(dir) = flag {
+        return PathBuf::from(dir);
+    }
+    match std::env::var_os(DATA_DIR_VAR) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => dirs::data_dir()
+            .map(|dir| dir.join("nums"))
+            .unwrap_or_else(|| PathBuf::from(".")),
+    }
+}
+
+pub fn history_file(dir: &Path) -> PathBuf {
+    dir.join("history.txt")
+}
+
+pub fn session_file(dir: &Path) -> PathBuf {
+    dir.join("session.nums")
+}
+
+// extra rewrite rules, one `pattern -> replacement` per line
+pub fn rules_file(dir: &Path) -> PathBuf {
+    dir.join("rules.txt")
+}
+
+// writes the file, making the directory first if it's missing
+pub fn write(path: &Path, contents: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, contents)
+}